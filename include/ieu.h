@@ -0,0 +1,31 @@
+#ifndef IEU_H
+#define IEU_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct IeuPool IeuPool;
+
+typedef void (*IeuCallback)(void *user_data, size_t index);
+
+/* Creates a pool with `size` worker threads, NULL if they can't be spawned. */
+IeuPool *ieu_pool_new(size_t size);
+
+/* Calls `func(user_data, i)` for every i in 0..num across the workers and
+ * returns once all calls have finished. */
+void ieu_pool_execute(IeuPool *pool, size_t num, IeuCallback func, void *user_data);
+
+/* Stops the workers and frees the pool, NULL is ignored. */
+void ieu_pool_free(IeuPool *pool);
+
+/* Like ieu_pool_execute but on the lazily created global pool. */
+void ieu_execute(size_t num, IeuCallback func, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,132 @@
+use crate::sync::{AtomicUsize, Condvar, Mutex, Ordering};
+
+// Hands the job slots of a pool to submitters in the order they arrived. Each
+// submitter draws a ticket, and only the ticket being served may take the
+// next free slot, so a busy pool can't starve anyone by letting whoever
+// happens to run when a slot frees up win it again and again. Without
+// contention this is a few atomics, sleeping only happens while waiting.
+pub(crate) struct Admission {
+    next:    AtomicUsize,
+    serving: AtomicUsize,
+    // a bit per slot, set while a submitter holds it
+    busy:    AtomicUsize,
+    slots:   usize,
+    waiting: AtomicUsize,
+    lock:    Mutex<()>,
+    turn:    Condvar,
+}
+
+// A slot taken through `Admission::enter`, freed again on drop.
+pub(crate) struct Admitted<'a> {
+    admission:       &'a Admission,
+    pub(crate) slot: usize,
+}
+
+impl Admission {
+    pub(crate) fn new(slots: usize) -> Admission {
+        assert!(slots <= usize::BITS as usize);
+        Admission {
+            next: AtomicUsize::new(0),
+            serving: AtomicUsize::new(0),
+            busy: AtomicUsize::new(0),
+            slots,
+            waiting: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            turn: Condvar::new(),
+        }
+    }
+
+    // submitters that drew a ticket and haven't been served yet
+    #[cfg(feature = "std")]
+    pub(crate) fn queued(&self) -> usize {
+        let serving = self.serving.load(Ordering::SeqCst);
+        self.next.load(Ordering::SeqCst).saturating_sub(serving)
+    }
+
+    // waits for the caller's turn and for a free slot
+    pub(crate) fn enter(&self) -> Admitted<'_> {
+        let ticket = self.next.fetch_add(1, Ordering::SeqCst);
+        let slot = self.wait_until(|| {
+            match self.serving.load(Ordering::SeqCst) == ticket {
+                true => self.take_free(),
+                false => None,
+            }
+        });
+        self.serving.fetch_add(1, Ordering::SeqCst);
+        // the next ticket may already be waiting for its turn
+        self.wake();
+        Admitted {
+            admission: self,
+            slot,
+        }
+    }
+
+    // a free slot if nobody is queued for one, without waiting; a submitter
+    // that draws a ticket at the same time may find the slot taken, as if
+    // it had come second
+    pub(crate) fn try_enter(&self) -> Option<Admitted<'_>> {
+        if self.queued() > 0 {
+            return None;
+        }
+        let slot = self.take_free()?;
+        Some(Admitted {
+            admission: self,
+            slot,
+        })
+    }
+
+    fn take_free(&self) -> Option<usize> {
+        let mut busy = self.busy.load(Ordering::SeqCst);
+        loop {
+            let slot = (!busy).trailing_zeros() as usize;
+            if slot >= self.slots {
+                return None;
+            }
+            match self.busy.compare_exchange(
+                busy,
+                busy | 1 << slot,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return Some(slot),
+                Err(current) => busy = current,
+            }
+        }
+    }
+
+    // A waiter announces itself before checking `ready` under the lock, and
+    // wakers take the lock after changing the state whenever anyone waits, so
+    // a change either shows up in the check or comes with a notification.
+    fn wait_until<T>(&self, mut ready: impl FnMut() -> Option<T>) -> T {
+        if let Some(value) = ready() {
+            return value;
+        }
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        let mut guard = self.lock.lock().unwrap_or_else(|err| err.into_inner());
+        let value = loop {
+            if let Some(value) = ready() {
+                break value;
+            }
+            guard = self.turn.wait(guard).unwrap_or_else(|err| err.into_inner());
+        };
+        drop(guard);
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        value
+    }
+
+    fn wake(&self) {
+        if self.waiting.load(Ordering::SeqCst) > 0 {
+            drop(self.lock.lock().unwrap_or_else(|err| err.into_inner()));
+            self.turn.notify_all();
+        }
+    }
+}
+
+impl Drop for Admitted<'_> {
+    fn drop(&mut self) {
+        self.admission
+            .busy
+            .fetch_and(!(1 << self.slot), Ordering::SeqCst);
+        self.admission.wake();
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+use crate::{pool::PoolInner, sync::Ordering};
+
+// The state of `Builder::autoscale`: the fewest workers jobs use, how often
+// the load is looked at, and when it last was along with how long each
+// worker had been busy by then.
+pub(crate) struct Autoscale {
+    min:      usize,
+    interval: Duration,
+    last:     std::sync::Mutex<(Instant, Vec<Duration>)>,
+}
+
+impl Autoscale {
+    pub(crate) fn new(min: usize, interval: Duration, size: usize) -> Autoscale {
+        Autoscale {
+            min: min.clamp(1, size.max(1)),
+            interval,
+            last: std::sync::Mutex::new((Instant::now(), vec![Duration::ZERO; size])),
+        }
+    }
+
+    pub(crate) fn min(&self) -> usize {
+        self.min
+    }
+}
+
+impl PoolInner {
+    // Adjusts the workers that take part in jobs to the load, at most once
+    // per interval: if the active workers were busy for at least 3/4 of the
+    // time since the last look, their number doubles up to the size of the
+    // pool, if they were busy for less than 1/4 of it, it halves down to the
+    // minimum once for every interval that went by.
+    pub(crate) fn autoscale(&self) {
+        let Some(scale) = &self.autoscale else {
+            return;
+        };
+        // whoever holds the lock is already adjusting
+        let Ok(mut last) = scale.last.try_lock() else {
+            return;
+        };
+        let elapsed = last.0.elapsed();
+        if elapsed < scale.interval {
+            return;
+        }
+        let busy = self.stats.busy();
+        let active = self.active.load(Ordering::SeqCst);
+        // the counters go back to 0 on `reset_stats`
+        let used = busy
+            .iter()
+            .zip(&last.1)
+            .take(active)
+            .map(|(now, then)| now.saturating_sub(*then))
+            .sum::<Duration>();
+        let load = used.as_secs_f64() / (elapsed.as_secs_f64() * active as f64);
+        let active = match load {
+            load if load >= 0.75 => (active * 2).min(self.size.max(1)),
+            load if load < 0.25 => {
+                let intervals = elapsed.as_nanos() / scale.interval.as_nanos().max(1);
+                active
+                    .checked_shr(intervals.min(usize::BITS as u128) as u32)
+                    .unwrap_or(0)
+                    .max(scale.min)
+            },
+            _ => active,
+        };
+        self.active.store(active, Ordering::SeqCst);
+        *last = (Instant::now(), busy);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::{sync::Mutex, Pool};
+
+impl Pool {
+    // Runs `func` on every item of a serial iterator, like the lines of a
+    // reader or the entries of `read_dir`. The workers take turns pulling one
+    // item at a time from `iter` and run `func` on it outside the lock, so
+    // expensive items spread over the workers as they come; the order the
+    // items run in isn't defined.
+    pub fn par_bridge<T: Send>(
+        &self,
+        iter: impl Iterator<Item = T> + Send,
+        func: impl Fn(T) + Send + Sync,
+    ) {
+        // `None` once `iter` ran out, so it isn't polled again
+        let iter = Mutex::new(Some(iter));
+        let next = || {
+            let mut iter = iter.lock().unwrap();
+            let item = iter.as_mut()?.next();
+            if item.is_none() {
+                *iter = None;
+            }
+            item
+        };
+        self.run(self.num_threads().max(1), |_, _| {
+            while let Some(item) = next() {
+                func(item);
+            }
+        });
+    }
+
+    // Runs `func(key, value)` on every entry of a map, or anything else
+    // whose references iterate over key-value pairs (`HashMap`, `BTreeMap`,
+    // ...). The entries are collected into a list of references first, one
+    // cheap pass, which then splits evenly over the workers like an index
+    // range instead of funneling through a lock like `par_bridge`.
+    pub fn par_for_each_kv<'a, M, K: Sync + 'a, V: Sync + 'a>(
+        &self,
+        map: &'a M,
+        func: impl Fn(&'a K, &'a V) + Send + Sync,
+    ) where
+        &'a M: IntoIterator<Item = (&'a K, &'a V)>,
+    {
+        let entries = map.into_iter().collect::<Vec<_>>();
+        self.execute_chunked(entries.len(), |i| func(entries[i].0, entries[i].1));
+    }
+}
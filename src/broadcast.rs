@@ -0,0 +1,185 @@
+use crate::{
+    local::PerWorker,
+    sync::{thread, AtomicBool, AtomicUsize, Ordering},
+    Pool,
+};
+
+/// What a broadcast closure knows about the workers running it, with the
+/// building blocks for OpenMP-style `single`, `master` and `barrier`.
+///
+/// `once` and `single` rely on every worker reaching the same calls in the
+/// same order, as in OpenMP.
+pub struct Team<'a> {
+    index:  usize,
+    shared: &'a TeamShared,
+    onces:  core::cell::Cell<usize>,
+}
+
+struct TeamShared {
+    size:       usize,
+    claimed:    AtomicUsize,
+    arrived:    AtomicUsize,
+    generation: AtomicUsize,
+}
+
+impl Team<'_> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.shared.size
+    }
+
+    /// Runs `func` on whichever worker gets here first, the others skip it.
+    pub fn once<R>(&self, func: impl FnOnce() -> R) -> Option<R> {
+        let seq = self.onces.get();
+        self.onces.set(seq + 1);
+        // `claimed` counts the once blocks that have a runner, a worker
+        // arriving late at block `seq` finds it already past `seq`
+        self.shared
+            .claimed
+            .compare_exchange(seq, seq + 1, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()
+            .map(|_| func())
+    }
+
+    /// Like `once`, followed by a barrier so nobody continues before it ran.
+    pub fn single<R>(&self, func: impl FnOnce() -> R) -> Option<R> {
+        let ret = self.once(func);
+        self.barrier();
+        ret
+    }
+
+    /// Runs `func` on the worker with index 0 only.
+    pub fn master<R>(&self, func: impl FnOnce() -> R) -> Option<R> {
+        (self.index == 0).then(func)
+    }
+
+    /// Waits until every worker of the broadcast has reached this barrier.
+    pub fn barrier(&self) {
+        let shared = self.shared;
+        let generation = shared.generation.load(Ordering::SeqCst);
+        if shared.arrived.fetch_add(1, Ordering::SeqCst) + 1 == shared.size {
+            shared.arrived.store(0, Ordering::SeqCst);
+            shared.generation.fetch_add(1, Ordering::SeqCst);
+        } else {
+            while shared.generation.load(Ordering::SeqCst) == generation {
+                thread::yield_now();
+            }
+        }
+    }
+}
+
+// writes (and so faults in) the next 64 KiB of the calling thread's stack
+#[inline(never)]
+fn touch_stack() {
+    let mut stack = [0u8; 64 * 1024];
+    std::hint::black_box(&mut stack);
+}
+
+impl Pool {
+    // runs `func` exactly once on every worker (or once on the caller for a
+    // pool without workers), all of them at the same time
+    pub fn broadcast(&self, func: impl Fn(&Team) + Send + Sync) {
+        let shared = TeamShared {
+            size:       self.team_size(),
+            claimed:    AtomicUsize::new(0),
+            arrived:    AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        };
+        self.run_per_worker(|index| {
+            func(&Team {
+                index,
+                shared: &shared,
+                onces: core::cell::Cell::new(0),
+            })
+        });
+    }
+
+    // runs `func(index)` once on every worker like `broadcast` and returns
+    // the results in worker order, e.g. per-thread partial sums or stats
+    pub fn broadcast_map<T: Send>(&self, func: impl Fn(usize) -> T + Send + Sync) -> Vec<T> {
+        let results = PerWorker::new(self.team_size(), || None);
+        // every worker writes its own result
+        self.run_per_worker(|index| *unsafe { results.get(index) } = Some(func(index)));
+        results
+            .into_inner()
+            .into_iter()
+            .map(Option::unwrap)
+            .collect()
+    }
+
+    // Queues `func(index)` to run once on every worker, after the tasks
+    // already queued for it, and returns right away, e.g. to flush
+    // per-thread caches or rotate per-thread log files in the background.
+    // Lazy or retired workers are spawned for it. A pool without workers
+    // runs `func(0)` on the caller before returning.
+    pub fn spawn_broadcast(&self, func: impl Fn(usize) + Send + Sync + 'static) {
+        self.inner.push_broadcast(std::sync::Arc::new(func));
+    }
+
+    // wakes every worker once, spawning lazy or retired ones, and has it
+    // fault in the top of its stack, so the first real job doesn't pay for
+    // thread startup, page faults and migrations
+    pub fn warm_up(&self) {
+        self.warm_up_with(|_| {});
+    }
+
+    // `warm_up`, also running `func` with the worker's index on every worker,
+    // e.g. to fill thread-local caches
+    pub fn warm_up_with(&self, func: impl Fn(usize) + Send + Sync) {
+        self.run_per_worker(|index| {
+            touch_stack();
+            func(index);
+        });
+    }
+
+    pub(crate) fn team_size(&self) -> usize {
+        match self.runs_inline() {
+            true => 1,
+            false => self.num_threads(),
+        }
+    }
+
+    pub(crate) fn run_per_worker(&self, func: impl Fn(usize) + Send + Sync) {
+        // one at a time, they'd wait for each other's workers otherwise; the
+        // ones started from inside a job run inline and need no turn
+        let inline = self.runs_inline();
+        let _team = (!inline).then(|| {
+            self.inner
+                .teams
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+        });
+        let size = self.team_size();
+        let ran = (0..size)
+            .map(|_| AtomicBool::new(false))
+            .collect::<Vec<_>>();
+        let finished = AtomicUsize::new(0);
+        // claims aren't tied to workers, so the job has (practically) endless
+        // indices and workers that already ran keep claiming until everyone
+        // has, then the job is stopped; every worker has to take part, so the
+        // job must not run on the caller alone
+        let everyone = vec![true; size];
+        self.run_for(
+            None,
+            size,
+            Some(&everyone),
+            isize::MAX as usize,
+            |worker, _| {
+                // inline, the caller is the whole team whichever worker it is
+                let worker = if inline { 0 } else { worker };
+                match ran[worker].swap(true, Ordering::SeqCst) {
+                    false => {
+                        func(worker);
+                        if finished.fetch_add(1, Ordering::SeqCst) + 1 == size {
+                            self.dispatch().stop();
+                        }
+                    },
+                    true => thread::yield_now(),
+                }
+            },
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use std::{fmt, io, str::FromStr, sync::Arc, time::Duration};
+
+use crate::{Hang, Pool, QueuePolicy};
+
+/// Parsed from its name in any case, e.g. `IEU_THREAD_PRIORITY=low`, or as
+/// `nice:<level>` and `realtime:<priority>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadPriority {
+    /// Lowest priority, for work nobody is waiting on (nice 19, QoS
+    /// background, `THREAD_PRIORITY_LOWEST`).
+    Background,
+    /// Below the default priority (nice 10, QoS utility,
+    /// `THREAD_PRIORITY_BELOW_NORMAL`).
+    Low,
+    /// The OS default for new threads.
+    Normal,
+    /// Above the default priority (nice -5, QoS user-initiated,
+    /// `THREAD_PRIORITY_ABOVE_NORMAL`). Usually requires elevated
+    /// permissions on Linux and is silently ignored without them.
+    High,
+    /// A nice level from -20 (highest) to 19 (lowest). Elsewhere the level
+    /// picks the closest of the other priorities.
+    Nice(i8),
+    /// The `SCHED_FIFO` realtime class with a priority from 1 to 99, e.g.
+    /// for audio processing that must never miss a deadline. Needs
+    /// `CAP_SYS_NICE` or an `RLIMIT_RTPRIO` allowance, without them the
+    /// workers fall back to `High`. Elsewhere it's the highest priority the
+    /// OS offers (QoS user-interactive, `THREAD_PRIORITY_TIME_CRITICAL`).
+    Realtime(u8),
+}
+
+impl ThreadPriority {
+    // the fixed priority closest to a nice level, for the platforms that
+    // have no nice levels
+    #[cfg_attr(not(any(target_vendor = "apple", windows)), allow(dead_code))]
+    pub(crate) fn coarse(self) -> ThreadPriority {
+        match self {
+            ThreadPriority::Nice(15..) => ThreadPriority::Background,
+            ThreadPriority::Nice(5..) => ThreadPriority::Low,
+            ThreadPriority::Nice(-4..) => ThreadPriority::Normal,
+            ThreadPriority::Nice(_) => ThreadPriority::High,
+            priority => priority,
+        }
+    }
+}
+
+impl FromStr for ThreadPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ThreadPriority, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "background" => Ok(ThreadPriority::Background),
+            "low" => Ok(ThreadPriority::Low),
+            "normal" => Ok(ThreadPriority::Normal),
+            "high" => Ok(ThreadPriority::High),
+            s => {
+                let level = |prefix| s.strip_prefix(prefix)?.trim().parse().ok();
+                match (level("nice:"), level("realtime:")) {
+                    (Some(nice @ -20..=19), _) => Ok(ThreadPriority::Nice(nice as i8)),
+                    (_, Some(priority @ 1..=99)) => Ok(ThreadPriority::Realtime(priority as u8)),
+                    _ => Err(format!("unknown thread priority {s:?}")),
+                }
+            },
+        }
+    }
+}
+
+// the names `from_str` parses
+impl fmt::Display for ThreadPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThreadPriority::Background => f.write_str("background"),
+            ThreadPriority::Low => f.write_str("low"),
+            ThreadPriority::Normal => f.write_str("normal"),
+            ThreadPriority::High => f.write_str("high"),
+            ThreadPriority::Nice(nice) => write!(f, "nice:{nice}"),
+            ThreadPriority::Realtime(priority) => write!(f, "realtime:{priority}"),
+        }
+    }
+}
+
+// a closure set with `on_job_start`, `on_job_end` or `on_worker_exit`
+#[derive(Clone)]
+pub(crate) struct JobHook(pub(crate) Arc<dyn Fn(usize) + Send + Sync>);
+
+impl fmt::Debug for JobHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("JobHook(..)")
+    }
+}
+
+// the timeout and closure set with `on_hang`
+#[derive(Clone)]
+pub(crate) struct HangHook(
+    pub(crate) Duration,
+    pub(crate) Arc<dyn Fn(&Hang) + Send + Sync>,
+);
+
+impl fmt::Debug for HangHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HangHook({:?}, ..)", self.0)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Builder {
+    pub(crate) num_threads:    Option<usize>,
+    pub(crate) priority:       Option<ThreadPriority>,
+    pub(crate) idle_timeout:   Option<Duration>,
+    pub(crate) lazy:           bool,
+    pub(crate) busy_wait:      bool,
+    pub(crate) linger:         Option<Duration>,
+    pub(crate) sequential:     bool,
+    pub(crate) yield_every:    Option<usize>,
+    pub(crate) yield_sleep:    Option<Duration>,
+    pub(crate) inline_below:   usize,
+    pub(crate) help_when_busy: bool,
+    pub(crate) queue_limit:    Option<(usize, QueuePolicy)>,
+    pub(crate) power_saving:   bool,
+    pub(crate) stack_size:     Option<usize>,
+    pub(crate) physical:       bool,
+    pub(crate) pin:            bool,
+    pub(crate) job_start:      Option<JobHook>,
+    pub(crate) job_end:        Option<JobHook>,
+    pub(crate) worker_exit:    Option<JobHook>,
+    pub(crate) on_hang:        Option<HangHook>,
+    pub(crate) quota_interval: Option<Duration>,
+    pub(crate) autoscale:      Option<(usize, Duration)>,
+    #[cfg(feature = "chaos")]
+    pub(crate) chaos:          Option<u64>,
+    #[cfg(feature = "hwloc")]
+    pub(crate) placement:      Option<crate::Placement>,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    pub fn num_threads(mut self, num_threads: usize) -> Builder {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    pub fn priority(mut self, priority: ThreadPriority) -> Builder {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Lets workers that have been idle for `timeout` exit. They are
+    /// respawned on demand by the next job or spawned task.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Builder {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Defers spawning the workers until the first job or spawned task
+    /// needs them.
+    pub fn lazy(mut self, lazy: bool) -> Builder {
+        self.lazy = lazy;
+        self
+    }
+
+    /// Makes idle workers, and callers waiting for a job, spin instead of
+    /// sleeping in the OS. This cuts the wakeup latency of every job at the
+    /// cost of keeping one core per worker fully busy even while there is no
+    /// work, so only use it for latency-critical pools. Combine it with
+    /// `idle_timeout` to let the workers stop spinning after a while.
+    pub fn busy_wait(mut self, busy_wait: bool) -> Builder {
+        self.busy_wait = busy_wait;
+        self
+    }
+
+    /// Keeps idle workers, and callers waiting for a job, spinning for
+    /// `duration` before they sleep in the OS. When many tiny jobs come back
+    /// to back (e.g. per-frame passes), the next one then finds the workers
+    /// still awake and skips the park/unpark round trip, while the pool still
+    /// goes to sleep once the jobs stop coming.
+    pub fn linger(mut self, duration: Duration) -> Builder {
+        self.linger = Some(duration).filter(|duration| !duration.is_zero());
+        self
+    }
+
+    /// Runs every job in index order on the calling thread, for debugging.
+    /// `ieu::set_sequential` and `IEU_SEQUENTIAL` do the same for all pools.
+    pub fn sequential(mut self, sequential: bool) -> Builder {
+        self.sequential = sequential;
+        self
+    }
+
+    /// Trades speed for energy, for batch work with a generous deadline on a
+    /// laptop. Idle workers and waiting callers sleep right away instead of
+    /// spinning, and `execute_chunked` hands out larger chunks (16 indices
+    /// before `calibrate`, four times as long a chunk after). Unless
+    /// `num_threads` or a placement say otherwise, the pool gets a worker
+    /// pinned to each efficiency core of a hybrid CPU where Linux tells them
+    /// apart, or half of the available CPUs elsewhere. The workers also run
+    /// at `ThreadPriority::Background` unless `priority` is set, which on
+    /// macOS moves them to the efficiency cores.
+    pub fn power_saving(mut self, power_saving: bool) -> Builder {
+        self.power_saving = power_saving;
+        self
+    }
+
+    /// Makes every worker give up its time slice after each `indices` job
+    /// indices it runs, so long jobs share the CPU with other thread pools
+    /// (tokio, rayon, ...) when the process has more busy threads than cores.
+    /// `0` turns it off again. Jobs that hand out chunks (`execute_chunked`,
+    /// `execute_aligned`, ...) count a chunk as one index.
+    pub fn yield_every(mut self, indices: usize) -> Builder {
+        self.yield_every = Some(indices).filter(|&indices| indices > 0);
+        self
+    }
+
+    /// Makes the workers sleep for `duration` where `yield_every` has them
+    /// yield, for machines so oversubscribed (shared CI or build hosts) that
+    /// a yield gets the core straight back. Zero goes back to yielding.
+    pub fn yield_sleep(mut self, duration: Duration) -> Builder {
+        self.yield_sleep = Some(duration).filter(|duration| !duration.is_zero());
+        self
+    }
+
+    /// Runs jobs with fewer than `num` indices on the calling thread, like
+    /// jobs started from inside the pool, rather than waking the workers
+    /// for them, for callers that submit many jobs too small to be worth
+    /// the round trip. Empty jobs always return right away, and jobs tied to
+    /// the workers, like `broadcast`, always reach them.
+    pub fn inline_threshold(mut self, num: usize) -> Builder {
+        self.inline_below = num;
+        self
+    }
+
+    /// Runs a job on the calling thread when every job slot is taken, rather
+    /// than leaving the caller blocked until one frees up, so the core it
+    /// occupies isn't wasted while other threads' jobs hold the pool. The
+    /// workers never join such a job, it runs as if started from inside the
+    /// pool.
+    pub fn help_when_busy(mut self, help: bool) -> Builder {
+        self.help_when_busy = help;
+        self
+    }
+
+    /// Bounds the queue of tasks waiting for a worker to `limit` (at least
+    /// 1), with `policy` deciding what `spawn` and `submit` do when it's
+    /// full, so producers that outpace the pool get backpressure instead of
+    /// growing the queue without end. A worker of the pool that spawns into
+    /// its own full queue runs the task itself rather than block. Scoped
+    /// tasks and futures aren't limited.
+    pub fn max_queued_tasks(mut self, limit: usize, policy: QueuePolicy) -> Builder {
+        self.queue_limit = Some((limit.max(1), policy));
+        self
+    }
+
+    /// Injects faults to shake out code that relies on a schedule the pool
+    /// doesn't promise: workers are held up at random before they join a
+    /// job and between indices, so which worker runs which indices and in
+    /// what order they finish changes from run to run, and idle workers and
+    /// waiting callers wake up now and then with nothing to do. `seed` picks
+    /// the random decisions, the interleaving still depends on timing. Only
+    /// meant for tests, it makes every job slower.
+    #[cfg(feature = "chaos")]
+    pub fn chaos(mut self, seed: u64) -> Builder {
+        self.chaos = Some(seed);
+        self
+    }
+
+    /// Sets the stack size of the workers in bytes, e.g. for deeply recursive
+    /// jobs that overflow the default (2 MiB unless `RUST_MIN_STACK` says
+    /// otherwise).
+    pub fn stack_size(mut self, bytes: usize) -> Builder {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// Follows a cgroup CPU quota that changes at runtime, e.g. a Kubernetes
+    /// pod resized by the vertical pod autoscaler. Unless `num_threads` says
+    /// otherwise the pool gets a worker for every CPU of the machine, of
+    /// which only as many as the quota allows take part in jobs; a job
+    /// started more than `interval` after the last look re-reads the quota
+    /// first. `Pool::refresh_cpu_quota` re-reads it on demand instead.
+    pub fn track_cpu_quota(mut self, interval: Duration) -> Builder {
+        self.quota_interval = Some(interval);
+        self
+    }
+
+    /// Lets only `min` workers take part in jobs at first and adjusts that
+    /// number to the load: a job started more than `interval` after the last
+    /// adjustment doubles it, up to `num_threads`, if the active workers were
+    /// busy for at least 3/4 of that time, and halves it, down to `min`, for
+    /// every interval they were mostly idle. With an `idle_timeout`, the
+    /// workers left out exit until a burst needs them again, so a pool that
+    /// serves both interactive requests and batch jobs keeps few threads at
+    /// rest and all of them during bursts.
+    pub fn autoscale(mut self, min: usize, interval: Duration) -> Builder {
+        self.autoscale = Some((min, interval));
+        self
+    }
+
+    /// Sizes the pool to one worker per physical core the process may run
+    /// on, skipping the SMT siblings, and pins every worker to its core, for
+    /// kernels that get slower when two hyperthreads share a core. An
+    /// explicit `num_threads` still wins over the size. Setting
+    /// `IEU_PHYSICAL_CORES=1` does the same for all pools. Only Linux exposes
+    /// the topology so far, elsewhere this does nothing.
+    pub fn physical_cores(mut self, physical: bool) -> Builder {
+        self.physical = physical;
+        self
+    }
+
+    /// Pins every worker to one of the CPUs the process may run on, worker
+    /// `i` to the `i`-th of them (wrapping around when there are more
+    /// workers than CPUs), without changing the size of the pool. Does
+    /// nothing outside Linux and Windows, and `physical_cores` or a
+    /// placement take precedence.
+    pub fn pin_threads(mut self, pin: bool) -> Builder {
+        self.pin = pin;
+        self
+    }
+
+    /// Sizes the pool to the CPUs `placement` picks from the topology hwloc
+    /// detects and pins one worker to each, e.g. one per L3 slice. An
+    /// explicit `num_threads` still wins over the size. Falls back to an
+    /// unpinned pool if hwloc can't read the topology.
+    #[cfg(feature = "hwloc")]
+    pub fn placement(mut self, placement: crate::Placement) -> Builder {
+        self.placement = Some(placement);
+        self
+    }
+
+    /// Calls `func` on the submitting thread whenever it has waited another
+    /// `timeout` for a job to complete, with what the job is up to: which
+    /// workers are still running which index and which are done with it.
+    /// With the `log` feature the same goes to a warning. For the "execute
+    /// never returns" kind of bug.
+    pub fn on_hang(
+        mut self,
+        timeout: Duration,
+        func: impl Fn(&Hang) + Send + Sync + 'static,
+    ) -> Builder {
+        self.on_hang = Some(HangHook(timeout, Arc::new(func)));
+        self
+    }
+
+    /// Runs `func(worker)` on every worker that takes part in a job, before
+    /// the first index it runs, e.g. to set up per-job scratch state. Jobs
+    /// that run inline (on a pool without workers, in sequential mode or
+    /// nested in another job of the pool) don't call it.
+    pub fn on_job_start(mut self, func: impl Fn(usize) + Send + Sync + 'static) -> Builder {
+        self.job_start = Some(JobHook(Arc::new(func)));
+        self
+    }
+
+    /// Runs `func(worker)` on every worker that took part in a job, after
+    /// the last index it runs and before the job counts as done, e.g. to
+    /// reset a bump allocator or clear a scratch map. Like `on_job_start`,
+    /// inline jobs don't call it.
+    pub fn on_job_end(mut self, func: impl Fn(usize) + Send + Sync + 'static) -> Builder {
+        self.job_end = Some(JobHook(Arc::new(func)));
+        self
+    }
+
+    /// Runs `func(worker)` on every worker thread right before it exits,
+    /// when the pool shuts down or the worker retires after `idle_timeout`,
+    /// e.g. to flush buffered output or close per-thread handles while the
+    /// thread's locals are still there. Dropping the pool waits for it on
+    /// every worker. A worker that dies from a panic doesn't call it.
+    pub fn on_worker_exit(mut self, func: impl Fn(usize) + Send + Sync + 'static) -> Builder {
+        self.worker_exit = Some(JobHook(Arc::new(func)));
+        self
+    }
+
+    /// Applies those of these environment variables that are set (and
+    /// parse) over whatever was configured before, so ops can tune a pool
+    /// without recompiling:
+    ///
+    /// - `{prefix}_NUM_THREADS`: `num_threads`
+    /// - `{prefix}_PHYSICAL_CORES`: `physical_cores`, unless empty or `0`
+    /// - `{prefix}_PIN_THREADS`: `pin_threads`, unless empty or `0`
+    /// - `{prefix}_STACK_SIZE`: `stack_size` in bytes
+    /// - `{prefix}_SPIN_US`: `linger` in microseconds
+    /// - `{prefix}_THREAD_PRIORITY`: `priority`, by name
+    /// - `{prefix}_QUOTA_INTERVAL_MS`: `track_cpu_quota` in milliseconds
+    /// - `{prefix}_AUTOSCALE_MIN`: `autoscale`, with the interval from
+    ///   `{prefix}_AUTOSCALE_INTERVAL_MS`, the one configured before or 100
+    ///   milliseconds
+    /// - `{prefix}_POWER_SAVING`: `power_saving`, unless empty or `0`
+    /// - `{prefix}_YIELD_EVERY`: `yield_every`
+    /// - `{prefix}_YIELD_SLEEP_US`: `yield_sleep` in microseconds
+    /// - `{prefix}_INLINE_THRESHOLD`: `inline_threshold`
+    /// - `{prefix}_HELP_WHEN_BUSY`: `help_when_busy`, unless empty or `0`
+    /// - `{prefix}_MAX_QUEUED_TASKS`: `max_queued_tasks`, with the policy from
+    ///   `{prefix}_QUEUE_POLICY` (`block`, `reject` or `inline`) or the one
+    ///   configured before
+    /// - `{prefix}_CHAOS_SEED`: `chaos`, with the `chaos` feature
+    ///
+    /// The global pool applies them with the prefix `IEU`.
+    pub fn env(mut self, prefix: &str) -> Builder {
+        let flag = |name| env_var::<String>(prefix, name).map(|s| !s.is_empty() && s != "0");
+        if let Some(num_threads) = env_var(prefix, "NUM_THREADS") {
+            self.num_threads = Some(num_threads);
+        }
+        if let Some(physical) = flag("PHYSICAL_CORES") {
+            self.physical = physical;
+        }
+        if let Some(pin) = flag("PIN_THREADS") {
+            self.pin = pin;
+        }
+        if let Some(bytes) = env_var(prefix, "STACK_SIZE") {
+            self.stack_size = Some(bytes);
+        }
+        if let Some(micros) = env_var(prefix, "SPIN_US") {
+            self.linger = Some(Duration::from_micros(micros));
+        }
+        if let Some(priority) = env_var(prefix, "THREAD_PRIORITY") {
+            self.priority = Some(priority);
+        }
+        if let Some(millis) = env_var(prefix, "QUOTA_INTERVAL_MS") {
+            self.quota_interval = Some(Duration::from_millis(millis));
+        }
+        if let Some(min) = env_var(prefix, "AUTOSCALE_MIN") {
+            let interval = env_var(prefix, "AUTOSCALE_INTERVAL_MS").map(Duration::from_millis);
+            let interval = interval.or(self.autoscale.map(|(_, interval)| interval));
+            self = self.autoscale(min, interval.unwrap_or(Duration::from_millis(100)));
+        }
+        if let Some(power_saving) = flag("POWER_SAVING") {
+            self.power_saving = power_saving;
+        }
+        if let Some(indices) = env_var(prefix, "YIELD_EVERY") {
+            self = self.yield_every(indices);
+        }
+        if let Some(micros) = env_var(prefix, "YIELD_SLEEP_US") {
+            self = self.yield_sleep(Duration::from_micros(micros));
+        }
+        if let Some(num) = env_var(prefix, "INLINE_THRESHOLD") {
+            self = self.inline_threshold(num);
+        }
+        if let Some(help) = flag("HELP_WHEN_BUSY") {
+            self.help_when_busy = help;
+        }
+        let policy = env_var(prefix, "QUEUE_POLICY");
+        let limit = env_var(prefix, "MAX_QUEUED_TASKS");
+        if let Some(limit) = limit.or(self.queue_limit.map(|(limit, _)| limit)) {
+            let policy = policy.or(self.queue_limit.map(|(_, policy)| policy));
+            self = self.max_queued_tasks(limit, policy.unwrap_or_default());
+        }
+        #[cfg(feature = "chaos")]
+        if let Some(seed) = env_var(prefix, "CHAOS_SEED") {
+            self = self.chaos(seed);
+        }
+        self
+    }
+
+    pub fn build(self) -> Pool {
+        self.try_build().expect("failed to spawn ieu workers")
+    }
+
+    /// Like `build`, but returns the error if a worker can't be spawned
+    /// instead of panicking. Lazy pools spawn their workers later and only
+    /// fail here for other reasons.
+    pub fn try_build(self) -> io::Result<Pool> {
+        Pool::from_builder(self)
+    }
+}
+
+// `{prefix}_{name}` if it's set and parses
+fn env_var<T: FromStr>(prefix: &str, name: &str) -> Option<T> {
+    std::env::var(format!("{prefix}_{name}")).ok()?.parse().ok()
+}
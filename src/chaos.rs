@@ -0,0 +1,46 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::{rng_for_index, sync::thread, IndexRng};
+
+// The faults `Builder::chaos` injects. Every decision draws from the seeded
+// generator of a running count, so a seed picks the sequence of decisions;
+// which worker gets which of them still depends on timing.
+pub(crate) struct Chaos {
+    seed:  u64,
+    draws: AtomicUsize,
+}
+
+impl Chaos {
+    pub(crate) fn new(seed: u64) -> Chaos {
+        Chaos {
+            seed,
+            draws: AtomicUsize::new(0),
+        }
+    }
+
+    fn rng(&self) -> IndexRng {
+        rng_for_index(self.seed, self.draws.fetch_add(1, Ordering::Relaxed))
+    }
+
+    // true one time in `odds`
+    pub(crate) fn roll(&self, odds: u64) -> bool {
+        self.rng().below(odds) == 0
+    }
+
+    // usually nothing, sometimes a yield and now and then a sleep of up to
+    // 100µs, so the workers fall behind each other at random
+    pub(crate) fn delay(&self) {
+        let mut rng = self.rng();
+        match rng.below(16) {
+            0 | 1 => thread::yield_now(),
+            2 => std::thread::sleep(Duration::from_micros(rng.below(100))),
+            _ => {},
+        }
+    }
+
+    // up to a millisecond, how long a spuriously woken worker waits
+    pub(crate) fn pause(&self) -> Duration {
+        Duration::from_micros(self.rng().below(1000))
+    }
+}
@@ -0,0 +1,160 @@
+use std::{io, time::Duration};
+
+use crate::{Builder, Pool, QueuePolicy, ThreadPriority};
+
+/// The settings of a pool as plain data, e.g. read from a section of a
+/// service's TOML configuration with the `serde` feature, where priorities
+/// and queue policies are written as in `IEU_THREAD_PRIORITY` and
+/// `IEU_QUEUE_POLICY`. Settings left out keep what the `Builder` had.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct PoolConfig {
+    /// `Builder::num_threads`
+    pub threads:          Option<usize>,
+    /// `Builder::pin_threads`
+    pub pin_threads:      Option<bool>,
+    /// `Builder::physical_cores`
+    pub physical_cores:   Option<bool>,
+    /// `Builder::stack_size` in bytes
+    pub stack_size:       Option<usize>,
+    /// `Builder::linger` in microseconds
+    pub spin_us:          Option<u64>,
+    /// `Builder::busy_wait`
+    pub busy_wait:        Option<bool>,
+    /// `Builder::idle_timeout` in milliseconds
+    pub idle_timeout_ms:  Option<u64>,
+    /// `Builder::priority`
+    pub priority:         Option<ThreadPriority>,
+    /// `Builder::power_saving`
+    pub power_saving:     Option<bool>,
+    /// `Builder::yield_every`
+    pub yield_every:      Option<usize>,
+    /// `Builder::inline_threshold`
+    pub inline_threshold: Option<usize>,
+    /// `Builder::help_when_busy`
+    pub help_when_busy:   Option<bool>,
+    /// `Builder::max_queued_tasks`, with `queue_policy` or the policy
+    /// configured before
+    pub max_queued_tasks: Option<usize>,
+    /// The policy of `Builder::max_queued_tasks`. Only takes effect along
+    /// with a limit, from `max_queued_tasks` or configured before, as an
+    /// unbounded queue never fills up; `Pool::from_config` rejects it alone.
+    pub queue_policy:     Option<QueuePolicy>,
+}
+
+impl Builder {
+    /// Applies the settings `config` has over whatever was configured
+    /// before, like `env` does for environment variables. A `queue_policy`
+    /// without `max_queued_tasks` only replaces the policy of a limit
+    /// configured before, and is ignored if there is none.
+    pub fn config(mut self, config: &PoolConfig) -> Builder {
+        if let Some(threads) = config.threads {
+            self = self.num_threads(threads);
+        }
+        if let Some(pin) = config.pin_threads {
+            self = self.pin_threads(pin);
+        }
+        if let Some(physical) = config.physical_cores {
+            self = self.physical_cores(physical);
+        }
+        if let Some(bytes) = config.stack_size {
+            self = self.stack_size(bytes);
+        }
+        if let Some(micros) = config.spin_us {
+            self = self.linger(Duration::from_micros(micros));
+        }
+        if let Some(busy_wait) = config.busy_wait {
+            self = self.busy_wait(busy_wait);
+        }
+        if let Some(millis) = config.idle_timeout_ms {
+            self = self.idle_timeout(Duration::from_millis(millis));
+        }
+        if let Some(priority) = config.priority {
+            self = self.priority(priority);
+        }
+        if let Some(power_saving) = config.power_saving {
+            self = self.power_saving(power_saving);
+        }
+        if let Some(indices) = config.yield_every {
+            self = self.yield_every(indices);
+        }
+        if let Some(num) = config.inline_threshold {
+            self = self.inline_threshold(num);
+        }
+        if let Some(help) = config.help_when_busy {
+            self = self.help_when_busy(help);
+        }
+        let limit = config.max_queued_tasks;
+        if let Some(limit) = limit.or(self.queue_limit.map(|(limit, _)| limit)) {
+            let policy = config
+                .queue_policy
+                .or(self.queue_limit.map(|(_, policy)| policy));
+            self = self.max_queued_tasks(limit, policy.unwrap_or_default());
+        }
+        self
+    }
+}
+
+impl Pool {
+    // builds a pool with the settings of `config`, failing like
+    // `Builder::try_build` if a worker can't be spawned, or with
+    // `InvalidInput` for a `queue_policy` without `max_queued_tasks`, which
+    // would have no queue limit to apply to
+    pub fn from_config(config: &PoolConfig) -> io::Result<Pool> {
+        if config.queue_policy.is_some() && config.max_queued_tasks.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "queue_policy is set without max_queued_tasks",
+            ));
+        }
+        Builder::new().config(config).try_build()
+    }
+}
+
+// priorities and queue policies are strings in configuration files, as in
+// the environment variables
+#[cfg(feature = "serde")]
+mod names {
+    use std::{fmt::Display, str::FromStr};
+
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{QueuePolicy, ThreadPriority};
+
+    fn serialize<S: Serializer>(value: &impl Display, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    fn deserialize<'de, T: FromStr<Err = String>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+
+    impl Serialize for ThreadPriority {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(self, serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for ThreadPriority {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ThreadPriority, D::Error> {
+            deserialize(deserializer)
+        }
+    }
+
+    impl Serialize for QueuePolicy {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(self, serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for QueuePolicy {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<QueuePolicy, D::Error> {
+            deserialize(deserializer)
+        }
+    }
+}
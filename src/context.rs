@@ -0,0 +1,102 @@
+use std::{any::Any, cell::Cell, ops::Deref};
+
+use crate::{local::PerWorker, Pool};
+
+thread_local! {
+    // the context of the `execute_with_context` job this thread is running
+    // an index of, only set while the index runs
+    static JOB_CONTEXT: Cell<Option<*const (dyn Any + Send + Sync)>> = const { Cell::new(None) };
+}
+
+// The context of the job the calling thread is running an index of, if it
+// was started with `execute_with_context` and the context is a `T`. Jobs
+// nested in that index see it too, tasks spawned from it don't.
+pub fn current_job_context<T: Clone + 'static>() -> Option<T> {
+    let ctx = JOB_CONTEXT.get()?;
+    // only set while the job that borrows the context runs an index
+    unsafe { &*ctx }.downcast_ref::<T>().cloned()
+}
+
+/// A pool whose workers each own a context value, e.g. a GPU staging buffer
+/// or a database connection that can't be created inside a job. Created by
+/// `Pool::new_with_contexts`, it dereferences to the `Pool` for jobs that
+/// don't need the contexts.
+pub struct ContextPool<C> {
+    pool:     Pool,
+    contexts: PerWorker<C>,
+}
+
+impl<C: Send> ContextPool<C> {
+    /// Runs `func(context, i)` for every index in `0..num`, with the context
+    /// of the worker running the index. Takes `&mut self` so that no two
+    /// jobs ever share a context.
+    pub fn execute(&mut self, num: usize, func: impl Fn(&mut C, usize) + Send + Sync) {
+        let contexts = &self.contexts;
+        // a worker runs one index at a time, and only this job uses the
+        // contexts
+        self.pool
+            .run(num, |worker, i| func(unsafe { contexts.get(worker) }, i));
+    }
+
+    /// The contexts in worker order, e.g. to flush them between jobs.
+    pub fn contexts_mut(&mut self) -> impl Iterator<Item = &mut C> {
+        self.contexts.iter_mut()
+    }
+
+    /// Shuts the pool down and hands the contexts back in worker order.
+    pub fn into_contexts(self) -> Vec<C> {
+        drop(self.pool);
+        self.contexts.into_inner()
+    }
+}
+
+impl<C> Deref for ContextPool<C> {
+    type Target = Pool;
+
+    fn deref(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+impl Pool {
+    // a pool with one worker per context, worker `k` owning `contexts[k]`;
+    // panics if `contexts` is empty, a pool without workers runs its jobs
+    // with the first one
+    pub fn new_with_contexts<C: Send>(contexts: Vec<C>) -> ContextPool<C> {
+        assert!(
+            !contexts.is_empty(),
+            "a context pool needs at least one context"
+        );
+        let pool = Pool::new(contexts.len());
+        let len = contexts.len();
+        let mut contexts = contexts.into_iter();
+        ContextPool {
+            pool,
+            contexts: PerWorker::new(len, || contexts.next().unwrap()),
+        }
+    }
+
+    // like `execute`, with `ctx` available through `current_job_context`
+    // to everything `func` calls, e.g. a request id or a cancellation flag
+    // for library code whose signatures can't take it
+    pub fn execute_with_context<T: Send + Sync + 'static>(
+        &self,
+        ctx: T,
+        num: usize,
+        func: impl Fn(usize) + Send + Sync,
+    ) {
+        struct Restore(Option<*const (dyn Any + Send + Sync)>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                JOB_CONTEXT.set(self.0);
+            }
+        }
+
+        let ctx: &(dyn Any + Send + Sync) = &ctx;
+        self.run(num, |_, i| {
+            let _restore = Restore(JOB_CONTEXT.replace(Some(ctx)));
+            func(i);
+        });
+    }
+}
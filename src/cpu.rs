@@ -0,0 +1,180 @@
+use std::num::NonZeroUsize;
+
+pub(crate) fn available() -> usize {
+    let cpus = machine();
+    match quota() {
+        Some(quota) => cpus.min(quota),
+        None => cpus,
+    }
+}
+
+// the CPUs the process may run on, ignoring any CPU quota
+pub(crate) fn machine() -> usize {
+    let cpus = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    // std only counts the processor group of the process, the workers are
+    // spread over all of them
+    #[cfg(windows)]
+    let cpus = match crate::sys::processor_groups() {
+        groups if groups.len() > 1 => groups.iter().sum(),
+        _ => cpus,
+    };
+    cpus
+}
+
+// The CPUs to pin workers to for one worker per physical core: of every core
+// the process may run on, its first allowed hardware thread. `None` where
+// the topology isn't known (Linux only, unless hwloc is there to ask).
+#[cfg(feature = "hwloc")]
+pub(crate) fn physical_cores() -> Option<Vec<usize>> {
+    let cores = crate::Topology::detect()?.place(&crate::Placement::Compact);
+    let quota = quota().unwrap_or(usize::MAX);
+    Some(cores.into_iter().take(quota).collect::<Vec<_>>()).filter(|cores| !cores.is_empty())
+}
+
+#[cfg(all(target_os = "linux", not(feature = "hwloc")))]
+pub(crate) fn physical_cores() -> Option<Vec<usize>> {
+    let allowed = crate::sys::allowed_cpus()?;
+    let cores = allowed
+        .iter()
+        .copied()
+        .filter(|&cpu| {
+            let siblings = std::fs::read_to_string(format!(
+                "/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list",
+                cpu
+            ));
+            siblings
+                .ok()
+                .and_then(|list| parse_cpu_list(list.trim()))
+                .is_none_or(|siblings| {
+                    siblings
+                        .into_iter()
+                        .find(|sibling| allowed.contains(sibling))
+                        == Some(cpu)
+                })
+        })
+        .collect::<Vec<_>>();
+    let quota = quota().unwrap_or(usize::MAX);
+    Some(cores.into_iter().take(quota).collect::<Vec<_>>()).filter(|cores| !cores.is_empty())
+}
+
+#[cfg(not(any(target_os = "linux", feature = "hwloc")))]
+pub(crate) fn physical_cores() -> Option<Vec<usize>> {
+    None
+}
+
+// the CPUs the process may run on, in order, for `Builder::pin_threads`
+#[cfg(target_os = "linux")]
+pub(crate) fn allowed() -> Option<Vec<usize>> {
+    crate::sys::allowed_cpus().filter(|cpus| !cpus.is_empty())
+}
+
+#[cfg(windows)]
+pub(crate) fn allowed() -> Option<Vec<usize>> {
+    let cpus = crate::sys::processor_groups().into_iter().sum::<usize>();
+    (cpus > 0).then(|| (0..cpus).collect())
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub(crate) fn allowed() -> Option<Vec<usize>> {
+    None
+}
+
+// The efficiency cores of a hybrid CPU the process may run on, where Linux
+// lists them apart from the performance cores (Intel's `cpu_atom`).
+#[cfg(target_os = "linux")]
+pub(crate) fn efficiency_cores() -> Option<Vec<usize>> {
+    let list = std::fs::read_to_string("/sys/devices/cpu_atom/cpus").ok()?;
+    let allowed = crate::sys::allowed_cpus()?;
+    let cores = parse_cpu_list(list.trim())?
+        .into_iter()
+        .filter(|cpu| allowed.contains(cpu))
+        .collect::<Vec<_>>();
+    (!cores.is_empty()).then_some(cores)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn efficiency_cores() -> Option<Vec<usize>> {
+    None
+}
+
+// parses lists like `0-3,8,10-11` from sysfs
+#[cfg(target_os = "linux")]
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',') {
+        match range.split_once('-') {
+            Some((start, end)) => cpus.extend(start.parse::<usize>().ok()?..=end.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn quota() -> Option<usize> {
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let mut best: Option<usize> = None;
+    for line in cgroups.lines() {
+        let mut parts = line.splitn(3, ':');
+        let (_, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+        let quota = match controllers {
+            // cgroup v2 unified hierarchy
+            "" => cgroup_v2_quota(path),
+            c if c.split(',').any(|c| c == "cpu") => cgroup_v1_quota(path),
+            _ => None,
+        };
+        if let Some(quota) = quota {
+            best = Some(best.map_or(quota, |best| best.min(quota)));
+        }
+    }
+    best
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn quota() -> Option<usize> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn cgroup_v2_quota(path: &str) -> Option<usize> {
+    // containers usually see their own cgroup mounted at the root
+    [
+        format!("/sys/fs/cgroup{}/cpu.max", path),
+        "/sys/fs/cgroup/cpu.max".to_string(),
+    ]
+    .iter()
+    .find_map(|file| {
+        let max = std::fs::read_to_string(file).ok()?;
+        let mut parts = max.split_whitespace();
+        let quota = parts.next()?.parse::<u64>().ok()?;
+        let period = parts.next()?.parse::<u64>().ok()?;
+        ratio(quota, period)
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn cgroup_v1_quota(path: &str) -> Option<usize> {
+    ["/sys/fs/cgroup/cpu", "/sys/fs/cgroup/cpu,cpuacct"]
+        .iter()
+        .flat_map(|root| [format!("{}{}", root, path), root.to_string()])
+        .find_map(|dir| {
+            let read = |name| {
+                std::fs::read_to_string(format!("{}/{}", dir, name))
+                    .ok()?
+                    .trim()
+                    .parse::<i64>()
+                    .ok()
+            };
+            let quota = u64::try_from(read("cpu.cfs_quota_us")?).ok()?;
+            let period = u64::try_from(read("cpu.cfs_period_us")?).ok()?;
+            ratio(quota, period)
+        })
+}
+
+#[cfg(target_os = "linux")]
+fn ratio(quota: u64, period: u64) -> Option<usize> {
+    if quota == 0 || period == 0 {
+        return None;
+    }
+    Some((quota.div_ceil(period) as usize).max(1))
+}
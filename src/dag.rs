@@ -0,0 +1,186 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    global::with_global,
+    sync::{thread, AtomicBool, AtomicUsize, Mutex, Ordering},
+    Pool,
+};
+
+type Func<'a> = Box<dyn Fn(usize) + Send + Sync + 'a>;
+
+/// A set of jobs with dependencies between them, like the steps of a build,
+/// created by `Pool::dag` (or `ieu::dag` for the global pool). `run` starts
+/// every job as soon as the jobs it was added after have completed, so
+/// independent jobs run side by side and a job's indices are shared by
+/// whichever workers are free. The jobs may borrow from the caller.
+pub struct Dag<'a> {
+    pool: Option<&'a Pool>,
+    jobs: Vec<Node<'a>>,
+}
+
+/// A job added to a `Dag`, for later jobs to depend on. Only meaningful to
+/// the `Dag` that returned it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct JobId(usize);
+
+struct Node<'a> {
+    func:       Func<'a>,
+    num:        usize,
+    // the jobs added after this one
+    dependents: Vec<usize>,
+    depends_on: usize,
+}
+
+// what the workers share while the jobs run
+struct Run<'a, 'r> {
+    jobs:     &'r [Node<'a>],
+    // per job: the jobs it still waits for, the next index to hand out and
+    // the indices that ran
+    waiting:  Vec<AtomicUsize>,
+    next:     Vec<AtomicUsize>,
+    ran:      Vec<AtomicUsize>,
+    // jobs whose dependencies completed and that have indices left to hand
+    // out, in the order they got ready
+    ready:    Mutex<Vec<usize>>,
+    finished: AtomicUsize,
+    failed:   AtomicBool,
+    panic:    Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl<'a> Dag<'a> {
+    pub(crate) fn new(pool: Option<&'a Pool>) -> Dag<'a> {
+        Dag {
+            pool,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// Adds a job running `func` over `0..num` that can start right away.
+    pub fn add(&mut self, num: usize, func: impl Fn(usize) + Send + Sync + 'a) -> JobId {
+        self.add_after(&[], num, func)
+    }
+
+    /// Adds a job running `func` over `0..num` that starts once every job in
+    /// `deps` has completed. Panics if one of `deps` wasn't added to this
+    /// `Dag`.
+    pub fn add_after(
+        &mut self,
+        deps: &[JobId],
+        num: usize,
+        func: impl Fn(usize) + Send + Sync + 'a,
+    ) -> JobId {
+        let id = self.jobs.len();
+        for dep in deps {
+            assert!(dep.0 < id, "a job can only depend on jobs added before it");
+            self.jobs[dep.0].dependents.push(id);
+        }
+        self.jobs.push(Node {
+            func: Box::new(func),
+            num,
+            dependents: Vec::new(),
+            depends_on: deps.len(),
+        });
+        JobId(id)
+    }
+
+    /// Runs every job and returns once all of them have completed. A panic in
+    /// any job keeps the jobs that haven't started from starting and is
+    /// resumed here once the workers are done.
+    pub fn run(self) {
+        match self.pool {
+            Some(pool) => self.run_on(pool),
+            None => with_global(|pool| self.run_on(pool)),
+        }
+    }
+
+    fn run_on(self, pool: &Pool) {
+        let counters = || self.jobs.iter().map(|_| AtomicUsize::new(0)).collect();
+        let run = Run {
+            jobs:     &self.jobs,
+            waiting:  self
+                .jobs
+                .iter()
+                .map(|job| AtomicUsize::new(job.depends_on))
+                .collect(),
+            next:     counters(),
+            ran:      counters(),
+            ready:    Mutex::new(Vec::new()),
+            finished: AtomicUsize::new(0),
+            failed:   AtomicBool::new(false),
+            panic:    Mutex::new(None),
+        };
+        for (id, job) in self.jobs.iter().enumerate() {
+            if job.depends_on == 0 {
+                run.start(id);
+            }
+        }
+        pool.run(pool.num_threads().max(1), |_, _| run.drive());
+        if let Some(payload) = run.panic.into_inner().unwrap() {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl Run<'_, '_> {
+    // run by every worker until every job completed or one panicked
+    fn drive(&self) {
+        while !self.failed.load(Ordering::SeqCst) {
+            if self.finished.load(Ordering::SeqCst) == self.jobs.len() {
+                return;
+            }
+            match panic::catch_unwind(AssertUnwindSafe(|| self.step())) {
+                Ok(true) => {},
+                Ok(false) => thread::yield_now(),
+                Err(payload) => {
+                    self.panic.lock().unwrap().get_or_insert(payload);
+                    self.failed.store(true, Ordering::SeqCst);
+                },
+            }
+        }
+    }
+
+    // runs an index of the oldest ready job, returns whether there was one
+    fn step(&self) -> bool {
+        let Some(id) = self.ready.lock().unwrap().first().copied() else {
+            return false;
+        };
+        let i = self.next[id].fetch_add(1, Ordering::SeqCst);
+        if i >= self.jobs[id].num {
+            // every index is handed out, the job is no longer ready
+            self.ready.lock().unwrap().retain(|&ready| ready != id);
+            return true;
+        }
+        (self.jobs[id].func)(i);
+        if self.ran[id].fetch_add(1, Ordering::SeqCst) + 1 == self.jobs[id].num {
+            self.complete(id);
+        }
+        true
+    }
+
+    // a job whose dependencies completed, empty ones complete right away
+    fn start(&self, id: usize) {
+        match self.jobs[id].num {
+            0 => self.complete(id),
+            _ => self.ready.lock().unwrap().push(id),
+        }
+    }
+
+    fn complete(&self, id: usize) {
+        for &dependent in &self.jobs[id].dependents {
+            if self.waiting[dependent].fetch_sub(1, Ordering::SeqCst) == 1 {
+                self.start(dependent);
+            }
+        }
+        self.finished.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Pool {
+    // starts an empty `Dag` of jobs that run on this pool
+    pub fn dag(&self) -> Dag<'_> {
+        Dag::new(Some(self))
+    }
+}
@@ -0,0 +1,46 @@
+use crate::Pool;
+
+impl Pool {
+    // `hash` of every `chunk_size` bytes of `data` in order, the last chunk
+    // possibly shorter, e.g. a CRC per block of a large file to compare with
+    // a list of them; a `chunk_size` of 0 counts as 1
+    pub fn par_hash_chunks<D: Send>(
+        &self,
+        data: &[u8],
+        chunk_size: usize,
+        hash: impl Fn(&[u8]) -> D + Send + Sync,
+    ) -> Vec<D> {
+        let chunk_size = chunk_size.max(1);
+        self.vec_from_fn(data.len().div_ceil(chunk_size), |chunk| {
+            let start = chunk * chunk_size;
+            hash(&data[start..(start + chunk_size).min(data.len())])
+        })
+    }
+
+    // The root of a binary hash tree over `data`: `leaf` hashes every chunk
+    // like `par_hash_chunks`, then `node` combines neighbouring digests level
+    // by level until one is left, an odd digest at the end of a level moving
+    // up unchanged. Every level is hashed in parallel. Empty data is a single
+    // empty chunk.
+    pub fn par_hash_tree<D: Clone + Send + Sync>(
+        &self,
+        data: &[u8],
+        chunk_size: usize,
+        leaf: impl Fn(&[u8]) -> D + Send + Sync,
+        node: impl Fn(&D, &D) -> D + Send + Sync,
+    ) -> D {
+        let mut level = self.par_hash_chunks(data, chunk_size, &leaf);
+        if level.is_empty() {
+            return leaf(&[]);
+        }
+        while level.len() > 1 {
+            level = self.vec_from_fn(level.len().div_ceil(2), |i| {
+                match level.get(2 * i + 1) {
+                    Some(right) => node(&level[2 * i], right),
+                    None => level[2 * i].clone(),
+                }
+            });
+        }
+        level.pop().unwrap()
+    }
+}
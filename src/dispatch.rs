@@ -0,0 +1,221 @@
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+
+use crate::sync::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+// called with the participating worker's index and the claimed index
+pub(crate) type Func = Box<dyn Fn(usize, usize) + Send + Sync>;
+
+// The job slot shared by the submitter and the workers. A job is complete
+// once every index has been claimed and every worker that entered the slot
+// has left it again, so completion never depends on which (or how many)
+// workers actually woke up.
+pub(crate) struct Dispatch {
+    func:    AtomicPtr<Func>,
+    max:     AtomicUsize,
+    cnt:     AtomicUsize,
+    active:  AtomicUsize,
+    // workers with an index at or past this sit the job out
+    workers: AtomicUsize,
+    // if set, the first `workers` flags of which workers take part
+    allowed: AtomicPtr<bool>,
+    // the indices handed out before the job was stopped
+    stopped: AtomicUsize,
+}
+
+impl Dispatch {
+    pub(crate) fn new() -> Dispatch {
+        Dispatch {
+            func:    AtomicPtr::new(core::ptr::null_mut()),
+            max:     AtomicUsize::new(0),
+            cnt:     AtomicUsize::new(0),
+            active:  AtomicUsize::new(0),
+            workers: AtomicUsize::new(usize::MAX),
+            allowed: AtomicPtr::new(core::ptr::null_mut()),
+            stopped: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    // Safety: `func` must stay alive until `close` has been called and
+    // `is_idle` has returned true afterwards.
+    pub(crate) unsafe fn open(&self, num: usize, workers: usize, func: *mut Func) {
+        unsafe { self.open_for(num, workers, core::ptr::null(), func) };
+    }
+
+    // like `open`, only the workers flagged in `allowed` take part
+    //
+    // Safety: as for `open`, and `allowed` must point to `workers` flags that
+    // stay alive just as long.
+    pub(crate) unsafe fn open_for(
+        &self,
+        num: usize,
+        workers: usize,
+        allowed: *const bool,
+        func: *mut Func,
+    ) {
+        self.cnt.store(0, Ordering::SeqCst);
+        self.max.store(num, Ordering::SeqCst);
+        self.workers.store(workers, Ordering::SeqCst);
+        self.allowed.store(allowed as *mut bool, Ordering::SeqCst);
+        self.stopped.store(usize::MAX, Ordering::SeqCst);
+        self.func.store(func, Ordering::SeqCst);
+    }
+
+    pub(crate) fn close(&self) {
+        self.func.store(core::ptr::null_mut(), Ordering::SeqCst);
+    }
+
+    // makes every later claim fail so workers drop out at their next index
+    #[cfg(feature = "std")]
+    pub(crate) fn stop(&self) {
+        let max = self.max.load(Ordering::SeqCst);
+        let cnt = self.cnt.fetch_max(max, Ordering::SeqCst);
+        self.stopped.fetch_min(cnt.min(max), Ordering::SeqCst);
+    }
+
+    // Indices are handed out in order and each one handed out runs, so once
+    // the job is done the indices that ran are exactly `0..handed_out()`.
+    #[cfg(feature = "std")]
+    pub(crate) fn handed_out(&self) -> usize {
+        let max = self.max.load(Ordering::SeqCst);
+        let cnt = self.cnt.load(Ordering::SeqCst);
+        cnt.min(max).min(self.stopped.load(Ordering::SeqCst))
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn is_open(&self) -> bool {
+        !self.func.load(Ordering::SeqCst).is_null()
+    }
+
+    // the indices handed out so far, the job's length and the workers inside
+    // the slot, for debug output
+    #[cfg(feature = "std")]
+    pub(crate) fn progress(&self) -> (usize, usize, usize) {
+        (
+            self.handed_out(),
+            self.max.load(Ordering::SeqCst),
+            self.active.load(Ordering::SeqCst),
+        )
+    }
+
+    pub(crate) fn is_done(&self, num: usize) -> bool {
+        self.cnt.load(Ordering::SeqCst) >= num && self.is_idle()
+    }
+
+    pub(crate) fn is_idle(&self) -> bool {
+        self.active.load(Ordering::SeqCst) == 0
+    }
+
+    fn takes_part(&self, worker: usize) -> bool {
+        if worker >= self.workers.load(Ordering::SeqCst) {
+            return false;
+        }
+        let allowed = self.allowed.load(Ordering::SeqCst);
+        allowed.is_null() || unsafe { *allowed.add(worker) }
+    }
+
+    // Runs indices of the open job (if any) until none are left, returns
+    // whether this was the last worker to leave the slot.
+    pub(crate) fn run(&self, worker: usize) -> bool {
+        self.run_hooked(worker, || {}, || {})
+    }
+
+    // like `run`, calling `enter` before the first index this worker claims
+    // and `exit` after its last one, while it's still inside the slot
+    pub(crate) fn run_hooked(
+        &self,
+        worker: usize,
+        enter: impl FnOnce(),
+        exit: impl FnOnce(),
+    ) -> bool {
+        self.active.fetch_add(1, Ordering::SeqCst);
+        // leave the slot even when `func` unwinds, or the job never finishes
+        let leave = Leave(&self.active);
+        let func = self.func.load(Ordering::SeqCst);
+        if !func.is_null() && self.takes_part(worker) {
+            let func = unsafe { &*func };
+            let max = self.max.load(Ordering::SeqCst);
+            let mut enter = Some(enter);
+            loop {
+                let cnt = self.cnt.fetch_add(1, Ordering::SeqCst);
+                if cnt >= max {
+                    break;
+                }
+                if let Some(enter) = enter.take() {
+                    enter();
+                }
+                func(worker, cnt);
+            }
+            if enter.is_none() {
+                exit();
+            }
+        }
+        core::mem::forget(leave);
+        self.active.fetch_sub(1, Ordering::SeqCst) == 1
+    }
+}
+
+struct Leave<'a>(&'a AtomicUsize);
+
+impl Drop for Leave<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Safety: the returned pointer must be released with `free` once the job
+// using it is closed and idle, and must not outlive the borrows in `func`.
+pub(crate) unsafe fn erase<'a>(func: impl Fn(usize, usize) + Send + Sync + 'a) -> *mut Func {
+    let func = Box::new(func) as Box<dyn Fn(usize, usize) + Send + Sync + 'a>;
+    let func = unsafe {
+        core::mem::transmute::<
+            Box<dyn Fn(usize, usize) + Send + Sync + 'a>,
+            Box<dyn Fn(usize, usize) + Send + Sync + 'static>,
+        >(func)
+    };
+    Box::into_raw(Box::new(func))
+}
+
+pub(crate) unsafe fn free(func: *mut Func) {
+    drop(unsafe { Box::from_raw(func) });
+}
+
+// The thread to unpark when a job finishes, behind a tiny spin lock. It is only
+// held for a clone or a store, cheaper than a Mutex and Condvar round trip per
+// job and also available without std.
+pub(crate) struct Waiter<T> {
+    lock:   AtomicBool,
+    thread: UnsafeCell<Option<T>>,
+}
+
+unsafe impl<T: Send> Sync for Waiter<T> {}
+
+impl<T: Clone> Waiter<T> {
+    pub(crate) fn new() -> Waiter<T> {
+        Waiter {
+            lock:   AtomicBool::new(false),
+            thread: UnsafeCell::new(None),
+        }
+    }
+
+    fn with<R>(&self, func: impl FnOnce(&mut Option<T>) -> R) -> R {
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let ret = func(unsafe { &mut *self.thread.get() });
+        self.lock.store(false, Ordering::Release);
+        ret
+    }
+
+    pub(crate) fn get(&self) -> Option<T> {
+        self.with(|thread| thread.clone())
+    }
+
+    pub(crate) fn set(&self, value: Option<T>) {
+        self.with(|thread| *thread = value);
+    }
+}
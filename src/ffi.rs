@@ -0,0 +1,63 @@
+use std::ffi::c_void;
+
+use crate::Pool;
+
+pub type IeuCallback = extern "C" fn(user_data: *mut c_void, index: usize);
+
+struct UserData(*mut c_void);
+
+// the C caller is responsible for `user_data` being safe to share between the
+// workers for the duration of the call
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl UserData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn ieu_pool_new(size: usize) -> *mut Pool {
+    match Pool::try_new(size) {
+        Ok(pool) => Box::into_raw(Box::new(pool)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `pool` must come from `ieu_pool_new` and not have been freed. It may be
+/// used from several threads at once, their jobs share the workers.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ieu_pool_execute(
+    pool: *mut Pool,
+    num: usize,
+    func: IeuCallback,
+    user_data: *mut c_void,
+) {
+    let pool = unsafe { &*pool };
+    let user_data = UserData(user_data);
+    pool.execute(num, |i| func(user_data.get(), i));
+}
+
+/// # Safety
+///
+/// `pool` must come from `ieu_pool_new` (or be null) and must not be used
+/// afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ieu_pool_free(pool: *mut Pool) {
+    if !pool.is_null() {
+        drop(unsafe { Box::from_raw(pool) });
+    }
+}
+
+/// # Safety
+///
+/// `user_data` must be safe to use from several threads at once for the
+/// duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ieu_execute(num: usize, func: IeuCallback, user_data: *mut c_void) {
+    let user_data = UserData(user_data);
+    crate::execute(num, |i| func(user_data.get(), i));
+}
@@ -0,0 +1,94 @@
+use crate::{iter::SyncPtr, Pool};
+
+// chunks are a multiple of a page long, so every worker streams through
+// long runs of whole pages, which is what non-temporal stores like
+const PAGE: usize = 4096;
+
+impl Pool {
+    // one contiguous run of whole pages per worker, the last one gets the rest
+    fn page_chunk_len<T>(&self, len: usize) -> usize {
+        let per_page = (PAGE / size_of::<T>().max(1)).max(1);
+        len.div_ceil(self.num_threads().max(1))
+            .div_ceil(per_page)
+            .max(1)
+            * per_page
+    }
+
+    pub fn par_fill<T: Clone + Send + Sync>(&self, data: &mut [T], value: T) {
+        let len = data.len();
+        let chunk_len = self.page_chunk_len::<T>(len);
+        let ptr = SyncPtr(data.as_mut_ptr());
+        // every chunk is a disjoint part of `data`
+        self.run(len.div_ceil(chunk_len), |_, i| {
+            let start = i * chunk_len;
+            let end = (start + chunk_len).min(len);
+            let chunk =
+                unsafe { std::slice::from_raw_parts_mut(ptr.get().add(start), end - start) };
+            chunk.fill(value.clone());
+        });
+    }
+
+    // Splits `data` into exactly `num_threads()` contiguous tiles (at least
+    // one) of nearly equal length and calls `func(thread_idx, tile)` once per
+    // tile, worker `w` taking tile `w` unless it's busy elsewhere. For
+    // bandwidth-bound streaming kernels one long run per worker beats
+    // handing out indices. Tiles are empty when `data` is shorter than that.
+    pub fn par_tiles<T: Send>(&self, data: &mut [T], func: impl Fn(usize, &mut [T]) + Send + Sync) {
+        let len = data.len();
+        let tiles = self.num_threads().max(1);
+        let ptr = SyncPtr(data.as_mut_ptr());
+        // every tile is a disjoint part of `data`
+        self.run_parts(tiles, |t| {
+            let start = len * t / tiles;
+            let end = len * (t + 1) / tiles;
+            func(t, unsafe {
+                std::slice::from_raw_parts_mut(ptr.get().add(start), end - start)
+            });
+        });
+    }
+
+    // Allocates a `Vec` of `func(0), func(1), ..` with tile `w` of `par_tiles`
+    // written by worker `w` and no other, so on a NUMA machine with pinned
+    // workers (see `Builder::pin_threads`) the kernel places every page on
+    // the node of the worker that first touches it. Later `par_tiles` and
+    // `execute_sticky` jobs over the same length hand tile `w` back to worker
+    // `w`, keeping the accesses node-local.
+    pub fn first_touch_vec<T: Send>(
+        &self,
+        len: usize,
+        func: impl Fn(usize) -> T + Send + Sync,
+    ) -> Vec<T> {
+        let mut vec = Vec::<T>::with_capacity(len);
+        let ptr = SyncPtr(vec.as_mut_ptr());
+        let tiles = self.team_size();
+        // every worker runs exactly once and writes its own tile
+        self.run_per_worker(|t| {
+            for i in len * t / tiles..len * (t + 1) / tiles {
+                unsafe { ptr.get().add(i).write(func(i)) };
+            }
+        });
+        // a panic in `func` is resumed by `run_per_worker`, the elements
+        // written so far leak
+        unsafe { vec.set_len(len) };
+        vec
+    }
+
+    // panics if the slices differ in length, like `copy_from_slice`
+    pub fn par_copy<T: Copy + Send + Sync>(&self, dst: &mut [T], src: &[T]) {
+        assert_eq!(
+            dst.len(),
+            src.len(),
+            "source and destination lengths differ"
+        );
+        let len = dst.len();
+        let chunk_len = self.page_chunk_len::<T>(len);
+        let ptr = SyncPtr(dst.as_mut_ptr());
+        self.run(len.div_ceil(chunk_len), |_, i| {
+            let start = i * chunk_len;
+            let end = (start + chunk_len).min(len);
+            let chunk =
+                unsafe { std::slice::from_raw_parts_mut(ptr.get().add(start), end - start) };
+            chunk.copy_from_slice(&src[start..end]);
+        });
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Wake, Waker},
+};
+
+#[cfg(feature = "tokio")]
+use crate::global;
+use crate::{
+    pool::PoolInner,
+    sync::{self, AtomicUsize, Mutex, Ordering},
+    Pool,
+};
+
+const IDLE: usize = 0;
+const SCHEDULED: usize = 1;
+const RUNNING: usize = 2;
+// woken while being polled, polled again right away
+const NOTIFIED: usize = 3;
+const DONE: usize = 4;
+
+// A spawned future. Every wake queues one poll as a task, so futures are
+// polled by idle workers between jobs like any other spawned task. Always a
+// std `Arc`, that's what `Wake` wants.
+struct Task {
+    future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+    state:  AtomicUsize,
+    pool:   usize,
+    live:   sync::Arc<Mutex<bool>>,
+}
+
+impl Task {
+    fn poll(self: Arc<Task>) {
+        self.state.store(RUNNING, Ordering::SeqCst);
+        let waker = Waker::from(self.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut future = self.future.lock().unwrap();
+        loop {
+            if future.as_mut().unwrap().as_mut().poll(&mut cx).is_ready() {
+                *future = None;
+                self.state.store(DONE, Ordering::SeqCst);
+                return;
+            }
+            if self
+                .state
+                .compare_exchange(RUNNING, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return;
+            }
+            self.state.store(RUNNING, Ordering::SeqCst);
+        }
+    }
+
+    fn schedule(self: Arc<Task>) {
+        let live = self.live.clone();
+        let live = live.lock().unwrap();
+        // a pool that's gone leaves the future pending forever
+        if !*live {
+            return;
+        }
+        let pool = unsafe { &*(self.pool as *const PoolInner) };
+        match pool.size {
+            // polled right here, without holding up other wakers of the pool
+            0 => {
+                drop(live);
+                self.poll();
+            },
+            _ => pool.push_task(Box::new(move || self.poll())),
+        }
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Task>) {
+        let mut state = self.state.load(Ordering::SeqCst);
+        loop {
+            let next = match state {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+            match self
+                .state
+                .compare_exchange(state, next, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => break,
+                Err(actual) => state = actual,
+            }
+        }
+        if state == IDLE {
+            self.schedule();
+        }
+    }
+}
+
+impl Pool {
+    // Runs `future` on the workers: it's polled as a spawned task whenever
+    // it's woken, so a pool can own a few background futures alongside its
+    // jobs without a separate executor. Polls that block hold up the worker
+    // like any other task.
+    pub fn spawn_future(&self, future: impl Future<Output = ()> + Send + 'static) {
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            state:  AtomicUsize::new(SCHEDULED),
+            pool:   &*self.inner as *const PoolInner as usize,
+            live:   self.inner.live.clone(),
+        });
+        self.push_task(Box::new(move || task.poll()));
+    }
+
+    // Runs the job on the workers like `execute_detached` and resolves once
+    // every index is done, so async code can farm out CPU work without
+    // blocking a runtime thread. Dropping the future doesn't cancel the job,
+    // which is why `func` has to be `'static`.
+    #[cfg(feature = "tokio")]
+    pub fn execute_blocking_async<F: Fn(usize) + Send + Sync + 'static>(
+        &self,
+        num: usize,
+        func: F,
+    ) -> impl Future<Output = ()> + Send + use<F> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.execute_detached(num, func, move |res| {
+            let _ = tx.send(res);
+        });
+        async move {
+            if let Err(payload) = rx.await.unwrap() {
+                std::panic::resume_unwind(payload);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub fn execute_blocking_async(
+    num: usize,
+    func: impl Fn(usize) + Send + Sync + 'static,
+) -> impl Future<Output = ()> + Send + 'static {
+    global::with_global(|pool| pool.execute_blocking_async(num, func))
+}
@@ -0,0 +1,728 @@
+use std::{
+    any::Any,
+    cell::Cell,
+    cmp::Ordering,
+    collections::HashMap,
+    hash::Hash,
+    iter::Sum,
+    ops::Range,
+    sync::{
+        atomic::{self, AtomicBool, AtomicPtr, AtomicUsize},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    Builder,
+    Checkpoint,
+    Combiner,
+    Dag,
+    Halo,
+    IndexSpace,
+    JobHandle,
+    OrderedStream,
+    Outcome,
+    Pipeline,
+    Pool,
+    PoolConfig,
+    QueueFull,
+    ResultStream,
+    Schedule,
+    Scope,
+    Span,
+    Stages,
+    Team,
+};
+
+lazy_static::lazy_static! {
+    static ref GLOBAL: std::sync::Mutex<Option<Arc<Pool>>> = std::sync::Mutex::new(None);
+    // set by `init_from_config`
+    static ref CONFIG: std::sync::Mutex<PoolConfig> = std::sync::Mutex::new(PoolConfig::default());
+    static ref SEQUENTIAL: AtomicBool = AtomicBool::new(
+        std::env::var("IEU_SEQUENTIAL").is_ok_and(|s| !s.is_empty() && s != "0")
+    );
+}
+
+// set in the child of a fork(), where the global pool's workers don't exist
+static FORKED: AtomicBool = AtomicBool::new(false);
+
+// The pool in `GLOBAL`, for submitters to pick up without taking the lock.
+// Readers count themselves in `READERS` while they take their reference, and
+// whoever takes the pool out of `GLOBAL` clears this and waits for them to
+// leave before dropping it.
+static CURRENT: AtomicPtr<Pool> = AtomicPtr::new(std::ptr::null_mut());
+static READERS: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn env_num_threads() -> Option<usize> {
+    ["IEU_NUM_THREADS", "RAYON_NUM_THREADS"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok()?.parse::<usize>().ok())
+}
+
+// whether `IEU_PHYSICAL_CORES` asks every pool for one pinned worker per
+// physical core, see `Builder::physical_cores`
+pub(crate) fn physical_cores() -> bool {
+    std::env::var("IEU_PHYSICAL_CORES").is_ok_and(|s| !s.is_empty() && s != "0")
+}
+
+// Once the pool exists, submitters get it with a few atomics and jobs from
+// several threads queue up in the pool itself; the lock is only taken to
+// build the pool or to replace it.
+pub(crate) fn with_global<R>(f: impl FnOnce(&Pool) -> R) -> R {
+    let pool = match FORKED.load(atomic::Ordering::SeqCst) {
+        false => current(),
+        true => None,
+    };
+    let pool = pool.unwrap_or_else(|| {
+        let mut global = GLOBAL.lock().unwrap();
+        take_forked(&mut global);
+        global
+            .get_or_insert_with(|| {
+                watch_fork();
+                let config = CONFIG.lock().unwrap().clone();
+                let builder = Builder::new().lazy(true).config(&config).env("IEU");
+                let pool = Arc::new(builder.build());
+                CURRENT.store(Arc::as_ptr(&pool).cast_mut(), atomic::Ordering::SeqCst);
+                pool
+            })
+            .clone()
+    });
+    let _submitting = Submitting(SUBMITTING.replace(Arc::as_ptr(&pool)));
+    f(&pool)
+}
+
+// puts back the pool `SUBMITTING` held before, also when the job panics
+struct Submitting(*const Pool);
+
+impl Drop for Submitting {
+    fn drop(&mut self) {
+        SUBMITTING.set(self.0);
+    }
+}
+
+fn current() -> Option<Arc<Pool>> {
+    READERS.fetch_add(1, atomic::Ordering::SeqCst);
+    let pool = CURRENT.load(atomic::Ordering::SeqCst);
+    // `GLOBAL` keeps the pool alive until this reader has left
+    let pool = (!pool.is_null()).then(|| {
+        unsafe {
+            Arc::increment_strong_count(pool);
+            Arc::from_raw(pool)
+        }
+    });
+    READERS.fetch_sub(1, atomic::Ordering::SeqCst);
+    pool
+}
+
+// Joining threads that only existed in the parent of a fork() would hang, so
+// the old pool is leaked instead, without waiting for readers.
+fn take_forked(global: &mut Option<Arc<Pool>>) {
+    if FORKED.swap(false, atomic::Ordering::SeqCst) {
+        CURRENT.store(std::ptr::null_mut(), atomic::Ordering::SeqCst);
+        std::mem::forget(global.take());
+    }
+}
+
+#[cfg(unix)]
+fn watch_fork() {
+    extern "C" fn child() {
+        FORKED.store(true, atomic::Ordering::SeqCst);
+        // readers caught mid-way by the fork don't exist in the child
+        READERS.store(0, atomic::Ordering::SeqCst);
+    }
+
+    static ONCE: std::sync::Once = std::sync::Once::new();
+    ONCE.call_once(|| unsafe {
+        libc::pthread_atfork(None, None, Some(child));
+    });
+}
+
+#[cfg(not(unix))]
+fn watch_fork() {}
+
+// Re-reads the CPU quota of the process and lets as many workers of the
+// global pool take part in jobs as it allows, see `Pool::refresh_cpu_quota`.
+// Returns that number.
+pub fn refresh_cpu_quota() -> usize {
+    with_global(Pool::refresh_cpu_quota)
+}
+
+// Makes the next call into the global pool build a new one, leaking the old
+// pool without joining it. On unix this happens on its own in the child of a
+// fork(), this is for other ways of ending up with a pool whose workers are
+// gone.
+pub fn reinit_after_fork() {
+    FORKED.store(true, atomic::Ordering::SeqCst);
+}
+
+// Makes every pool run its jobs in index order on the calling thread, for
+// debugging. Starts out enabled when `IEU_SEQUENTIAL` is set to anything but
+// `0`. Spawned tasks still run on the workers.
+pub fn set_sequential(sequential: bool) {
+    SEQUENTIAL.store(sequential, atomic::Ordering::SeqCst);
+}
+
+pub(crate) fn sequential() -> bool {
+    SEQUENTIAL.load(atomic::Ordering::Relaxed)
+}
+
+thread_local! {
+    // the limit of the innermost `with_num_threads` on this thread
+    static NUM_THREADS: Cell<Option<usize>> = const { Cell::new(None) };
+    // the global pool while this thread submits to it through `with_global`
+    static SUBMITTING: Cell<*const Pool> = const { Cell::new(std::ptr::null()) };
+}
+
+// Runs `func` with the jobs it starts on the global pool, directly or in
+// library code it calls, limited to `num_threads` workers (at least one),
+// e.g. to keep a third-party library's parallel section from taking every
+// core. Only jobs started on the calling thread count, and nested calls can
+// only lower the limit.
+pub fn with_num_threads<R>(num_threads: usize, func: impl FnOnce() -> R) -> R {
+    struct Restore(Option<usize>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            NUM_THREADS.set(self.0);
+        }
+    }
+
+    let outer = NUM_THREADS.get();
+    let _restore = Restore(outer);
+    let limit = outer.map_or(num_threads, |outer| outer.min(num_threads));
+    NUM_THREADS.set(Some(limit.max(1)));
+    func()
+}
+
+// the limit `with_num_threads` puts on `pool`'s jobs started from here
+pub(crate) fn num_threads_limit(pool: &Pool) -> Option<usize> {
+    let limit = NUM_THREADS.get()?;
+    std::ptr::eq(pool, SUBMITTING.get()).then_some(limit)
+}
+
+// Stops the global pool, running its queued tasks and joining its workers. A
+// later call into the global pool builds a new one. Jobs still running on the
+// old pool from other threads finish first, the last of them joins it.
+pub fn shutdown_global() {
+    let mut global = GLOBAL.lock().unwrap();
+    take_forked(&mut global);
+    let pool = global.take();
+    CURRENT.store(std::ptr::null_mut(), atomic::Ordering::SeqCst);
+    while READERS.load(atomic::Ordering::SeqCst) != 0 {
+        std::thread::yield_now();
+    }
+    drop(global);
+    drop(pool);
+}
+
+// Builds the global pool from `config` from now on, with the `IEU_*`
+// environment variables still applied over it. A global pool built before
+// is shut down like `shutdown_global`, the next call into the global pool
+// builds the new one.
+pub fn init_from_config(config: &PoolConfig) {
+    *CONFIG.lock().unwrap() = config.clone();
+    shutdown_global();
+}
+
+pub fn execute(num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute(num, func));
+}
+
+pub fn scope_execute<'env>(num: usize, func: impl Fn(usize) + Send + Sync + 'env) {
+    with_global(|pool| pool.scope_execute(num, func));
+}
+
+pub fn par_fill<T: Clone + Send + Sync>(data: &mut [T], value: T) {
+    with_global(|pool| pool.par_fill(data, value));
+}
+
+pub fn par_bridge<T: Send>(iter: impl Iterator<Item = T> + Send, func: impl Fn(T) + Send + Sync) {
+    with_global(|pool| pool.par_bridge(iter, func));
+}
+
+pub fn par_for_each_kv<'a, M, K: Sync + 'a, V: Sync + 'a>(
+    map: &'a M,
+    func: impl Fn(&'a K, &'a V) + Send + Sync,
+) where
+    &'a M: IntoIterator<Item = (&'a K, &'a V)>,
+{
+    with_global(|pool| pool.par_for_each_kv(map, func));
+}
+
+pub fn par_tiles<T: Send>(data: &mut [T], func: impl Fn(usize, &mut [T]) + Send + Sync) {
+    with_global(|pool| pool.par_tiles(data, func));
+}
+
+pub fn first_touch_vec<T: Send>(len: usize, func: impl Fn(usize) -> T + Send + Sync) -> Vec<T> {
+    with_global(|pool| pool.first_touch_vec(len, func))
+}
+
+pub fn par_copy<T: Copy + Send + Sync>(dst: &mut [T], src: &[T]) {
+    with_global(|pool| pool.par_copy(dst, src));
+}
+
+pub fn par_partition<T: Send + Sync>(
+    data: &mut [T],
+    pred: impl Fn(&T) -> bool + Send + Sync,
+) -> usize {
+    with_global(|pool| pool.par_partition(data, pred))
+}
+
+pub fn par_retain<T: Send + Sync>(
+    vec: &mut Vec<T>,
+    pred: impl Fn(&T) -> bool + Send + Sync,
+) -> usize {
+    with_global(|pool| pool.par_retain(vec, pred))
+}
+
+pub fn par_dedup<T: PartialEq + Send + Sync>(vec: &mut Vec<T>) {
+    with_global(|pool| pool.par_dedup(vec));
+}
+
+pub fn vec_from_fn<T: Send>(len: usize, func: impl Fn(usize) -> T + Send + Sync) -> Vec<T> {
+    with_global(|pool| pool.vec_from_fn(len, func))
+}
+
+pub fn execute_into<T: Send>(
+    out: &mut [std::mem::MaybeUninit<T>],
+    func: impl Fn(usize) -> T + Send + Sync,
+) -> &mut [T] {
+    with_global(|pool| pool.execute_into(out, func))
+}
+
+pub fn execute_labeled(label: &str, num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_labeled(label, num, func));
+}
+
+pub fn execute_on(num_threads: usize, num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_on(num_threads, num, func));
+}
+
+pub fn execute_over<S: IndexSpace>(space: S, func: impl Fn(S::Item) + Send + Sync) {
+    with_global(|pool| pool.execute_over(space, func));
+}
+
+pub fn execute_indices(indices: &[usize], func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_indices(indices, func));
+}
+
+pub fn execute_bits(words: &[u64], func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_bits(words, func));
+}
+
+pub fn execute_with_context<T: Send + Sync + 'static>(
+    ctx: T,
+    num: usize,
+    func: impl Fn(usize) + Send + Sync,
+) {
+    with_global(|pool| pool.execute_with_context(ctx, num, func));
+}
+
+pub fn execute_u64(num: u64, func: impl Fn(u64) + Send + Sync) {
+    with_global(|pool| pool.execute_u64(num, func));
+}
+
+pub fn execute_watched(
+    num: usize,
+    threshold: Duration,
+    on_stuck: impl Fn(usize, usize) + Send + Sync,
+    func: impl Fn(usize) + Send + Sync,
+) {
+    with_global(|pool| pool.execute_watched(num, threshold, on_stuck, func));
+}
+
+pub fn execute_weighted_by(
+    num: usize,
+    cost: impl Fn(usize) -> u64 + Send + Sync,
+    func: impl Fn(usize) + Send + Sync,
+) {
+    with_global(|pool| pool.execute_weighted_by(num, cost, func));
+}
+
+pub fn execute_chunked(num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_chunked(num, func));
+}
+
+pub fn execute_aligned(num: usize, align: usize, func: impl Fn(Range<usize>) + Send + Sync) {
+    with_global(|pool| pool.execute_aligned(num, align, func));
+}
+
+pub fn execute_batches(num: usize, batch_size: usize, func: impl Fn(Range<usize>) + Send + Sync) {
+    with_global(|pool| pool.execute_batches(num, batch_size, func));
+}
+
+pub fn calibrate(sample_body: impl Fn(usize)) -> usize {
+    with_global(|pool| pool.calibrate(sample_body))
+}
+
+pub fn execute_weighted(weights: &[u64], func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_weighted(weights, func));
+}
+
+pub fn execute_detached(
+    num: usize,
+    func: impl Fn(usize) + Send + Sync + 'static,
+    on_complete: impl FnOnce(Result<(), Box<dyn Any + Send>>) + Send + 'static,
+) {
+    with_global(|pool| pool.execute_detached(num, func, on_complete));
+}
+
+// Blocks until the global pool has no job running or waiting and every
+// spawned task has run, see `Pool::wait_idle`.
+pub fn wait_idle() {
+    with_global(|pool| pool.wait_idle());
+}
+
+pub fn submit(num: usize, func: impl Fn(usize) + Send + Sync + 'static) -> JobHandle {
+    with_global(|pool| pool.submit(num, func))
+}
+
+pub fn try_submit(
+    num: usize,
+    func: impl Fn(usize) + Send + Sync + 'static,
+) -> Result<JobHandle, QueueFull> {
+    with_global(|pool| pool.try_submit(num, func))
+}
+
+pub fn execute_stream<T: Send + 'static>(
+    num: usize,
+    func: impl Fn(usize) -> T + Send + Sync + 'static,
+) -> ResultStream<T> {
+    with_global(|pool| pool.execute_stream(num, func))
+}
+
+pub fn execute_stream_ordered<T: Send + 'static>(
+    num: usize,
+    func: impl Fn(usize) -> T + Send + Sync + 'static,
+) -> OrderedStream<T> {
+    with_global(|pool| pool.execute_stream_ordered(num, func))
+}
+
+pub fn execute_tiles(
+    nx: usize,
+    ny: usize,
+    tile_w: usize,
+    tile_h: usize,
+    func: impl Fn(Range<usize>, Range<usize>) + Send + Sync,
+) {
+    with_global(|pool| pool.execute_tiles(nx, ny, tile_w, tile_h, func));
+}
+
+pub fn execute_recorded(num: usize, func: impl Fn(usize) + Send + Sync) -> Schedule {
+    with_global(|pool| pool.execute_recorded(num, func))
+}
+
+pub fn execute_replayed(schedule: &Schedule, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_replayed(schedule, func));
+}
+
+pub fn execute_traced(num: usize, func: impl Fn(usize) + Send + Sync) -> Vec<Span> {
+    with_global(|pool| pool.execute_traced(num, func))
+}
+
+pub fn execute_with_progress(
+    num: usize,
+    every: usize,
+    func: impl Fn(usize) + Send + Sync,
+    on_progress: impl Fn(usize, usize) + Send + Sync,
+) {
+    with_global(|pool| pool.execute_with_progress(num, every, func, on_progress));
+}
+
+pub fn execute_factoring(num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_factoring(num, func));
+}
+
+pub fn execute_from(checkpoint: &Checkpoint, num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_from(checkpoint, num, func));
+}
+
+pub fn execute_strided(
+    start: usize,
+    step: usize,
+    count: usize,
+    func: impl Fn(usize) + Send + Sync,
+) {
+    with_global(|pool| pool.execute_strided(start, step, count, func));
+}
+
+pub fn execute_rev(num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_rev(num, func));
+}
+
+pub fn execute_shuffled(num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_shuffled(num, func));
+}
+
+pub fn execute_on_cpus(cpus: &[usize], num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_on_cpus(cpus, num, func));
+}
+
+pub fn execute_sticky(num: usize, func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.execute_sticky(num, func));
+}
+
+pub fn try_for_each<R: Outcome>(num: usize, func: impl Fn(usize) -> R + Send + Sync) -> R {
+    with_global(|pool| pool.try_for_each(num, func))
+}
+
+pub fn try_for_each_counted<R: Outcome>(
+    num: usize,
+    func: impl Fn(usize) -> R + Send + Sync,
+) -> (R, usize) {
+    with_global(|pool| pool.try_for_each_counted(num, func))
+}
+
+pub fn find_map_any<T: Send>(
+    num: usize,
+    func: impl Fn(usize) -> Option<T> + Send + Sync,
+) -> Option<T> {
+    with_global(|pool| pool.find_map_any(num, func))
+}
+
+pub fn find_any(num: usize, pred: impl Fn(usize) -> bool + Send + Sync) -> Option<usize> {
+    with_global(|pool| pool.find_any(num, pred))
+}
+
+pub fn par_binary_search<T: Ord + Sync>(
+    haystack: &[T],
+    needles: &[T],
+) -> Vec<Result<usize, usize>> {
+    with_global(|pool| pool.par_binary_search(haystack, needles))
+}
+
+pub fn par_find_all(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+    with_global(|pool| pool.par_find_all(haystack, needle))
+}
+
+pub fn par_hash_chunks<D: Send>(
+    data: &[u8],
+    chunk_size: usize,
+    hash: impl Fn(&[u8]) -> D + Send + Sync,
+) -> Vec<D> {
+    with_global(|pool| pool.par_hash_chunks(data, chunk_size, hash))
+}
+
+pub fn par_hash_tree<D: Clone + Send + Sync>(
+    data: &[u8],
+    chunk_size: usize,
+    leaf: impl Fn(&[u8]) -> D + Send + Sync,
+    node: impl Fn(&D, &D) -> D + Send + Sync,
+) -> D {
+    with_global(|pool| pool.par_hash_tree(data, chunk_size, leaf, node))
+}
+
+pub fn par_reduce_buffers<T: Clone + Send + Sync>(
+    num: usize,
+    len: usize,
+    identity: T,
+    func: impl Fn(&mut [T], usize) + Send + Sync,
+    combine: impl Fn(&mut T, &T) + Send + Sync,
+) -> Vec<T> {
+    with_global(|pool| pool.par_reduce_buffers(num, len, identity, func, combine))
+}
+
+pub fn par_windows_with_halo<T: Sync, U: Send>(
+    data: &[T],
+    out: &mut [U],
+    chunk: usize,
+    halo: usize,
+    func: impl Fn(Halo<'_, T>, &mut [U]) + Send + Sync,
+) {
+    with_global(|pool| pool.par_windows_with_halo(data, out, chunk, halo, func));
+}
+
+pub fn par_partition_points<T: Sync, N: Sync>(
+    haystack: &[T],
+    needles: &[N],
+    pred: impl Fn(&T, &N) -> bool + Sync,
+) -> Vec<usize> {
+    with_global(|pool| pool.par_partition_points(haystack, needles, pred))
+}
+
+pub fn any(num: usize, pred: impl Fn(usize) -> bool + Send + Sync) -> bool {
+    with_global(|pool| pool.any(num, pred))
+}
+
+pub fn all(num: usize, pred: impl Fn(usize) -> bool + Send + Sync) -> bool {
+    with_global(|pool| pool.all(num, pred))
+}
+
+pub fn par_accumulate<B: Send>(
+    num: usize,
+    init: impl FnMut() -> B,
+    func: impl Fn(&mut B, usize) + Send + Sync,
+    merge: impl Fn(&mut B, B),
+) -> B {
+    with_global(|pool| pool.par_accumulate(num, init, func, merge))
+}
+
+pub fn map_reduce<C: Combiner>(
+    num: usize,
+    map: impl Fn(usize) -> C::Item + Send + Sync,
+    init: impl FnMut() -> C,
+) -> C {
+    with_global(|pool| pool.map_reduce(num, map, init))
+}
+
+pub fn par_collect<C, T>(num: usize, func: impl Fn(usize) -> T + Send + Sync) -> C
+where C: Default + Extend<T> + IntoIterator<Item = T> + Send {
+    with_global(|pool| pool.par_collect(num, func))
+}
+
+pub fn par_extend<C: Extend<T>, T: Send>(
+    target: &mut C,
+    num: usize,
+    func: impl Fn(usize) -> T + Send + Sync,
+) {
+    with_global(|pool| pool.par_extend(target, num, func));
+}
+
+pub fn par_collect_map<K: Eq + Hash + Send, V: Send>(
+    num: usize,
+    func: impl Fn(usize) -> (K, V) + Send + Sync,
+    resolve: impl Fn(V, V) -> V + Send + Sync,
+) -> HashMap<K, V> {
+    with_global(|pool| pool.par_collect_map(num, func, resolve))
+}
+
+pub fn par_histogram(
+    num: usize,
+    buckets: usize,
+    func: impl Fn(usize) -> usize + Send + Sync,
+) -> Vec<u64> {
+    with_global(|pool| pool.par_histogram(num, buckets, func))
+}
+
+pub fn par_tree_reduce<A: Send>(
+    num: usize,
+    map: impl Fn(usize) -> A + Send + Sync,
+    combine: impl Fn(A, A) -> A + Send + Sync,
+) -> Option<A> {
+    with_global(|pool| pool.par_tree_reduce(num, map, combine))
+}
+
+pub fn par_sum_tree<T: Copy + Send + Sync + Sum<T>>(data: &[T]) -> T {
+    with_global(|pool| pool.par_sum_tree(data))
+}
+
+pub fn par_sum<T: Copy + Send + Sync + Sum<T>>(data: &[T]) -> T {
+    with_global(|pool| pool.par_sum(data))
+}
+
+pub fn par_merge<T: Clone + Send + Sync>(
+    runs: &[&[T]],
+    out: &mut [T],
+    cmp: impl Fn(&T, &T) -> Ordering + Sync,
+) {
+    with_global(|pool| pool.par_merge(runs, out, cmp));
+}
+
+pub fn par_min_by<T: Sync>(
+    data: &[T],
+    cmp: impl Fn(&T, &T) -> Ordering + Send + Sync,
+) -> Option<&T> {
+    with_global(|pool| pool.par_min_by(data, cmp))
+}
+
+pub fn par_max_by<T: Sync>(
+    data: &[T],
+    cmp: impl Fn(&T, &T) -> Ordering + Send + Sync,
+) -> Option<&T> {
+    with_global(|pool| pool.par_max_by(data, cmp))
+}
+
+pub fn broadcast(func: impl Fn(&Team) + Send + Sync) {
+    with_global(|pool| pool.broadcast(func));
+}
+
+pub fn broadcast_map<T: Send>(func: impl Fn(usize) -> T + Send + Sync) -> Vec<T> {
+    with_global(|pool| pool.broadcast_map(func))
+}
+
+pub fn spawn_broadcast(func: impl Fn(usize) + Send + Sync + 'static) {
+    with_global(|pool| pool.spawn_broadcast(func));
+}
+
+pub fn warm_up() {
+    with_global(|pool| pool.warm_up());
+}
+
+pub fn warm_up_with(func: impl Fn(usize) + Send + Sync) {
+    with_global(|pool| pool.warm_up_with(func));
+}
+
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    with_global(|pool| pool.join(a, b))
+}
+
+#[cfg(feature = "hdrhistogram")]
+pub fn execute_timed(
+    num: usize,
+    func: impl Fn(usize) + Send + Sync,
+) -> hdrhistogram::Histogram<u64> {
+    with_global(|pool| pool.execute_timed(num, func))
+}
+
+#[cfg(feature = "hdrhistogram")]
+pub fn execute_batches_timed(
+    num: usize,
+    batch_size: usize,
+    func: impl Fn(std::ops::Range<usize>) + Send + Sync,
+) -> hdrhistogram::Histogram<u64> {
+    with_global(|pool| pool.execute_batches_timed(num, batch_size, func))
+}
+
+#[cfg(all(feature = "perf", target_os = "linux"))]
+pub fn execute_perf(num: usize, func: impl Fn(usize) + Send + Sync) -> crate::JobPerf {
+    with_global(|pool| pool.execute_perf(num, func))
+}
+
+#[cfg(feature = "rayon")]
+pub fn install<R: Send>(func: impl FnOnce() -> R + Send) -> R {
+    with_global(|pool| pool.install(func))
+}
+
+pub fn spawn(func: impl FnOnce() + Send + 'static) {
+    with_global(|pool| pool.spawn(func));
+}
+
+pub fn try_spawn(func: impl FnOnce() + Send + 'static) -> Result<(), QueueFull> {
+    with_global(|pool| pool.try_spawn(func))
+}
+
+pub fn spawn_future(future: impl std::future::Future<Output = ()> + Send + 'static) {
+    with_global(|pool| pool.spawn_future(future));
+}
+
+pub fn scope<'scope, R>(func: impl FnOnce(&Scope<'scope>) -> R) -> R {
+    with_global(|pool| pool.scope(func))
+}
+
+pub fn in_place_scope<'scope, R>(func: impl FnOnce(&Scope<'scope>) -> R) -> R {
+    with_global(|pool| pool.in_place_scope(func))
+}
+
+pub fn pipeline<'env, R>(func: impl FnOnce(&Pipeline<'env>) -> R) -> R {
+    with_global(|pool| pool.pipeline(func))
+}
+
+// like `Pool::dag`, the jobs run on the global pool
+pub fn dag<'a>() -> Dag<'a> {
+    Dag::new(None)
+}
+
+// like `Pool::stages`, the stages run on the global pool
+pub fn stages<'a, T: Send + 'static>(
+    capacity: usize,
+    source: impl Iterator<Item = T> + Send + 'a,
+) -> Stages<'a, T> {
+    Stages::new(None, capacity, source)
+}
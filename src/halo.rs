@@ -0,0 +1,69 @@
+use crate::{iter::SyncPtr, Pool};
+
+/// A chunk of the input of `Pool::par_windows_with_halo` with the elements
+/// around it its kernel may read, up to `halo` on either side and fewer at
+/// the ends of the input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Halo<'a, T> {
+    /// Where `chunk` starts in the input.
+    pub start:  usize,
+    pub before: &'a [T],
+    pub chunk:  &'a [T],
+    pub after:  &'a [T],
+}
+
+impl<'a, T> Halo<'a, T> {
+    /// The element `offset` places from the start of the chunk, negative in
+    /// the halo before it, or `None` outside the halo.
+    pub fn get(&self, offset: isize) -> Option<&'a T> {
+        match offset < 0 {
+            true => {
+                let back = offset.unsigned_abs();
+                self.before.len().checked_sub(back).map(|i| &self.before[i])
+            },
+            false => {
+                let i = offset as usize;
+                self.chunk
+                    .get(i)
+                    .or_else(|| self.after.get(i - self.chunk.len()))
+            },
+        }
+    }
+}
+
+impl Pool {
+    // Runs a stencil over `data` a chunk at a time: `func` gets every
+    // `chunk` elements of `data` with up to `halo` of their neighbours on
+    // either side, and the same range of `out` to write. For rows of an
+    // image or grid, `chunk` and `halo` are multiples of the row length.
+    // Panics if `out` isn't as long as `data`; a `chunk` of 0 counts as 1.
+    pub fn par_windows_with_halo<T: Sync, U: Send>(
+        &self,
+        data: &[T],
+        out: &mut [U],
+        chunk: usize,
+        halo: usize,
+        func: impl Fn(Halo<'_, T>, &mut [U]) + Send + Sync,
+    ) {
+        assert_eq!(
+            out.len(),
+            data.len(),
+            "the output must be as long as the data"
+        );
+        let (len, chunk) = (data.len(), chunk.max(1));
+        let ptr = SyncPtr(out.as_mut_ptr());
+        self.run(len.div_ceil(chunk), |_, i| {
+            let (start, end) = (i * chunk, (i * chunk + chunk).min(len));
+            let window = Halo {
+                start,
+                before: &data[start.saturating_sub(halo)..start],
+                chunk: &data[start..end],
+                after: &data[end..end.saturating_add(halo).min(len)],
+            };
+            // every chunk writes its own range of `out`
+            func(window, unsafe {
+                std::slice::from_raw_parts_mut(ptr.get().add(start), end - start)
+            });
+        });
+    }
+}
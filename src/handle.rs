@@ -0,0 +1,45 @@
+use std::{ops::Deref, sync::Arc};
+
+use crate::Pool;
+
+/// A shared reference to a pool that is cheap to clone and can be handed to
+/// every subsystem of an application, all of them submitting to the same
+/// workers. It dereferences to the `Pool`, which shuts down once the last
+/// handle is dropped.
+#[derive(Clone, Debug)]
+pub struct PoolHandle {
+    pool: Arc<Pool>,
+}
+
+impl PoolHandle {
+    pub fn new(pool: Pool) -> PoolHandle {
+        PoolHandle {
+            pool: Arc::new(pool),
+        }
+    }
+
+    /// Whether both handles refer to the same pool.
+    pub fn ptr_eq(&self, other: &PoolHandle) -> bool {
+        Arc::ptr_eq(&self.pool, &other.pool)
+    }
+}
+
+impl Deref for PoolHandle {
+    type Target = Pool;
+
+    fn deref(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+impl From<Pool> for PoolHandle {
+    fn from(pool: Pool) -> PoolHandle {
+        PoolHandle::new(pool)
+    }
+}
+
+impl Pool {
+    pub fn into_handle(self) -> PoolHandle {
+        PoolHandle::new(self)
+    }
+}
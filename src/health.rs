@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+use crate::Pool;
+
+/// What a job was up to when its submitter had been waiting on it for
+/// longer than the timeout of `Builder::on_hang`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hang {
+    /// The label of `Pool::execute_labeled` jobs.
+    pub label:      Option<String>,
+    pub waited:     Duration,
+    pub num:        usize,
+    /// Indices claimed so far; they all ran unless they show up in
+    /// `running`.
+    pub handed_out: usize,
+    /// The workers in the middle of an index, with that index.
+    pub running:    Vec<(usize, usize)>,
+    /// The workers that ran indices of the job and aren't running one now.
+    pub finished:   Vec<usize>,
+}
+
+// Sets the flag behind the mutex and notifies the condvar when dropped, so
+// the watchdog of `execute_watched` stops even when the job panics.
+struct Notify<'a>(&'a (std::sync::Mutex<bool>, std::sync::Condvar));
+
+impl Drop for Notify<'_> {
+    fn drop(&mut self) {
+        *self.0 .0.lock().unwrap_or_else(|err| err.into_inner()) = true;
+        self.0 .1.notify_all();
+    }
+}
+
+impl Pool {
+    // Wakes every live worker and returns the indices of those that didn't
+    // check in within `timeout`. A worker busy with a long task or job
+    // counts as unresponsive, as does one whose thread is stuck or gone.
+    pub fn unresponsive_workers(&self, timeout: Duration) -> Vec<usize> {
+        let (ping, mut pending) = self.inner.ping();
+        let start = Instant::now();
+        loop {
+            pending.retain(|&index| !self.inner.answered(index, ping));
+            if pending.is_empty() || start.elapsed() >= timeout {
+                return pending;
+            }
+            std::thread::sleep(Duration::from_micros(100));
+        }
+    }
+
+    // whether every live worker answers within `timeout`, e.g. for a
+    // service's health probe
+    pub fn is_healthy(&self, timeout: Duration) -> bool {
+        self.unresponsive_workers(timeout).is_empty()
+    }
+
+    // Like `execute`, with a watchdog thread that calls `on_stuck(i, worker)`
+    // once for every index `i` still running after `threshold`, e.g. to log
+    // the pathological input that hangs a job. It only reports, the index
+    // keeps running and the job still waits for it.
+    pub fn execute_watched(
+        &self,
+        num: usize,
+        threshold: Duration,
+        on_stuck: impl Fn(usize, usize) + Send + Sync,
+        func: impl Fn(usize) + Send + Sync,
+    ) {
+        // the index every worker is running, since when, and whether it was
+        // reported already
+        let running = (0..self.num_threads().max(1))
+            .map(|_| std::sync::Mutex::new(None::<(usize, Instant, bool)>))
+            .collect::<Vec<_>>();
+        let done = (std::sync::Mutex::new(false), std::sync::Condvar::new());
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let (lock, cvar) = &done;
+                let mut finished = lock.lock().unwrap();
+                while !*finished {
+                    for (worker, running) in running.iter().enumerate() {
+                        if let Some((i, start, reported)) = &mut *running.lock().unwrap()
+                            && !*reported
+                            && start.elapsed() >= threshold
+                        {
+                            *reported = true;
+                            on_stuck(*i, worker);
+                        }
+                    }
+                    let tick = (threshold / 4).max(Duration::from_millis(1));
+                    finished = cvar.wait_timeout(finished, tick).unwrap().0;
+                }
+            });
+            let _done = Notify(&done);
+            self.run(num, |worker, i| {
+                *running[worker].lock().unwrap() = Some((i, Instant::now(), false));
+                func(i);
+                *running[worker].lock().unwrap() = None;
+            });
+        });
+    }
+}
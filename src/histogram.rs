@@ -0,0 +1,46 @@
+use std::{ops::Range, time::Instant};
+
+use hdrhistogram::Histogram;
+
+use crate::{local::PerWorker, Pool};
+
+impl Pool {
+    // like `execute`, recording how long every index took in nanoseconds, to
+    // find the p99 or p999 of the processing time and the inputs behind it.
+    // Every worker records into its own histogram, they're merged after the
+    // job.
+    pub fn execute_timed(&self, num: usize, func: impl Fn(usize) + Send + Sync) -> Histogram<u64> {
+        self.timed(num, func)
+    }
+
+    // like `execute_batches`, recording how long every batch took
+    pub fn execute_batches_timed(
+        &self,
+        num: usize,
+        batch_size: usize,
+        func: impl Fn(Range<usize>) + Send + Sync,
+    ) -> Histogram<u64> {
+        let batch_size = batch_size.max(1);
+        self.timed(num.div_ceil(batch_size), |batch| {
+            func(batch * batch_size..((batch + 1) * batch_size).min(num))
+        })
+    }
+
+    fn timed(&self, num: usize, func: impl Fn(usize) + Send + Sync) -> Histogram<u64> {
+        // auto-resizing with 3 significant figures, so neither creating them
+        // nor recording into them fails
+        let new = || Histogram::new(3).unwrap();
+        let histograms = PerWorker::new(self.num_threads(), new);
+        self.run(num, |worker, i| {
+            let start = Instant::now();
+            func(i);
+            let nanos = start.elapsed().as_nanos() as u64;
+            unsafe { histograms.get(worker) }.record(nanos).unwrap();
+        });
+        let mut merged = new();
+        for histogram in histograms.into_inner() {
+            merged.add(histogram).unwrap();
+        }
+        merged
+    }
+}
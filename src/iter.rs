@@ -0,0 +1,173 @@
+use std::{mem::MaybeUninit, ops::Range};
+
+use crate::{global, Pool};
+
+/// A random-access parallel iterator running on the global pool. Every index
+/// in `0..len()` is produced independently by `get` on whichever worker claims
+/// it.
+pub trait ParIter: Sized + Sync {
+    type Item;
+
+    fn len(&self) -> usize;
+
+    fn get(&self, index: usize) -> Self::Item;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn for_each(self, func: impl Fn(Self::Item) + Send + Sync) {
+        crate::execute(self.len(), |i| func(self.get(i)));
+    }
+
+    fn map<R, F: Fn(Self::Item) -> R + Send + Sync>(self, func: F) -> Map<Self, F> {
+        Map { iter: self, func }
+    }
+
+    /// Collects the items in index order.
+    fn collect<C: FromIterator<Self::Item>>(self) -> C
+    where Self::Item: Send {
+        global::with_global(|pool| pool.vec_from_fn(self.len(), |i| self.get(i)))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Collections that can be extended with the items of a `ParIter`, computed
+/// on the global pool and appended in index order. Every `Extend` collection
+/// is one, see `Pool::par_extend`.
+pub trait ParExtend<T> {
+    fn par_extend<I: ParIter<Item = T>>(&mut self, iter: I);
+}
+
+impl<T: Send, C: Extend<T>> ParExtend<T> for C {
+    fn par_extend<I: ParIter<Item = T>>(&mut self, iter: I) {
+        global::with_global(|pool| pool.par_extend(self, iter.len(), |i| iter.get(i)));
+    }
+}
+
+pub trait IntoParIter {
+    type Iter: ParIter;
+
+    fn par_iter(self) -> Self::Iter;
+
+    fn par_for_each(self, func: impl Fn(<Self::Iter as ParIter>::Item) + Send + Sync)
+    where Self: Sized {
+        self.par_iter().for_each(func);
+    }
+}
+
+pub struct RangeIter {
+    range: Range<usize>,
+}
+
+pub struct SliceIter<'a, T> {
+    slice: &'a [T],
+}
+
+pub struct Map<I, F> {
+    iter: I,
+    func: F,
+}
+
+impl IntoParIter for Range<usize> {
+    type Iter = RangeIter;
+
+    fn par_iter(self) -> RangeIter {
+        RangeIter { range: self }
+    }
+}
+
+impl<'a, T: Sync> IntoParIter for &'a [T] {
+    type Iter = SliceIter<'a, T>;
+
+    fn par_iter(self) -> SliceIter<'a, T> {
+        SliceIter { slice: self }
+    }
+}
+
+impl<'a, T: Sync> IntoParIter for &'a Vec<T> {
+    type Iter = SliceIter<'a, T>;
+
+    fn par_iter(self) -> SliceIter<'a, T> {
+        SliceIter { slice: self }
+    }
+}
+
+impl ParIter for RangeIter {
+    type Item = usize;
+
+    fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    fn get(&self, index: usize) -> usize {
+        self.range.start + index
+    }
+}
+
+impl<'a, T: Sync> ParIter for SliceIter<'a, T> {
+    type Item = &'a T;
+
+    fn len(&self) -> usize {
+        self.slice.len()
+    }
+
+    fn get(&self, index: usize) -> &'a T {
+        &self.slice[index]
+    }
+}
+
+impl<I: ParIter, R, F: Fn(I::Item) -> R + Send + Sync> ParIter for Map<I, F> {
+    type Item = R;
+
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+
+    fn get(&self, index: usize) -> R {
+        (self.func)(self.iter.get(index))
+    }
+}
+
+pub(crate) struct SyncPtr<T>(pub(crate) *mut T);
+
+unsafe impl<T: Send> Send for SyncPtr<T> {}
+unsafe impl<T: Send> Sync for SyncPtr<T> {}
+
+impl<T> SyncPtr<T> {
+    pub(crate) fn get(&self) -> *mut T {
+        self.0
+    }
+}
+
+impl Pool {
+    // builds `vec![func(0), func(1), ..]` with a single allocation, computing
+    // the elements in parallel
+    pub fn vec_from_fn<T: Send>(
+        &self,
+        len: usize,
+        func: impl Fn(usize) -> T + Send + Sync,
+    ) -> Vec<T> {
+        let mut out = Vec::with_capacity(len);
+        self.execute_into(&mut out.spare_capacity_mut()[..len], func);
+        unsafe { out.set_len(len) };
+        out
+    }
+
+    // fills every slot of `out` with `func(i)` and hands the buffer back
+    // initialized, without allocating; if `func` panics the values written so
+    // far are leaked
+    pub fn execute_into<'a, T: Send>(
+        &self,
+        out: &'a mut [MaybeUninit<T>],
+        func: impl Fn(usize) -> T + Send + Sync,
+    ) -> &'a mut [T] {
+        let ptr = SyncPtr(out.as_mut_ptr());
+        // every index writes its own slot exactly once
+        self.run(out.len(), |_, i| {
+            unsafe { ptr.get().add(i).write(MaybeUninit::new(func(i))) };
+        });
+        unsafe { &mut *(out as *mut [MaybeUninit<T>] as *mut [T]) }
+    }
+}
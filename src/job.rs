@@ -0,0 +1,232 @@
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    sync::{Arc, AtomicBool, AtomicUsize, Condvar, Mutex, Ordering},
+    Pool,
+    QueueFull,
+};
+
+/// How a job started with `Pool::submit` ended.
+pub enum JobResult {
+    /// Every index ran.
+    Completed,
+    /// The job was aborted before every index was handed out, the rest never
+    /// ran.
+    Aborted,
+    /// An index panicked, no further indices were handed out. Holds the
+    /// panic payload.
+    Panicked(Box<dyn Any + Send>),
+}
+
+impl fmt::Debug for JobResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobResult::Completed => f.write_str("Completed"),
+            JobResult::Aborted => f.write_str("Aborted"),
+            JobResult::Panicked(_) => f.write_str("Panicked(..)"),
+        }
+    }
+}
+
+/// A job running in the background, returned by `Pool::submit`. Dropping
+/// the handle leaves the job running, unless it was made to abort with
+/// `abort_on_drop`.
+pub struct JobHandle {
+    job:           Arc<Job>,
+    abort_on_drop: bool,
+}
+
+/// Aborts a submitted job from anywhere, see `JobHandle::abort_handle`.
+#[derive(Clone)]
+pub struct AbortHandle {
+    job: Arc<Job>,
+}
+
+struct Job {
+    func:     Box<dyn Fn(usize) + Send + Sync>,
+    num:      usize,
+    next:     AtomicUsize,
+    // indices that ran or were skipped by an abort
+    done:     AtomicUsize,
+    // indices that ran without panicking, for `progress`
+    ran:      AtomicUsize,
+    // set once an abort skipped at least one index
+    aborted:  AtomicBool,
+    panic:    Mutex<Option<Box<dyn Any + Send>>>,
+    result:   Mutex<Option<JobResult>>,
+    finished: Condvar,
+}
+
+impl Job {
+    // run by one task per worker taking part in the job
+    fn run(&self) {
+        loop {
+            let i = self.next.fetch_add(1, Ordering::SeqCst);
+            if i >= self.num {
+                break;
+            }
+            match panic::catch_unwind(AssertUnwindSafe(|| (self.func)(i))) {
+                Ok(()) => {
+                    self.ran.fetch_add(1, Ordering::SeqCst);
+                },
+                Err(payload) => {
+                    self.panic.lock().unwrap().get_or_insert(payload);
+                    self.skip_rest(false);
+                },
+            }
+            self.complete(1);
+        }
+    }
+
+    // stops handing out indices, the ones not handed out yet count as done;
+    // returns how many were handed out
+    fn skip_rest(&self, abort: bool) -> usize {
+        let handed_out = self.next.fetch_max(self.num, Ordering::SeqCst);
+        let skipped = self.num.saturating_sub(handed_out);
+        if skipped > 0 {
+            // the skipped indices keep the job from ending until they're
+            // counted, so whoever ends it sees the flag
+            if abort {
+                self.aborted.store(true, Ordering::SeqCst);
+            }
+            self.complete(skipped);
+        }
+        handed_out.min(self.num)
+    }
+
+    fn complete(&self, count: usize) {
+        if self.done.fetch_add(count, Ordering::SeqCst) + count != self.num {
+            return;
+        }
+        let result = match self.panic.lock().unwrap().take() {
+            Some(payload) => JobResult::Panicked(payload),
+            None if self.aborted.load(Ordering::SeqCst) => JobResult::Aborted,
+            None => JobResult::Completed,
+        };
+        *self.result.lock().unwrap() = Some(result);
+        self.finished.notify_all();
+    }
+}
+
+impl JobHandle {
+    /// Waits for the job to end and returns how it ended. Once this returns,
+    /// no index of the job is running anymore.
+    pub fn wait(self) -> JobResult {
+        let mut result = self.job.result.lock().unwrap();
+        loop {
+            match result.take() {
+                Some(result) => return result,
+                None => result = self.job.finished.wait(result).unwrap(),
+            }
+        }
+    }
+
+    /// Whether the job has ended, i.e. whether `wait` would return right away.
+    pub fn is_finished(&self) -> bool {
+        self.job.result.lock().unwrap().is_some()
+    }
+
+    /// How many indices have run so far, out of how many the job has, e.g. to
+    /// redraw a progress bar by polling. Only indices that returned count as
+    /// run, the ones that panicked or were skipped by an abort or after a
+    /// panic don't.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.job.ran.load(Ordering::SeqCst), self.job.num)
+    }
+
+    /// Makes dropping the handle without `wait` abort the job and wait for
+    /// the indices that are still running, so a scope that returns early or
+    /// unwinds doesn't leave the job running against state it freed. Don't
+    /// drop such a handle from inside the job.
+    pub fn abort_on_drop(mut self) -> JobHandle {
+        self.abort_on_drop = true;
+        self
+    }
+
+    /// A handle that aborts the job, which can be sent to another thread.
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle {
+            job: self.job.clone(),
+        }
+    }
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        if !self.abort_on_drop {
+            return;
+        }
+        self.job.skip_rest(true);
+        // the skipped indices are counted right away, the rest as they finish
+        let mut result = self.job.result.lock().unwrap();
+        while self.job.done.load(Ordering::SeqCst) < self.job.num {
+            result = self.job.finished.wait(result).unwrap();
+        }
+    }
+}
+
+impl AbortHandle {
+    /// Stops handing out the job's indices. Indices that are already running
+    /// finish, after which the job ends with `JobResult::Aborted`. Aborting a
+    /// job that has handed out every index already does nothing.
+    ///
+    /// Returns how many indices were handed out. They are handed out in
+    /// order, so the indices that ran (or are still finishing) are exactly
+    /// `0..count`.
+    pub fn abort(&self) -> usize {
+        self.job.skip_rest(true)
+    }
+}
+
+impl Pool {
+    // Starts running `func` over `0..num` on the workers and returns right
+    // away with a handle to wait for the job or abort it. Like
+    // `execute_detached`, the indices are claimed through spawned tasks.
+    pub fn submit(&self, num: usize, func: impl Fn(usize) + Send + Sync + 'static) -> JobHandle {
+        self.try_submit(num, func)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    // `submit`, failing instead of panicking when the queue is full and the
+    // pool rejects tasks then. Only the first of the job's tasks needs room,
+    // the ones that help it along are left out of a full queue.
+    pub fn try_submit(
+        &self,
+        num: usize,
+        func: impl Fn(usize) + Send + Sync + 'static,
+    ) -> Result<JobHandle, QueueFull> {
+        let job = Arc::new(Job {
+            func: Box::new(func),
+            num,
+            next: AtomicUsize::new(0),
+            done: AtomicUsize::new(0),
+            ran: AtomicUsize::new(0),
+            aborted: AtomicBool::new(false),
+            panic: Mutex::new(None),
+            result: Mutex::new((num == 0).then_some(JobResult::Completed)),
+            finished: Condvar::new(),
+        });
+        let tasks = match num {
+            0 => 0,
+            num => self.num_threads().clamp(1, num),
+        };
+        let on_worker = self.worker_index().is_some();
+        for k in 0..tasks {
+            let job = job.clone();
+            let task = Box::new(move || job.run());
+            if k == 0 {
+                self.inner.queue_task(task, on_worker)?;
+            } else if self.inner.try_push_task(task).is_err() {
+                break;
+            }
+        }
+        Ok(JobHandle {
+            job,
+            abort_on_drop: false,
+        })
+    }
+}
@@ -1,183 +1,678 @@
-use std::pin::Pin;
-#[cfg(not(loom))]
-use std::{
-    sync::{
-        atomic::{AtomicPtr, AtomicUsize, Ordering},
-        Condvar,
-        Mutex,
-    },
-    thread,
-};
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
-#[cfg(loom)]
-use loom::{
-    sync::{
-        atomic::{AtomicPtr, AtomicUsize, Ordering},
-        Condvar,
-        Mutex,
-    },
-    thread,
+#[cfg(feature = "std")]
+mod admission;
+#[cfg(feature = "std")]
+mod autoscale;
+#[cfg(feature = "std")]
+mod bridge;
+#[cfg(feature = "std")]
+mod broadcast;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "chaos")]
+mod chaos;
+#[cfg(feature = "std")]
+mod config;
+#[cfg(feature = "std")]
+mod context;
+#[cfg(feature = "std")]
+mod cpu;
+#[cfg(feature = "std")]
+mod dag;
+#[cfg(feature = "std")]
+mod digest;
+mod dispatch;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+mod fill;
+#[cfg(feature = "std")]
+mod future;
+#[cfg(feature = "std")]
+mod global;
+#[cfg(feature = "std")]
+mod halo;
+#[cfg(feature = "std")]
+mod handle;
+#[cfg(feature = "std")]
+mod health;
+#[cfg(feature = "hdrhistogram")]
+mod histogram;
+#[cfg(feature = "std")]
+mod iter;
+#[cfg(feature = "std")]
+mod job;
+#[cfg(feature = "std")]
+mod local;
+#[cfg(feature = "std")]
+mod macros;
+#[cfg(feature = "std")]
+mod merge;
+#[cfg(feature = "std")]
+mod ordered;
+#[cfg(feature = "std")]
+mod partition;
+#[cfg(all(feature = "perf", target_os = "linux"))]
+mod perf;
+#[cfg(feature = "std")]
+mod pipeline;
+#[cfg(feature = "std")]
+mod pool;
+#[cfg(feature = "puffin")]
+mod profile;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+mod queue;
+#[cfg(feature = "std")]
+mod quota;
+pub mod raw;
+#[cfg(feature = "std")]
+mod reduce;
+#[cfg(feature = "std")]
+mod replay;
+mod rng;
+#[cfg(feature = "std")]
+mod schedule;
+#[cfg(feature = "std")]
+mod scope;
+#[cfg(feature = "std")]
+mod search;
+#[cfg(feature = "std")]
+mod signal;
+#[cfg(feature = "std")]
+mod space;
+#[cfg(feature = "std")]
+mod split;
+#[cfg(feature = "std")]
+mod stages;
+#[cfg(feature = "std")]
+mod static_pool;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod stream;
+mod sync;
+#[cfg(feature = "std")]
+mod sys;
+#[cfg(feature = "hwloc")]
+mod topology;
+#[cfg(feature = "std")]
+mod trace;
+#[cfg(all(
+    feature = "wasm-threads",
+    target_arch = "wasm32",
+    target_feature = "atomics"
+))]
+mod wasm;
+
+#[cfg(feature = "std")]
+pub use broadcast::Team;
+#[cfg(feature = "std")]
+pub use builder::{Builder, ThreadPriority};
+#[cfg(feature = "std")]
+pub use config::PoolConfig;
+#[cfg(feature = "std")]
+pub use context::{current_job_context, ContextPool};
+#[cfg(feature = "std")]
+pub use dag::{Dag, JobId};
+#[cfg(feature = "tokio")]
+pub use future::execute_blocking_async;
+#[cfg(all(feature = "perf", target_os = "linux"))]
+pub use global::execute_perf;
+#[cfg(feature = "rayon")]
+pub use global::install;
+#[cfg(feature = "std")]
+pub use global::{
+    all,
+    any,
+    broadcast,
+    broadcast_map,
+    calibrate,
+    dag,
+    execute,
+    execute_aligned,
+    execute_batches,
+    execute_bits,
+    execute_chunked,
+    execute_detached,
+    execute_factoring,
+    execute_from,
+    execute_indices,
+    execute_into,
+    execute_labeled,
+    execute_on,
+    execute_on_cpus,
+    execute_over,
+    execute_recorded,
+    execute_replayed,
+    execute_rev,
+    execute_shuffled,
+    execute_sticky,
+    execute_stream,
+    execute_stream_ordered,
+    execute_strided,
+    execute_tiles,
+    execute_traced,
+    execute_u64,
+    execute_watched,
+    execute_weighted,
+    execute_weighted_by,
+    execute_with_context,
+    execute_with_progress,
+    find_any,
+    find_map_any,
+    first_touch_vec,
+    in_place_scope,
+    init_from_config,
+    join,
+    map_reduce,
+    par_accumulate,
+    par_binary_search,
+    par_bridge,
+    par_collect,
+    par_collect_map,
+    par_copy,
+    par_dedup,
+    par_extend,
+    par_fill,
+    par_find_all,
+    par_for_each_kv,
+    par_hash_chunks,
+    par_hash_tree,
+    par_histogram,
+    par_max_by,
+    par_merge,
+    par_min_by,
+    par_partition,
+    par_partition_points,
+    par_reduce_buffers,
+    par_retain,
+    par_sum,
+    par_sum_tree,
+    par_tiles,
+    par_tree_reduce,
+    par_windows_with_halo,
+    pipeline,
+    refresh_cpu_quota,
+    reinit_after_fork,
+    scope,
+    scope_execute,
+    set_sequential,
+    shutdown_global,
+    spawn,
+    spawn_broadcast,
+    spawn_future,
+    stages,
+    submit,
+    try_for_each,
+    try_for_each_counted,
+    try_spawn,
+    try_submit,
+    vec_from_fn,
+    wait_idle,
+    warm_up,
+    warm_up_with,
+    with_num_threads,
 };
+#[cfg(feature = "hdrhistogram")]
+pub use global::{execute_batches_timed, execute_timed};
+#[cfg(feature = "std")]
+pub use halo::Halo;
+#[cfg(feature = "std")]
+pub use handle::PoolHandle;
+#[cfg(feature = "std")]
+pub use health::Hang;
+#[cfg(feature = "std")]
+pub use iter::{IntoParIter, Map, ParExtend, ParIter, RangeIter, SliceIter};
+#[cfg(feature = "std")]
+pub use job::{AbortHandle, JobHandle, JobResult};
+#[cfg(feature = "std")]
+pub use ordered::Ordered;
+#[cfg(all(feature = "perf", target_os = "linux"))]
+pub use perf::{JobPerf, PerfCounts};
+#[cfg(feature = "std")]
+pub use pipeline::Pipeline;
+#[cfg(feature = "std")]
+pub use pool::{is_worker_thread, Pool};
+#[cfg(feature = "std")]
+pub use queue::{QueueFull, QueuePolicy};
+pub use raw::{RawPool, ThreadProvider};
+#[cfg(feature = "std")]
+pub use reduce::Combiner;
+#[cfg(feature = "std")]
+pub use replay::Schedule;
+pub use rng::{rng_for_index, IndexRng};
+#[cfg(feature = "std")]
+pub use schedule::Checkpoint;
+#[cfg(feature = "std")]
+pub use scope::Scope;
+#[cfg(feature = "std")]
+pub use search::Outcome;
+#[cfg(feature = "std")]
+pub use space::{Bits, IndexSpace, Pairs, Product};
+#[cfg(feature = "std")]
+pub use split::SubPool;
+#[cfg(feature = "std")]
+pub use stages::Stages;
+#[cfg(feature = "std")]
+pub use static_pool::StaticPool;
+#[cfg(feature = "std")]
+pub use stats::Stats;
+#[cfg(feature = "std")]
+pub use stream::{OrderedStream, ResultStream};
+#[cfg(feature = "hwloc")]
+pub use topology::{Cpu, Placement, Topology};
+#[cfg(feature = "std")]
+pub use trace::Span;
+#[cfg(all(
+    feature = "wasm-threads",
+    target_arch = "wasm32",
+    target_feature = "atomics"
+))]
+pub use wasm::{WebThread, WebWorkers};
 
-pub struct Pool {
-    threads: Vec<thread::Thread>,
-    inner:   Pin<Box<PoolInner>>,
-}
+#[cfg(all(
+    feature = "wasm-threads",
+    target_arch = "wasm32",
+    not(target_feature = "atomics")
+))]
+compile_error!("the `wasm-threads` feature needs the `atomics` target feature");
 
-struct PoolInner {
-    func:        AtomicPtr<Box<dyn Fn(usize) + Send + Sync>>,
-    max:         AtomicUsize,
-    cnt:         AtomicUsize,
-    finished:    AtomicUsize,
-    notif_mutex: Mutex<bool>,
-    notif_var:   Condvar,
-    lock_mutex:  Mutex<()>,
-}
+#[cfg(all(test, feature = "std", not(loom)))]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::sync::{AtomicUsize, Ordering};
+
+    fn alive(pool: &Pool) -> usize {
+        pool.inner
+            .workers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|w| w.alive)
+            .count()
+    }
+
+    #[test]
+    fn test_pool() {
+        let pool = Pool::new(4);
+        let cnt = AtomicUsize::new(0);
+        pool.execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        pool.execute(20, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        pool.execute(50, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 80);
+        drop(pool);
+    }
+
+    #[test]
+    fn test_shutdown_global() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send(()).unwrap();
+        });
+        shutdown_global();
+        // queued tasks still run, other tests may keep the old pool alive a
+        // little longer so this can't check that it happened right away
+        rx.recv().unwrap();
+        let cnt = AtomicUsize::new(0);
+        execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.into_inner(), 10);
+    }
 
-impl Pool {
-    pub fn new(size: usize) -> Pool {
-        let threads = Vec::with_capacity(size);
-        let mut pool = Pool {
-            threads,
-            inner: Box::pin(PoolInner {
-                func:        AtomicPtr::new(std::ptr::null_mut()),
-                max:         AtomicUsize::new(0),
-                cnt:         AtomicUsize::new(0),
-                finished:    AtomicUsize::new(0),
-                notif_mutex: Mutex::new(false),
-                notif_var:   Condvar::new(),
-                lock_mutex:  Mutex::new(()),
-            }),
+    #[test]
+    fn test_pool_config() {
+        let config = PoolConfig {
+            threads: Some(3),
+            stack_size: Some(1 << 20),
+            priority: Some(ThreadPriority::Nice(5)),
+            max_queued_tasks: Some(8),
+            ..PoolConfig::default()
+        };
+        let pool = Pool::from_config(&config).unwrap();
+        assert_eq!(pool.num_threads(), 3);
+        let cnt = AtomicUsize::new(0);
+        pool.execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 10);
+        // settings the config leaves out keep what the builder had
+        let pool = Pool::builder()
+            .num_threads(2)
+            .config(&PoolConfig::default())
+            .build();
+        assert_eq!(pool.num_threads(), 2);
+        // a policy has nothing to apply to without a queue limit
+        let policy_only = PoolConfig {
+            queue_policy: Some(QueuePolicy::Reject),
+            ..PoolConfig::default()
         };
-        let ptr = &*pool.inner.as_ref() as *const _ as usize;
-        for _ in 0..size {
-            pool.threads.push(
+        let err = Pool::from_config(&policy_only).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let builder = Pool::builder()
+            .max_queued_tasks(4, QueuePolicy::Block)
+            .config(&policy_only);
+        assert_eq!(builder.queue_limit, Some((4, QueuePolicy::Reject)));
+        #[cfg(feature = "serde")]
+        {
+            let parsed = toml::from_str::<PoolConfig>(
+                "threads = 3\nstack_size = 1048576\npriority = \"nice:5\"\nmax_queued_tasks = 8",
+            )
+            .unwrap();
+            assert_eq!(parsed, config);
+            let text = toml::to_string(&config).unwrap();
+            assert_eq!(toml::from_str::<PoolConfig>(&text).unwrap(), config);
+            assert!(toml::from_str::<PoolConfig>("threds = 3").is_err());
+            assert!(toml::from_str::<PoolConfig>("queue_policy = \"sometimes\"").is_err());
+        }
+        // the global pool is rebuilt from the config, other tests can only
+        // see another size
+        let threads = global::with_global(Pool::num_threads);
+        init_from_config(&PoolConfig {
+            threads: Some(threads + 1),
+            ..PoolConfig::default()
+        });
+        assert_eq!(global::with_global(Pool::num_threads), threads + 1);
+        init_from_config(&PoolConfig::default());
+        assert_eq!(global::with_global(Pool::num_threads), threads);
+    }
+
+    #[test]
+    fn test_global_submitters() {
+        let cnt = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..200 {
+                        execute(3, |_| {
+                            cnt.fetch_add(1, Ordering::SeqCst);
+                        });
+                    }
+                });
+            }
+            // submitters holding the old pool finish their jobs on it
+            for _ in 0..5 {
+                thread::sleep(Duration::from_millis(2));
+                shutdown_global();
+            }
+        });
+        assert_eq!(cnt.into_inner(), 4 * 200 * 3);
+    }
+
+    #[test]
+    fn test_reinit_after_fork() {
+        reinit_after_fork();
+        let cnt = AtomicUsize::new(0);
+        execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.into_inner(), 10);
+    }
+
+    #[test]
+    fn test_shared() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Pool>();
+        let pool = std::sync::Arc::new(Pool::new(2));
+        let cnt = std::sync::Arc::new(AtomicUsize::new(0));
+        let handles = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                let cnt = cnt.clone();
                 thread::spawn(move || {
-                    #[allow(invalid_reference_casting)]
-                    let inner = unsafe { &mut *(ptr as *mut PoolInner) };
-                    #[allow(clippy::never_loop)] // it does...
-                    loop {
-                        thread::park();
-                        let func = inner.func.load(Ordering::SeqCst);
-                        match func.is_null() {
-                            false => {
-                                let func =
-                                    unsafe { &*func as *const Box<dyn Fn(usize) + Send + Sync> };
-                                let max = inner.max.load(Ordering::SeqCst);
-                                loop {
-                                    let cnt = inner.cnt.fetch_add(1, Ordering::SeqCst);
-                                    if cnt >= max {
-                                        let old = inner.finished.fetch_add(1, Ordering::SeqCst);
-                                        if old == size - 1 {
-                                            *inner.notif_mutex.lock().unwrap() = true;
-                                            inner.notif_var.notify_all();
-                                        }
-                                        break;
-                                    }
-                                    (unsafe { &*func })(cnt);
-                                }
-                            },
-                            true => {
-                                let old = inner.finished.fetch_add(1, Ordering::SeqCst);
-                                if old == size - 1 {
-                                    *inner.notif_mutex.lock().unwrap() = true;
-                                    inner.notif_var.notify_all();
-                                }
-                                break;
-                            },
-                        }
+                    for _ in 0..50 {
+                        pool.execute(10, |_| {
+                            cnt.fetch_add(1, Ordering::SeqCst);
+                        });
                     }
                 })
-                .thread()
-                .clone(),
-            );
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
         }
-        pool
+        assert_eq!(cnt.load(Ordering::SeqCst), 2000);
     }
 
-    pub fn execute(&mut self, num: usize, func: impl Fn(usize) + Send + Sync) {
-        let inner = self.inner.as_mut();
-        let _guard = inner.lock_mutex.lock().unwrap();
-        let func = Box::new(func) as Box<dyn Fn(usize) + Send + Sync>;
-        let func = Box::new(func);
-        let ptr = unsafe {
-            std::mem::transmute::<
-                *mut std::boxed::Box<dyn Fn(usize) + Send + Sync>,
-                *mut std::boxed::Box<dyn Fn(usize) + Send + Sync + 'static>,
-            >(Box::into_raw(func))
-        };
-        inner.func.store(ptr, Ordering::SeqCst);
-        inner.cnt.store(0, Ordering::SeqCst);
-        inner.finished.store(0, Ordering::SeqCst);
-        inner.max.store(num, Ordering::SeqCst);
-        for thread in &self.threads {
-            thread.unpark();
-        }
-        let mut notif = inner.notif_mutex.lock().unwrap();
-        while !*notif {
-            notif = inner.notif_var.wait(notif).unwrap();
-        }
-        *notif = false;
-        drop(notif);
-        inner.func.store(std::ptr::null_mut(), Ordering::SeqCst);
+    #[test]
+    fn test_move_pool() {
+        static POOL: std::sync::OnceLock<Pool> = std::sync::OnceLock::new();
+        let pool = Pool::new(2);
+        pool.execute(10, |_| {});
+        // moved with its workers running, used and dropped on another thread
+        let cnt = thread::spawn(move || {
+            let cnt = AtomicUsize::new(0);
+            pool.execute(100, |_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+            cnt.into_inner()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(cnt, 100);
+        let cnt = AtomicUsize::new(0);
+        POOL.get_or_init(|| Pool::new(2)).execute(100, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.into_inner(), 100);
     }
-}
 
-impl Drop for Pool {
-    fn drop(&mut self) {
-        self.inner.finished.store(0, Ordering::SeqCst);
-        for thread in &self.threads {
-            thread.unpark();
-        }
-        let mut guard = self.inner.notif_mutex.lock().unwrap();
-        while !*guard {
-            guard = self.inner.notif_var.wait(guard).unwrap();
+    #[test]
+    fn test_physical_cores() {
+        let pool = Pool::builder().physical_cores(true).build();
+        assert!(pool.num_threads() >= 1);
+        assert!(pool.num_threads() <= cpu::available());
+        #[cfg(target_os = "linux")]
+        {
+            let cores = cpu::physical_cores().unwrap();
+            assert_eq!(pool.num_threads(), cores.len());
+            let pinned = std::sync::Mutex::new(Vec::new());
+            pool.warm_up_with(|index| {
+                pinned
+                    .lock()
+                    .unwrap()
+                    .push((index, sys::allowed_cpus().unwrap()));
+            });
+            for (index, cpus) in pinned.into_inner().unwrap() {
+                assert_eq!(cpus, [cores[index]]);
+            }
         }
+        let pool = Pool::builder().physical_cores(true).num_threads(3).build();
+        assert_eq!(pool.num_threads(), 3);
+        pool.execute(3, |_| {});
     }
-}
 
-lazy_static::lazy_static! {
-    static ref GLOBAL: std::sync::Mutex<Option<Pool>> = std::sync::Mutex::new(None);
-}
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_execute_on_cpus() {
+        let pool = Pool::builder().pin_threads(true).num_threads(3).build();
+        let cpu = sys::allowed_cpus().unwrap()[0];
+        let cnt = AtomicUsize::new(0);
+        pool.execute_on_cpus(&[cpu], 100, |_| {
+            assert_eq!(sys::allowed_cpus().unwrap(), [cpu]);
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 100);
+        let res = std::panic::catch_unwind(|| pool.execute_on_cpus(&[usize::MAX], 1, |_| {}));
+        assert!(res.is_err());
+        let unpinned = Pool::new(2);
+        let res = std::panic::catch_unwind(|| unpinned.execute_on_cpus(&[cpu], 1, |_| {}));
+        assert!(res.is_err());
+        // small jobs and busy pools don't move it onto the caller's CPU
+        let pool = Pool::builder()
+            .pin_threads(true)
+            .num_threads(2)
+            .inline_threshold(usize::MAX)
+            .help_when_busy(true)
+            .build();
+        let caller = thread::current().id();
+        pool.execute_on_cpus(&[cpu], 1, |_| assert_ne!(thread::current().id(), caller));
+    }
 
-pub fn execute(num: usize, func: impl Fn(usize) + Send + Sync) {
-    GLOBAL
-        .lock()
-        .unwrap()
-        .get_or_insert_with(|| {
-            let size = std::env::var("IEU_NUM_THREADS")
-                .ok()
-                .and_then(|s| s.parse::<usize>().ok())
-                .unwrap_or_else(|| {
-                    std::env::var("RAYON_NUM_THREADS")
-                        .ok()
-                        .and_then(|s| s.parse::<usize>().ok())
-                        .unwrap_or_else(num_cpus::get)
-                });
-            Pool::new(size)
-        })
-        .execute(num, func);
-}
+    #[test]
+    fn test_pin_threads() {
+        let pool = Pool::builder().pin_threads(true).num_threads(3).build();
+        assert_eq!(pool.num_threads(), 3);
+        #[cfg(target_os = "linux")]
+        {
+            let allowed = sys::allowed_cpus().unwrap();
+            let pinned = std::sync::Mutex::new(Vec::new());
+            pool.warm_up_with(|index| {
+                pinned
+                    .lock()
+                    .unwrap()
+                    .push((index, sys::allowed_cpus().unwrap()));
+            });
+            for (index, cpus) in pinned.into_inner().unwrap() {
+                assert_eq!(cpus, [allowed[index % allowed.len()]]);
+            }
+        }
+        assert_eq!("High".parse(), Ok(ThreadPriority::High));
+        assert_eq!("background".parse(), Ok(ThreadPriority::Background));
+        assert!("highest".parse::<ThreadPriority>().is_err());
+        assert_eq!("nice:-3".parse(), Ok(ThreadPriority::Nice(-3)));
+        assert_eq!("Realtime: 50".parse(), Ok(ThreadPriority::Realtime(50)));
+        assert!("nice:20".parse::<ThreadPriority>().is_err());
+        assert!("realtime:0".parse::<ThreadPriority>().is_err());
+        assert_eq!(ThreadPriority::Nice(7).coarse(), ThreadPriority::Low);
+        // nothing is set under this prefix
+        let builder = Builder::new().stack_size(1 << 20).env("IEU_TEST_UNSET");
+        assert_eq!(builder.stack_size, Some(1 << 20));
+        assert_eq!(builder.linger, None);
+    }
 
-#[cfg(all(test, not(loom)))]
-mod tests {
-    use super::*;
+    #[cfg(feature = "hwloc")]
+    #[test]
+    fn test_topology() {
+        let topology = Topology::detect().unwrap();
+        assert!(topology.cores() >= topology.l3_groups().max(topology.packages()));
+        assert_eq!(topology.place(&Placement::Compact).len(), topology.cores());
+        assert_eq!(topology.place(&Placement::Spread).len(), topology.cores());
+        let pool = Pool::builder().placement(Placement::PerL3).build();
+        assert_eq!(pool.num_threads(), topology.place(&Placement::PerL3).len());
+        pool.execute(pool.num_threads(), |_| {});
+    }
 
     #[test]
-    fn test_pool() {
-        let mut pool = Pool::new(4);
+    fn test_concurrent_jobs() {
+        let pool = Pool::new(2);
+        let flag = AtomicUsize::new(0);
+        thread::scope(|s| {
+            // holds one worker until the other job has run, which it can only
+            // do if the two jobs share the workers
+            s.spawn(|| {
+                pool.execute(1, |_| {
+                    let start = std::time::Instant::now();
+                    while flag.load(Ordering::SeqCst) == 0
+                        && start.elapsed() < Duration::from_secs(10)
+                    {
+                        thread::yield_now();
+                    }
+                });
+            });
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                pool.execute(1, |_| {
+                    flag.store(1, Ordering::SeqCst);
+                });
+            });
+        });
+        assert_eq!(flag.load(Ordering::SeqCst), 1);
         let cnt = AtomicUsize::new(0);
-        pool.execute(10, |_| {
-            cnt.fetch_add(1, Ordering::SeqCst);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    pool.broadcast(|team| {
+                        team.barrier();
+                        cnt.fetch_add(1, Ordering::SeqCst);
+                    });
+                });
+            }
         });
-        pool.execute(20, |_| {
-            cnt.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(cnt.load(Ordering::SeqCst), 16);
+    }
+
+    #[test]
+    fn test_fair_admission() {
+        let pool = Pool::new(5);
+        let held = [(); 4].map(|_| std::sync::atomic::AtomicBool::new(true));
+        let order = std::sync::Mutex::new(Vec::new());
+        let wait_for = |done: &dyn Fn() -> bool| {
+            let start = std::time::Instant::now();
+            while !done() && start.elapsed() < Duration::from_secs(10) {
+                thread::yield_now();
+            }
+        };
+        thread::scope(|s| {
+            // fill every job slot with a job that runs until released
+            for held in &held {
+                s.spawn(|| pool.execute(1, |_| wait_for(&|| !held.load(Ordering::SeqCst))));
+            }
+            thread::sleep(Duration::from_millis(50));
+            for name in ["a", "b", "c"] {
+                let order = &order;
+                let pool = &pool;
+                s.spawn(move || pool.execute(1, |_| order.lock().unwrap().push(name)));
+                thread::sleep(Duration::from_millis(20));
+            }
+            // free the slots one at a time, each goes to the longest waiter
+            for (i, held) in held.iter().enumerate() {
+                held.store(false, Ordering::SeqCst);
+                wait_for(&|| order.lock().unwrap().len() > i.min(2));
+            }
         });
-        pool.execute(50, |_| {
+        assert_eq!(*order.lock().unwrap(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_help_when_busy() {
+        let pool = Pool::builder().num_threads(4).help_when_busy(true).build();
+        let held = std::sync::atomic::AtomicBool::new(true);
+        let started = AtomicUsize::new(0);
+        thread::scope(|s| {
+            // fill every job slot with a job that runs until released
+            for _ in 0..4 {
+                s.spawn(|| {
+                    pool.execute(1, |_| {
+                        started.fetch_add(1, Ordering::SeqCst);
+                        while held.load(Ordering::SeqCst) {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                    })
+                });
+            }
+            while started.load(Ordering::SeqCst) < 4 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            // runs on this thread rather than waiting for a slot
+            let caller = thread::current().id();
+            pool.execute(10, |_| assert_eq!(thread::current().id(), caller));
+            // a job for every worker can't run on the caller, it waits
+            let team = s.spawn(|| pool.broadcast_map(|index| index));
+            thread::sleep(Duration::from_millis(20));
+            held.store(false, Ordering::SeqCst);
+            assert_eq!(team.join().unwrap(), [0, 1, 2, 3]);
+        });
+        let cnt = AtomicUsize::new(0);
+        pool.execute(100, |_| {
             cnt.fetch_add(1, Ordering::SeqCst);
         });
-        assert_eq!(cnt.load(Ordering::SeqCst), 80);
-        drop(pool);
+        assert_eq!(cnt.load(Ordering::SeqCst), 100);
+        assert_eq!(pool.queued_jobs(), 0);
     }
 
     #[test]
@@ -214,27 +709,2668 @@ mod tests {
         });
         assert_eq!(cnt.load(Ordering::SeqCst), 2);
     }
-}
-
-#[cfg(all(test, loom))]
-mod loom_tests {
-    use super::*;
 
     #[test]
-    fn test_pool() {
-        loom::model(|| {
-            let mut pool = Pool::new(4);
+    fn test_builder_priority() {
+        // raising the priority without permission falls back quietly
+        let priorities = [
+            ThreadPriority::Background,
+            ThreadPriority::Nice(5),
+            ThreadPriority::Realtime(10),
+        ];
+        for priority in priorities {
+            let pool = Pool::builder().num_threads(2).priority(priority).build();
             let cnt = AtomicUsize::new(0);
             pool.execute(10, |_| {
                 cnt.fetch_add(1, Ordering::SeqCst);
             });
-            pool.execute(20, |_| {
+            assert_eq!(cnt.load(Ordering::SeqCst), 10);
+        }
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let pool = Pool::builder()
+            .num_threads(3)
+            .idle_timeout(Duration::from_millis(10))
+            .build();
+        let cnt = AtomicUsize::new(0);
+        pool.execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(alive(&pool), 0);
+        pool.execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(alive(&pool), 3);
+        assert_eq!(cnt.load(Ordering::SeqCst), 20);
+    }
+
+    #[test]
+    fn test_lazy() {
+        let pool = Pool::builder().num_threads(3).lazy(true).build();
+        assert_eq!(alive(&pool), 0);
+        let cnt = AtomicUsize::new(0);
+        pool.execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(alive(&pool), 3);
+        assert_eq!(cnt.load(Ordering::SeqCst), 10);
+        drop(Pool::builder().num_threads(2).lazy(true).build());
+    }
+
+    #[test]
+    fn test_split() {
+        let pool = Pool::new(4);
+        let subs = pool.split(&[3, 1, 0]);
+        assert_eq!(subs.iter().map(SubPool::num_threads).collect::<Vec<_>>(), [
+            3, 1, 0
+        ]);
+        let cnt = AtomicUsize::new(0);
+        let threads = std::sync::Mutex::new(vec![Vec::new(); 3]);
+        thread::scope(|s| {
+            for (k, sub) in subs.iter().enumerate() {
+                let (cnt, threads) = (&cnt, &threads);
+                s.spawn(move || {
+                    for _ in 0..20 {
+                        sub.execute(50, |_| {
+                            cnt.fetch_add(1, Ordering::SeqCst);
+                            threads.lock().unwrap()[k].push(thread::current().id());
+                        });
+                    }
+                });
+            }
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 3000);
+        let threads = threads.into_inner().unwrap();
+        // the sub-pools never share a worker
+        assert!(threads[0].iter().all(|id| !threads[1].contains(id)));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            subs[1].execute(4, |i| assert_ne!(i, 2));
+        }));
+        assert!(result.is_err());
+        subs[1].execute(1, |_| {});
+        drop(subs);
+        pool.execute(8, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 3008);
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let pool = Pool::new(3);
+        assert_eq!(pool.grain(), 1);
+        let cheap = pool.calibrate(|i| {
+            std::hint::black_box(i);
+        });
+        let costly = pool.calibrate(|_| thread::sleep(Duration::from_micros(100)));
+        assert!(cheap > 1);
+        assert_eq!(costly, 1);
+        assert_eq!(pool.grain(), 1);
+        pool.set_grain(7);
+        let seen = (0..100).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        pool.execute_chunked(100, |i| {
+            seen[i].fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(seen.iter().all(|cnt| cnt.load(Ordering::SeqCst) == 1));
+        pool.execute_aligned(100, 8, |range| {
+            assert_eq!(range.start % 8, 0);
+            assert!(range.len() == 8 || range.end == 100);
+            for i in range {
+                seen[i].fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        assert!(seen.iter().all(|cnt| cnt.load(Ordering::SeqCst) == 2));
+        pool.execute_batches(100, 30, |range| {
+            assert_eq!(range.start % 30, 0);
+            assert!(range.len() == 30 || range == (90..100));
+            for i in range {
+                seen[i].fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        assert!(seen.iter().all(|cnt| cnt.load(Ordering::SeqCst) == 3));
+        pool.execute_batches(0, 0, |_| unreachable!());
+    }
+
+    #[test]
+    fn test_pipeline() {
+        for pool in [Pool::new(3), Pool::new(0)] {
+            let data = (0..100).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+            let stages = 50;
+            pool.pipeline(|pipe| {
+                for stage in 0..stages {
+                    let data = &data;
+                    pipe.execute(data.len(), move |i| {
+                        // the stage before this one is done everywhere
+                        assert!(data[(i + 1) % data.len()].load(Ordering::SeqCst) >= stage);
+                        data[i].fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            });
+            assert!(data
+                .iter()
+                .all(|value| value.load(Ordering::SeqCst) == stages));
+            let cnt = AtomicUsize::new(0);
+            pool.pipeline(|pipe| {
+                pipe.execute(10, |_| {
+                    cnt.fetch_add(1, Ordering::SeqCst);
+                });
+                pipe.wait();
+                assert_eq!(cnt.load(Ordering::SeqCst), 10);
+            });
+        }
+        let pool = Pool::new(2);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pool.pipeline(|pipe| {
+                pipe.execute(4, |i| assert_ne!(i, 2));
+                pipe.execute(4, |_| {});
+            })
+        }));
+        assert!(result.is_err());
+        pool.execute(2, |_| {});
+    }
+
+    #[test]
+    fn test_linger() {
+        let pool = Pool::builder()
+            .num_threads(2)
+            .linger(Duration::from_millis(20))
+            .idle_timeout(Duration::from_millis(100))
+            .build();
+        let cnt = AtomicUsize::new(0);
+        for _ in 0..1000 {
+            pool.execute(4, |_| {
                 cnt.fetch_add(1, Ordering::SeqCst);
             });
-            pool.execute(50, |_| {
+        }
+        assert_eq!(cnt.load(Ordering::SeqCst), 4000);
+        // lingering workers still go to sleep, and retire, once jobs stop
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(alive(&pool), 0);
+        pool.execute(1, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 4001);
+    }
+
+    #[test]
+    fn test_busy_wait() {
+        let pool = Pool::builder()
+            .num_threads(2)
+            .busy_wait(true)
+            .idle_timeout(Duration::from_millis(50))
+            .build();
+        let cnt = AtomicUsize::new(0);
+        for _ in 0..10 {
+            pool.execute(10, |_| {
                 cnt.fetch_add(1, Ordering::SeqCst);
             });
-            assert_eq!(cnt.load(Ordering::SeqCst), 80);
+        }
+        assert_eq!(cnt.load(Ordering::SeqCst), 100);
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(alive(&pool), 0);
+        pool.execute(1, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 101);
+    }
+
+    #[test]
+    fn test_sequential() {
+        let pool = Pool::builder().num_threads(4).sequential(true).build();
+        let caller = thread::current().id();
+        let order = std::sync::Mutex::new(Vec::new());
+        pool.execute(100, |i| {
+            assert_eq!(thread::current().id(), caller);
+            order.lock().unwrap().push(i);
+        });
+        assert_eq!(order.into_inner().unwrap(), (0..100).collect::<Vec<_>>());
+        pool.broadcast(|team| assert_eq!(team.num_threads(), 1));
+        assert!(pool.any(10, |i| i == 3));
+    }
+
+    #[test]
+    fn test_scope_execute() {
+        let pool = Pool::new(4);
+        let mut out = vec![0; 100];
+        {
+            let slots = out.iter().map(|&v| AtomicUsize::new(v)).collect::<Vec<_>>();
+            pool.scope_execute(100, |i| slots[i].store(i * 2, Ordering::SeqCst));
+            for (out, slot) in out.iter_mut().zip(slots) {
+                *out = slot.into_inner();
+            }
+        }
+        assert_eq!(out, (0..100).map(|i| i * 2).collect::<Vec<_>>());
+
+        // a panic on the caller still waits for the workers before unwinding
+        let pool = Pool::builder().num_threads(2).sequential(true).build();
+        let data = [1; 10];
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pool.scope_execute(10, |i| assert!(data[i] == 1 && i < 5));
+        }));
+        assert!(res.is_err());
+        let cnt = AtomicUsize::new(0);
+        pool.execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_execute_into() {
+        let pool = Pool::new(4);
+        let mut buf = [const { std::mem::MaybeUninit::uninit() }; 100];
+        let out = pool.execute_into(&mut buf, |i| i.to_string());
+        assert_eq!(out[42], "42");
+        assert_eq!(out.len(), 100);
+        let mut buf = Vec::with_capacity(10);
+        let out = crate::execute_into(&mut buf.spare_capacity_mut()[..10], |i| i * i);
+        assert_eq!(out, (0..10).map(|i| i * i).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_vec_from_fn() {
+        let table = crate::vec_from_fn(10_000, |i| (i as f64).sqrt());
+        assert_eq!(table.len(), 10_000);
+        assert_eq!(table[144], 12.0);
+        assert!(Pool::new(2).vec_from_fn(0, |i| i).is_empty());
+    }
+
+    #[test]
+    fn test_fill_copy() {
+        let pool = Pool::new(3);
+        for len in [0, 1, 1000, 100_000] {
+            let mut data = vec![0u32; len];
+            pool.par_fill(&mut data, 7);
+            assert!(data.iter().all(|&x| x == 7));
+            let src = (0..len as u32).collect::<Vec<_>>();
+            crate::par_copy(&mut data, &src);
+            assert_eq!(data, src);
+        }
+        let mut names = vec![String::new(); 50];
+        pool.par_fill(&mut names, "ieu".to_string());
+        assert!(names.iter().all(|name| name == "ieu"));
+    }
+
+    #[test]
+    fn test_execute_stream() {
+        let pool = Pool::new(3);
+        let mut seen = pool.execute_stream(1000, |i| i * 2).collect::<Vec<_>>();
+        seen.sort();
+        assert_eq!(seen, (0..1000).map(|i| (i, i * 2)).collect::<Vec<_>>());
+        assert_eq!(crate::execute_stream(0, |i| i).count(), 0);
+        let ordered = pool.execute_stream_ordered(1000, |i| {
+            if i % 7 == 0 {
+                thread::sleep(Duration::from_micros(50));
+            }
+            i * 2
+        });
+        assert_eq!(ordered.len(), 1000);
+        assert_eq!(
+            ordered.collect::<Vec<_>>(),
+            (0..1000).map(|i| i * 2).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            crate::execute_stream_ordered(5, |i| i).collect::<Vec<_>>(),
+            [0, 1, 2, 3, 4]
+        );
+        // dropping the stream early stops the job
+        let ran = std::sync::Arc::new(AtomicUsize::new(0));
+        let counter = ran.clone();
+        let mut stream = pool.execute_stream(1_000_000, move |i| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            i
+        });
+        assert!(stream.next().is_some());
+        drop(stream);
+        pool.wait_idle();
+        assert!(ran.load(Ordering::SeqCst) < 1_000_000);
+        let stream = pool.execute_stream(10, |i| assert_ne!(i, 5));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| stream.count()));
+        assert!(result.is_err());
+        pool.execute(3, |_| {});
+    }
+
+    #[test]
+    fn test_stages() {
+        let pool = Pool::new(3);
+        let lines = (0..1000).map(|i| i.to_string()).collect::<Vec<_>>();
+        let mut out = Vec::new();
+        pool.stages(8, lines.into_iter())
+            .map(|line| line.parse::<usize>().unwrap())
+            .map(|i| {
+                if i % 13 == 0 {
+                    thread::sleep(Duration::from_micros(100));
+                }
+                i * 2
+            })
+            .for_each(|i| out.push(i));
+        assert_eq!(out, (0..1000).map(|i| i * 2).collect::<Vec<usize>>());
+        let mut sum = 0;
+        crate::stages(1, 0..10usize).for_each(|i| sum += i);
+        assert_eq!(sum, 45);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pool.stages(4, 0..100usize)
+                .map(|i| assert_ne!(i, 50))
+                .for_each(|_| {});
+        }));
+        assert!(result.is_err());
+        Pool::new(0)
+            .stages(2, 0..5usize)
+            .map(|i| i + 1)
+            .for_each(|i| assert!(i > 0));
+    }
+
+    #[test]
+    fn test_dag() {
+        let pool = Pool::new(3);
+        // a diamond, `d` needs both `b` and `c`, which both need `a`
+        let [a, b, c, d] = [(); 4].map(|_| AtomicUsize::new(0));
+        let mut dag = pool.dag();
+        let ja = dag.add(100, |_| {
+            a.fetch_add(1, Ordering::SeqCst);
+        });
+        let jb = dag.add_after(&[ja], 50, |_| {
+            assert_eq!(a.load(Ordering::SeqCst), 100);
+            b.fetch_add(1, Ordering::SeqCst);
+        });
+        let jc = dag.add_after(&[ja], 0, |_| unreachable!());
+        let jc = dag.add_after(&[jc], 30, |_| {
+            assert_eq!(a.load(Ordering::SeqCst), 100);
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+        dag.add_after(&[jb, jc], 10, |_| {
+            assert_eq!(b.load(Ordering::SeqCst), 50);
+            assert_eq!(c.load(Ordering::SeqCst), 30);
+            d.fetch_add(1, Ordering::SeqCst);
+        });
+        dag.run();
+        assert_eq!(d.load(Ordering::SeqCst), 10);
+        // a panic keeps the jobs after it from starting
+        let mut dag = crate::dag();
+        let first = dag.add(10, |i| assert_ne!(i, 5));
+        dag.add_after(&[first], 10, |_| unreachable!());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| dag.run()));
+        assert!(result.is_err());
+        Pool::new(0).dag().run();
+    }
+
+    #[test]
+    fn test_par_bridge() {
+        let pool = Pool::new(3);
+        let text = (0..500).map(|i| format!("{i}\n")).collect::<String>();
+        let sum = AtomicUsize::new(0);
+        pool.par_bridge(text.lines(), |line| {
+            sum.fetch_add(line.parse::<usize>().unwrap(), Ordering::SeqCst);
+        });
+        assert_eq!(sum.load(Ordering::SeqCst), 499 * 500 / 2);
+        let seen = std::sync::Mutex::new(Vec::new());
+        crate::par_bridge((0..100).filter(|i| i % 3 == 0), |i| {
+            seen.lock().unwrap().push(i)
+        });
+        let mut seen = seen.into_inner().unwrap();
+        seen.sort();
+        assert_eq!(seen, (0..100).step_by(3).collect::<Vec<usize>>());
+        Pool::new(0).par_bridge(std::iter::empty::<usize>(), |_| unreachable!());
+    }
+
+    #[test]
+    fn test_par_for_each_kv() {
+        let pool = Pool::new(3);
+        let map = (0..1000)
+            .map(|i| (i, i * 2))
+            .collect::<std::collections::HashMap<_, _>>();
+        let sum = AtomicUsize::new(0);
+        pool.par_for_each_kv(&map, |k, v| {
+            assert_eq!(*v, k * 2);
+            sum.fetch_add(*v, Ordering::SeqCst);
+        });
+        assert_eq!(sum.load(Ordering::SeqCst), 999 * 1000);
+        let tree = (0..100)
+            .map(|i| (i.to_string(), i))
+            .collect::<std::collections::BTreeMap<_, _>>();
+        let cnt = AtomicUsize::new(0);
+        par_for_each_kv(&tree, |k, v| {
+            assert_eq!(k.parse::<usize>().unwrap(), *v);
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 100);
+        pool.par_for_each_kv(
+            &std::collections::HashMap::<u8, u8>::new(),
+            |_, _| unreachable!(),
+        );
+    }
+
+    #[test]
+    fn test_execute_tiles() {
+        let pool = Pool::new(3);
+        for (nx, ny, w, h) in [(0, 5, 2, 2), (10, 7, 4, 3), (64, 64, 16, 16), (5, 5, 0, 9)] {
+            let hits = (0..nx * ny)
+                .map(|_| AtomicUsize::new(0))
+                .collect::<Vec<_>>();
+            pool.execute_tiles(nx, ny, w, h, |xs, ys| {
+                assert!(xs.len() <= w.max(1) && ys.len() <= h.max(1));
+                for y in ys {
+                    for x in xs.clone() {
+                        hits[y * nx + x].fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            });
+            assert!(hits.iter().all(|hit| hit.load(Ordering::SeqCst) == 1));
+        }
+        let cnt = AtomicUsize::new(0);
+        crate::execute_tiles(3, 3, 2, 2, |_, _| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_job_hooks() {
+        thread_local! {
+            static SCRATCH: std::cell::RefCell<Vec<usize>> = const { std::cell::RefCell::new(Vec::new()) };
+        }
+        let starts = std::sync::Arc::new(AtomicUsize::new(0));
+        let ends = std::sync::Arc::new(AtomicUsize::new(0));
+        let (s, e) = (starts.clone(), ends.clone());
+        let pool = Pool::builder()
+            .num_threads(3)
+            .on_job_start(move |_| {
+                assert!(SCRATCH.with_borrow(Vec::is_empty));
+                s.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_job_end(move |_| {
+                SCRATCH.with_borrow_mut(Vec::clear);
+                e.fetch_add(1, Ordering::SeqCst);
+            })
+            .build();
+        for round in 1..=5 {
+            pool.execute(30, |i| {
+                SCRATCH.with_borrow_mut(|scratch| scratch.push(i));
+                thread::sleep(Duration::from_micros(100));
+            });
+            // every worker that took part is done with its end hook
+            let (starts, ends) = (starts.load(Ordering::SeqCst), ends.load(Ordering::SeqCst));
+            assert_eq!(starts, ends);
+            assert!((round..=round * 3).contains(&starts));
+        }
+    }
+
+    #[test]
+    fn test_index_spaces() {
+        let pool = Pool::new(3);
+        for n in [0, 1, 2, 7, 100] {
+            let seen = (0..n * n).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+            pool.execute_over(Pairs(n), |(i, j)| {
+                assert!(i < j && j < n);
+                seen[i * n + j].fetch_add(1, Ordering::SeqCst);
+            });
+            let visited = seen
+                .iter()
+                .filter(|cnt| cnt.load(Ordering::SeqCst) == 1)
+                .count();
+            assert_eq!(visited, Pairs(n).len());
+            assert_eq!(visited, n * n.saturating_sub(1) / 2);
+        }
+        let pairs = (0..6).map(|k| Pairs(4).decode(k)).collect::<Vec<_>>();
+        assert_eq!(pairs, [(0, 1), (0, 2), (1, 2), (0, 3), (1, 3), (2, 3)]);
+        let seen = (0..5 * 4 * 3)
+            .map(|_| AtomicUsize::new(0))
+            .collect::<Vec<_>>();
+        pool.execute_over(Product(Product(5, 4), 3), |((x, y), z)| {
+            seen[x * 12 + y * 3 + z].fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(seen.iter().all(|cnt| cnt.load(Ordering::SeqCst) == 1));
+        assert_eq!(Product(3, 4).decode(5), (1, 1));
+        assert_eq!(Product(Pairs(3), 2).decode(3), ((0, 2), 1));
+        assert!(Product(3, 0).is_empty());
+    }
+
+    #[test]
+    fn test_execute_indices() {
+        let pool = Pool::new(3);
+        let seen = (0..1000).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        let indices = [999, 3, 500, 4, 0, 77];
+        pool.execute_indices(&indices, |i| {
+            seen[i].fetch_add(1, Ordering::SeqCst);
+        });
+        for (i, cnt) in seen.iter().enumerate() {
+            assert_eq!(cnt.swap(0, Ordering::SeqCst), indices.contains(&i) as usize);
+        }
+        // empty words between set ones, and one with every bit set
+        let words = [1 << 63 | 1 << 5, 0, 0, u64::MAX, 0, 1 << 7 | 1];
+        let expected = (0..words.len() * 64)
+            .filter(|&i| words[i / 64] >> (i % 64) & 1 == 1)
+            .collect::<Vec<_>>();
+        let bits = Bits::new(&words);
+        assert_eq!(bits.len(), expected.len());
+        assert!((0..bits.len())
+            .map(|k| bits.decode(k))
+            .eq(expected.iter().copied()));
+        execute_bits(&words, |i| {
+            seen[i].fetch_add(1, Ordering::SeqCst);
+        });
+        for (i, cnt) in seen.iter().enumerate() {
+            assert_eq!(cnt.load(Ordering::SeqCst), expected.contains(&i) as usize);
+        }
+        assert!(Bits::new(&[0, 0]).is_empty());
+        execute_indices(&[], |_| unreachable!());
+    }
+
+    #[test]
+    fn test_worker_exit_hook() {
+        thread_local! {
+            static BUFFER: std::cell::RefCell<Vec<usize>> = const { std::cell::RefCell::new(Vec::new()) };
+        }
+        let flushed = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let exited = std::sync::Arc::new(AtomicUsize::new(0));
+        let (f, e) = (flushed.clone(), exited.clone());
+        let pool = Pool::builder()
+            .num_threads(3)
+            .on_worker_exit(move |_| {
+                f.lock().unwrap().extend(BUFFER.take());
+                e.fetch_add(1, Ordering::SeqCst);
+            })
+            .build();
+        pool.execute(100, |i| BUFFER.with_borrow_mut(|buffer| buffer.push(i)));
+        assert_eq!(exited.load(Ordering::SeqCst), 0);
+        drop(pool);
+        assert_eq!(exited.load(Ordering::SeqCst), 3);
+        let mut flushed = flushed.lock().unwrap().clone();
+        // indices the caller ran end up in its own buffer
+        flushed.extend(BUFFER.take());
+        flushed.sort();
+        assert_eq!(flushed, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let pool = Pool::new(3);
+        let threads = std::sync::Mutex::new(vec![None; 200]);
+        let schedule = pool.execute_recorded(200, |i| {
+            threads.lock().unwrap()[i] = Some(thread::current().id());
+            if i % 17 == 0 {
+                thread::sleep(Duration::from_micros(50));
+            }
+        });
+        assert_eq!(schedule.len(), 200);
+        let schedule = schedule.to_string().parse::<Schedule>().unwrap();
+        let order = std::sync::Mutex::new(Vec::new());
+        pool.execute_replayed(&schedule, |i| {
+            order.lock().unwrap().push((i, thread::current().id()));
+        });
+        // every index ran on the thread that ran it before, and started in
+        // order
+        let threads = threads.into_inner().unwrap();
+        let order = order.into_inner().unwrap();
+        assert_eq!(order.len(), 200);
+        for (k, &(i, thread)) in order.iter().enumerate() {
+            assert_eq!(i, k);
+            assert_eq!(Some(thread), threads[i]);
+        }
+        assert!("".parse::<Schedule>().unwrap().is_empty());
+        assert!("0,x".parse::<Schedule>().is_err());
+        let inline = crate::execute_recorded(5, |_| {});
+        crate::execute_replayed(&inline, |_| {});
+        let two = "0,1".parse::<Schedule>().unwrap();
+        let result = std::panic::catch_unwind(|| Pool::new(0).execute_replayed(&two, |_| {}));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_execute_traced() {
+        let pool = Pool::new(3);
+        let spans = pool.execute_traced(100, |i| {
+            if i % 10 == 0 {
+                thread::sleep(Duration::from_micros(100));
+            }
+        });
+        let mut covered = spans
+            .iter()
+            .flat_map(|span| span.indices.clone())
+            .collect::<Vec<_>>();
+        covered.sort();
+        assert_eq!(covered, (0..100).collect::<Vec<usize>>());
+        assert!(spans.windows(2).all(|w| w[0].start <= w[1].start));
+        assert!(spans
+            .iter()
+            .all(|span| span.worker < 3 && span.start <= span.end));
+        let inline = crate::execute_traced(5, |_| {});
+        assert!(inline.iter().map(|span| span.indices.len()).sum::<usize>() == 5);
+        assert!(Pool::new(0).execute_traced(0, |_| {}).is_empty());
+    }
+
+    #[test]
+    fn test_first_touch_vec() {
+        let pool = Pool::new(3);
+        for len in [0, 2, 1000] {
+            let threads = pool.first_touch_vec(len, |i| (i, thread::current().id()));
+            assert!(threads.iter().enumerate().all(|(i, &(j, _))| i == j));
+            // every tile was written by one worker
+            for t in 0..3 {
+                let tile = &threads[len * t / 3..len * (t + 1) / 3];
+                assert!(tile.iter().all(|&(_, id)| id == tile[0].1));
+            }
+        }
+        let squares = crate::first_touch_vec(10, |i| i * i);
+        assert_eq!(squares, (0..10).map(|i| i * i).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_pause_resume() {
+        let pool = Pool::new(2);
+        pool.pause();
+        assert!(pool.is_paused());
+        let cnt = std::sync::Arc::new(AtomicUsize::new(0));
+        let counter = cnt.clone();
+        let job = pool.submit(50, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(cnt.load(Ordering::SeqCst), 0);
+        pool.resume();
+        assert!(!pool.is_paused());
+        assert!(matches!(job.wait(), JobResult::Completed));
+        assert_eq!(cnt.load(Ordering::SeqCst), 50);
+        let cnt = AtomicUsize::new(0);
+        thread::scope(|s| {
+            pool.pause();
+            s.spawn(|| {
+                pool.execute(20, |_| {
+                    cnt.fetch_add(1, Ordering::SeqCst);
+                })
+            });
+            thread::sleep(Duration::from_millis(20));
+            // at most the index each worker had already claimed
+            assert!(cnt.load(Ordering::SeqCst) <= 2);
+            pool.resume();
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 20);
+        // dropping a paused pool doesn't hang
+        let paused = Pool::new(2);
+        paused.pause();
+        paused.spawn(|| {});
+        drop(paused);
+    }
+
+    #[test]
+    fn test_stats() {
+        let pool = Pool::new(2);
+        pool.execute(10, |_| {});
+        pool.reset_stats();
+        assert_eq!(pool.stats_snapshot().jobs, 0);
+        pool.execute(100, |_| {});
+        pool.execute(50, |_| {});
+        pool.spawn(|| {});
+        pool.wait_idle();
+        let stats = pool.stats_snapshot();
+        assert_eq!((stats.jobs, stats.indices, stats.tasks), (2, 150, 1));
+        assert_eq!((stats.busy.len(), stats.idle.len()), (2, 2));
+        assert!(stats.max_pickup > Duration::ZERO && stats.max_pickup <= stats.pickup);
+        assert_eq!((pool.queued_jobs(), pool.queued_tasks()), (0, 0));
+        pool.pause();
+        pool.spawn(|| {});
+        assert_eq!(pool.queued_tasks(), 1);
+        pool.resume();
+        pool.wait_idle();
+        pool.reset_stats();
+        let stats = pool.stats_snapshot();
+        assert_eq!((stats.jobs, stats.indices, stats.tasks), (0, 0, 0));
+        assert_eq!(stats.max_pickup, Duration::ZERO);
+        assert!(stats.busy.iter().chain(&stats.idle).all(Duration::is_zero));
+    }
+
+    #[test]
+    fn test_pool_handle() {
+        let handle = Pool::new(2).into_handle();
+        let cnt = std::sync::Arc::new(AtomicUsize::new(0));
+        let threads = (0..3)
+            .map(|_| {
+                let (handle, cnt) = (handle.clone(), cnt.clone());
+                thread::spawn(move || {
+                    handle.execute(10, |_| {
+                        cnt.fetch_add(1, Ordering::SeqCst);
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(cnt.load(Ordering::SeqCst), 30);
+        assert!(handle.ptr_eq(&handle.clone()));
+        assert!(!handle.ptr_eq(&PoolHandle::from(Pool::new(1))));
+        assert_eq!(handle.num_threads(), 2);
+    }
+
+    #[test]
+    fn test_context_pool() {
+        let mut pool = Pool::new_with_contexts(vec![Vec::new(), Vec::new(), Vec::new()]);
+        assert_eq!(pool.num_threads(), 3);
+        pool.execute(100, |seen: &mut Vec<usize>, i| seen.push(i));
+        pool.execute(0, |_, _| unreachable!());
+        let mut seen = pool
+            .contexts_mut()
+            .flat_map(|seen| seen.drain(..))
+            .collect::<Vec<_>>();
+        seen.sort();
+        assert_eq!(seen, (0..100).collect::<Vec<usize>>());
+        pool.execute(10, |seen, i| seen.push(i));
+        let total = pool.into_contexts().iter().map(Vec::len).sum::<usize>();
+        assert_eq!(total, 10);
+    }
+
+    #[test]
+    fn test_par_tiles() {
+        let pool = Pool::new(3);
+        for len in [0, 2, 10, 1001] {
+            let mut data = vec![0usize; len];
+            let calls = AtomicUsize::new(0);
+            pool.par_tiles(&mut data, |t, tile| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                assert!(tile.len() <= len.div_ceil(3));
+                tile.fill(t + 1);
+            });
+            assert_eq!(calls.load(Ordering::SeqCst), 3);
+            assert!(data.windows(2).all(|w| w[0] <= w[1]));
+            assert!(data.iter().all(|&t| (1..=3).contains(&t)));
+        }
+        let mut data = vec![1u8; 10];
+        crate::par_tiles(&mut data, |_, tile| tile.iter_mut().for_each(|x| *x *= 2));
+        assert!(data.iter().all(|&x| x == 2));
+    }
+
+    #[test]
+    fn test_partition_dedup() {
+        let pool = Pool::new(3);
+        for len in [0, 1, 7, 10_000] {
+            let mut data = (0..len).map(|i| i * 7 % 10).collect::<Vec<usize>>();
+            let (mut even, odd): (Vec<_>, Vec<_>) = data.iter().copied().partition(|&x| x % 2 == 0);
+            let count = pool.par_partition(&mut data, |&x| x % 2 == 0);
+            assert_eq!(count, even.len());
+            even.extend(odd);
+            assert_eq!(data, even);
+        }
+        // owned elements are moved, not copied or dropped
+        let mut names = (0..1000).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert_eq!(par_partition(&mut names, |name| name.len() == 3), 900);
+        assert_eq!(names[0], "100");
+        assert_eq!(names[900], "0");
+        let mut data = (0..10_000).map(|i| i / 3 % 5).collect::<Vec<_>>();
+        let mut expected = data.clone();
+        expected.dedup();
+        pool.par_dedup(&mut data);
+        assert_eq!(data, expected);
+        let mut data = (0..100_000).collect::<Vec<u64>>();
+        assert_eq!(pool.par_retain(&mut data, |&x| x % 3 == 0), 33_334);
+        assert!(data.iter().enumerate().all(|(i, &x)| x == 3 * i as u64));
+        let mut names = (0..100).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert_eq!(par_retain(&mut names, |name| name.ends_with('7')), 10);
+        assert_eq!(names[1], "17");
+        let mut names = vec![
+            "a".to_string(),
+            "a".to_string(),
+            "b".to_string(),
+            "a".to_string(),
+        ];
+        par_dedup(&mut names);
+        assert_eq!(names, ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn test_par_extend() {
+        let pool = Pool::new(3);
+        let mut squares = vec![0usize];
+        pool.par_extend(&mut squares, 1000, |i| i * i);
+        assert_eq!(squares.len(), 1001);
+        assert!(squares[1..].iter().enumerate().all(|(i, &s)| s == i * i));
+        let mut text = String::from(">");
+        par_extend(&mut text, 5, |i| char::from(b'a' + i as u8));
+        assert_eq!(text, ">abcde");
+        let mut names = std::collections::VecDeque::new();
+        names.par_extend((0..100).par_iter().map(|i| i.to_string()));
+        assert_eq!(names.len(), 100);
+        assert_eq!(names[42], "42");
+        let mut set = std::collections::BTreeSet::new();
+        set.par_extend([3, 1, 2, 3].as_slice().par_iter().map(|&x| x));
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), [1, 2, 3]);
+        pool.par_extend(&mut squares, 0, |_| -> usize { unreachable!() });
+        assert_eq!(squares.len(), 1001);
+    }
+
+    #[test]
+    fn test_collect_map() {
+        let pool = Pool::new(4);
+        let squares: std::collections::HashMap<_, _> = pool.par_collect(1000, |i| (i, i * i));
+        assert_eq!(squares.len(), 1000);
+        assert_eq!(squares[&30], 900);
+        let mut odd: Vec<usize> = crate::par_collect(10, |i| i * 2 + 1);
+        odd.sort();
+        assert_eq!(odd, [1, 3, 5, 7, 9, 11, 13, 15, 17, 19]);
+        let counts = pool.par_collect_map(1000, |i| (i % 7, 1), |a, b| a + b);
+        assert_eq!(counts.values().sum::<usize>(), 1000);
+        assert_eq!(counts[&0], 143);
+    }
+
+    #[test]
+    fn test_execute_factoring() {
+        assert_eq!(schedule::factoring_bounds(100, 2), [
+            0, 25, 50, 63, 76, 82, 88, 91, 94, 96, 98, 99, 100
+        ]);
+        assert_eq!(schedule::factoring_bounds(0, 4), [0]);
+        let pool = Pool::new(3);
+        let seen = (0..1000).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        pool.execute_factoring(1000, |i| {
+            seen[i].fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(seen.iter().all(|cnt| cnt.load(Ordering::SeqCst) == 1));
+    }
+
+    #[test]
+    fn test_power_saving() {
+        let pool = Pool::builder().power_saving(true).build();
+        let expected =
+            cpu::efficiency_cores().map_or(cpu::available().div_ceil(2), |cores| cores.len());
+        assert_eq!(pool.num_threads(), expected);
+        assert_eq!(pool.grain(), 16);
+        let cnt = AtomicUsize::new(0);
+        pool.execute_chunked(1000, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 1000);
+        let pool = Pool::builder().power_saving(true).num_threads(3).build();
+        assert_eq!(pool.num_threads(), 3);
+    }
+
+    #[cfg(feature = "chaos")]
+    #[test]
+    fn test_chaos() {
+        let pool = Pool::builder()
+            .num_threads(3)
+            .idle_timeout(Duration::from_millis(5))
+            .chaos(7)
+            .build();
+        for _ in 0..20 {
+            let seen = (0..200).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+            pool.execute(200, |i| {
+                seen[i].fetch_add(1, Ordering::SeqCst);
+            });
+            assert!(seen.iter().all(|cnt| cnt.load(Ordering::SeqCst) == 1));
+        }
+        let cnt = AtomicUsize::new(0);
+        pool.scope(|s| {
+            for _ in 0..50 {
+                s.spawn(|_| {
+                    cnt.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 50);
+        thread::sleep(Duration::from_millis(20));
+        pool.execute(10, |_| {});
+    }
+
+    #[test]
+    fn test_yield_every() {
+        let pools = [
+            Pool::builder().num_threads(3).yield_every(10).build(),
+            Pool::builder()
+                .num_threads(3)
+                .yield_every(100)
+                .yield_sleep(Duration::from_micros(50))
+                .build(),
+        ];
+        for pool in pools {
+            let cnt = AtomicUsize::new(0);
+            pool.execute(1000, |_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+            assert_eq!(cnt.load(Ordering::SeqCst), 1000);
+        }
+    }
+
+    #[test]
+    fn test_spawn_future() {
+        use std::{future::Future, pin::Pin, task};
+
+        // pending on the first poll, wakes itself from another thread
+        struct Yield(bool);
+
+        impl Future for Yield {
+            type Output = ();
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<()> {
+                if self.0 {
+                    return task::Poll::Ready(());
+                }
+                self.0 = true;
+                let waker = cx.waker().clone();
+                thread::spawn(move || waker.wake());
+                task::Poll::Pending
+            }
+        }
+
+        for num_threads in [0, 2] {
+            let pool = Pool::new(num_threads);
+            let (tx, rx) = std::sync::mpsc::channel();
+            for i in 0..10 {
+                let tx = tx.clone();
+                pool.spawn_future(async move {
+                    Yield(false).await;
+                    Yield(false).await;
+                    tx.send(i).unwrap();
+                });
+            }
+            let mut got = (0..10).map(|_| rx.recv().unwrap()).collect::<Vec<_>>();
+            got.sort();
+            assert_eq!(got, (0..10).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let pool = Pool::builder().num_threads(3).lazy(true).build();
+        let seen = (0..3).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        pool.warm_up_with(|index| {
+            seen[index].fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(seen.iter().all(|s| s.load(Ordering::SeqCst) == 1));
+        pool.warm_up();
+        crate::warm_up();
+    }
+
+    #[test]
+    fn test_stack_size() {
+        // recurses through ~8 MiB of stack, more than the default
+        #[inline(never)]
+        fn depth(n: usize) -> usize {
+            let frame = std::hint::black_box([0u8; 1024]);
+            match n {
+                0 => frame[0] as usize,
+                _ => depth(n - 1) + 1,
+            }
+        }
+
+        let pool = Pool::builder()
+            .num_threads(2)
+            .stack_size(64 * 1024 * 1024)
+            .build();
+        let cnt = AtomicUsize::new(0);
+        pool.execute(2, |_| {
+            cnt.fetch_add(depth(8 * 1024), Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 16 * 1024);
+    }
+
+    #[test]
+    fn test_try_new() {
+        let pool = Pool::try_new(2).unwrap();
+        assert_eq!(pool.num_threads(), 2);
+        // far beyond any address space, so spawning the first worker fails
+        let res = Pool::builder()
+            .num_threads(2)
+            .stack_size(usize::MAX / 2)
+            .try_build();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_panic() {
+        let pool = Pool::new(3);
+        for _ in 0..3 {
+            let res = std::panic::catch_unwind(|| {
+                pool.execute(100, |i| assert_ne!(i, 42));
+            });
+            assert!(res.is_err());
+        }
+        pool.spawn(|| panic!("task"));
+        let res = std::panic::catch_unwind(|| {
+            pool.scope(|scope| scope.spawn(|_| panic!("scope")));
+        });
+        assert!(res.is_err());
+        // every worker is still there
+        let seen = (0..3).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        pool.warm_up_with(|index| {
+            seen[index].fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(seen.iter().all(|s| s.load(Ordering::SeqCst) == 1));
+    }
+
+    #[test]
+    fn test_health() {
+        let pool = Pool::new(2);
+        assert!(pool.is_healthy(Duration::from_secs(10)));
+        // a worker stuck in a task doesn't answer
+        let (started_tx, started) = std::sync::mpsc::channel();
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        pool.spawn(move || {
+            started_tx.send(()).unwrap();
+            let _ = rx.recv();
+        });
+        started.recv().unwrap();
+        assert_eq!(
+            pool.unresponsive_workers(Duration::from_millis(50)).len(),
+            1
+        );
+        drop(tx);
+        assert!(pool.is_healthy(Duration::from_secs(10)));
+        assert!(Pool::new(0).is_healthy(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_execute_watched() {
+        let pool = Pool::new(2);
+        let stuck = std::sync::Mutex::new(Vec::new());
+        pool.execute_watched(
+            4,
+            Duration::from_millis(20),
+            |i, worker| stuck.lock().unwrap().push((i, worker)),
+            |i| {
+                if i == 2 {
+                    thread::sleep(Duration::from_millis(200));
+                }
+            },
+        );
+        let stuck = stuck.into_inner().unwrap();
+        assert_eq!(stuck.len(), 1);
+        assert_eq!(stuck[0].0, 2);
+        assert!(stuck[0].1 < 2);
+        let res = std::panic::catch_unwind(|| {
+            execute_watched(1, Duration::from_secs(10), |_, _| {}, |_| panic!("boom"))
+        });
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_broadcast_map() {
+        let pool = Pool::new(3);
+        let ids = pool.broadcast_map(|worker| (worker, thread::current().id()));
+        assert_eq!(ids.iter().map(|&(worker, _)| worker).collect::<Vec<_>>(), [
+            0, 1, 2
+        ]);
+        let workers = pool.worker_ids();
+        assert!(ids.iter().all(|(_, id)| workers.contains(id)));
+        assert_eq!(Pool::new(0).broadcast_map(|worker| worker * 10), [0]);
+        assert!(broadcast_map(|_| 1).iter().all(|&one| one == 1));
+        // from inside a job it runs once, on the calling worker
+        pool.execute(1, |_| {
+            assert_eq!(pool.broadcast_map(|worker| worker).len(), 1)
+        });
+    }
+
+    #[test]
+    fn test_spawn_broadcast() {
+        for pool in [
+            Pool::new(3),
+            Pool::builder().num_threads(2).lazy(true).build(),
+        ] {
+            let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let recorded = seen.clone();
+            pool.spawn_broadcast(move |index| recorded.lock().unwrap().push(index));
+            pool.wait_idle();
+            let mut seen = seen.lock().unwrap().clone();
+            seen.sort();
+            assert_eq!(seen, (0..pool.num_threads()).collect::<Vec<usize>>());
+        }
+        let ran = std::sync::Arc::new(AtomicUsize::new(0));
+        let cnt = ran.clone();
+        Pool::new(0).spawn_broadcast(move |index| {
+            cnt.fetch_add(index + 1, Ordering::SeqCst);
+        });
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_in_place_scope() {
+        // the only worker blocks until the other task ran, which takes the
+        // caller pitching in
+        let pool = Pool::new(1);
+        let flag = AtomicUsize::new(0);
+        let ret = pool.in_place_scope(|s| {
+            s.spawn(|_| {
+                while flag.load(Ordering::SeqCst) == 0 {
+                    thread::yield_now();
+                }
+            });
+            s.spawn(|_| {
+                flag.store(1, Ordering::SeqCst);
+            });
+            7
+        });
+        assert_eq!(ret, 7);
+        let res = std::panic::catch_unwind(|| {
+            in_place_scope(|s| s.spawn(|_| panic!("boom")));
+        });
+        assert!(res.is_err());
+        let mut data = vec![0usize; 4];
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pool.in_place_scope(|s| {
+                s.spawn(|_| {
+                    thread::sleep(Duration::from_millis(20));
+                    data.fill(1);
+                });
+                panic!("boom");
+            })
+        }));
+        assert!(res.is_err());
+        assert_eq!(data, [1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_on_hang() {
+        let hangs = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = hangs.clone();
+        let pool = Pool::builder()
+            .num_threads(2)
+            .on_hang(Duration::from_millis(20), move |hang| {
+                reported.lock().unwrap().push(hang.clone())
+            })
+            .build();
+        pool.execute_labeled("slow", 4, |i| {
+            if i == 0 {
+                thread::sleep(Duration::from_millis(200));
+            }
+        });
+        let hangs = hangs.lock().unwrap();
+        assert!(!hangs.is_empty());
+        for hang in hangs.iter() {
+            assert_eq!(hang.label.as_deref(), Some("slow"));
+            assert_eq!(hang.num, 4);
+            assert!(hang.waited >= Duration::from_millis(20));
+            assert_eq!(hang.running.iter().map(|r| r.1).collect::<Vec<_>>(), [0]);
+        }
+    }
+
+    #[test]
+    fn test_rng_for_index() {
+        let draw = |pool: &Pool| {
+            pool.vec_from_fn(1000, |i| {
+                let mut rng = rng_for_index(42, i);
+                (rng.next_u64(), rng.next_f64(), rng.below(6))
+            })
+        };
+        let a = draw(&Pool::new(1));
+        assert_eq!(a, draw(&Pool::new(4)));
+        assert!(a.iter().all(|&(_, f, d)| (0.0..1.0).contains(&f) && d < 6));
+        // streams of neighbouring indices and seeds differ
+        assert_ne!(a[0].0, a[1].0);
+        assert_ne!(
+            rng_for_index(1, 0).next_u64(),
+            rng_for_index(2, 0).next_u64()
+        );
+        let mut rng = rng_for_index(7, 3);
+        let mean = (0..10_000).map(|_| rng.next_f64()).sum::<f64>() / 10_000.0;
+        assert!((mean - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_worker_ids() {
+        let pool = Pool::new(3);
+        let ids = pool.worker_ids();
+        assert_eq!(ids.len(), 3);
+        let ran = std::sync::Mutex::new(Vec::new());
+        pool.warm_up_with(|index| ran.lock().unwrap().push((index, thread::current().id())));
+        for (index, id) in ran.into_inner().unwrap() {
+            assert_eq!(ids[index], id);
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let tids = pool.worker_tids();
+            assert_eq!(tids.len(), 3);
+            assert!(tids
+                .iter()
+                .all(|&tid| tid > 0 && tid != unsafe { libc::gettid() }));
+        }
+        assert!(Pool::builder()
+            .num_threads(2)
+            .lazy(true)
+            .build()
+            .worker_ids()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_with_num_threads() {
+        let threads = || {
+            let workers = std::sync::Mutex::new(std::collections::HashSet::new());
+            execute(200, |_| {
+                workers.lock().unwrap().insert(thread::current().id());
+                thread::sleep(Duration::from_micros(50));
+            });
+            workers.into_inner().unwrap().len()
+        };
+        assert_eq!(with_num_threads(1, threads), 1);
+        // nested calls can't raise the limit
+        assert_eq!(with_num_threads(1, || with_num_threads(4, threads)), 1);
+        assert_eq!(with_num_threads(0, || with_num_threads(1, threads)), 1);
+        let ret = std::panic::catch_unwind(|| with_num_threads(1, || panic!("limited")));
+        assert!(ret.is_err());
+        assert!(threads() >= 1);
+    }
+
+    #[test]
+    fn test_execute_on() {
+        let pool = Pool::new(4);
+        for num_threads in [0, 1, 2, 8] {
+            let workers = std::sync::Mutex::new(std::collections::HashSet::new());
+            let cnt = AtomicUsize::new(0);
+            pool.execute_on(num_threads, 1000, |_| {
+                workers.lock().unwrap().insert(thread::current().id());
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+            assert_eq!(cnt.load(Ordering::SeqCst), 1000);
+            assert!(workers.into_inner().unwrap().len() <= num_threads.max(1));
+        }
+    }
+
+    #[test]
+    fn test_small_jobs() {
+        // small jobs only wake a few of the workers, retired ones included
+        let pool = Pool::builder()
+            .num_threads(8)
+            .idle_timeout(Duration::from_millis(1))
+            .build();
+        let cnt = AtomicUsize::new(0);
+        for round in 0..100 {
+            pool.execute(round % 3 + 1, |_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+            pool.execute_on(2, 3, |_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+            if round % 10 == 0 {
+                thread::sleep(Duration::from_millis(3));
+            }
+        }
+        assert_eq!(cnt.load(Ordering::SeqCst), 199 + 300);
+    }
+
+    #[test]
+    fn test_active_threads() {
+        let pool = Pool::new(4);
+        assert_eq!(pool.active_threads(), 4);
+        pool.set_active_threads(2);
+        let workers = std::sync::Mutex::new(std::collections::HashSet::new());
+        pool.execute(1000, |_| {
+            workers.lock().unwrap().insert(thread::current().id());
+        });
+        assert!(workers.into_inner().unwrap().len() <= 2);
+        let cnt = AtomicUsize::new(0);
+        pool.broadcast(|_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 4);
+        pool.set_active_threads(0);
+        assert_eq!(pool.active_threads(), 1);
+        pool.set_active_threads(100);
+        assert_eq!(pool.active_threads(), 4);
+        assert_eq!(pool.refresh_cpu_quota(), cpu::available().min(4));
+        let pool = Pool::builder().track_cpu_quota(Duration::ZERO).build();
+        assert_eq!(pool.num_threads(), cpu::machine());
+        pool.set_active_threads(1);
+        pool.execute(10, |_| {});
+        assert_eq!(pool.active_threads(), cpu::available());
+    }
+
+    #[test]
+    fn test_autoscale() {
+        let interval = Duration::from_millis(20);
+        let pool = Pool::builder()
+            .num_threads(4)
+            .autoscale(1, interval)
+            .build();
+        assert_eq!(pool.active_threads(), 1);
+        // sustained load grows the pool to its full size
+        let start = std::time::Instant::now();
+        while pool.active_threads() < 4 && start.elapsed() < Duration::from_secs(10) {
+            pool.execute(8, |_| thread::sleep(Duration::from_millis(2)));
+        }
+        assert_eq!(pool.active_threads(), 4);
+        // and a few idle intervals shrink it back
+        thread::sleep(interval * 5);
+        pool.execute(1, |_| {});
+        assert_eq!(pool.active_threads(), 1);
+    }
+
+    #[test]
+    fn test_nested() {
+        for num_threads in [0, 1, 4] {
+            let pool = Pool::new(num_threads);
+            let cnt = AtomicUsize::new(0);
+            pool.execute(8, |_| {
+                pool.execute(8, |_| {
+                    cnt.fetch_add(1, Ordering::SeqCst);
+                });
+                assert!(pool.any(8, |i| i == 5));
+            });
+            assert_eq!(cnt.load(Ordering::SeqCst), 64);
+        }
+        let cnt = AtomicUsize::new(0);
+        crate::execute(4, |_| {
+            crate::execute(4, |_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 16);
+    }
+
+    #[test]
+    fn test_raw_pool() {
+        let mut pool = RawPool::new(raw::StdThreads, 3);
+        let cnt = AtomicUsize::new(0);
+        for num in [10, 0, 50] {
+            pool.execute(num, |_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        assert_eq!(cnt.load(Ordering::SeqCst), 60);
+        // a panic stops the job and is resumed on the caller, the thread
+        // survives it
+        let mut pool = RawPool::new(raw::StdThreads, 1);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pool.execute(4, |i| assert_ne!(i, 0));
+        }));
+        assert!(res.is_err());
+        pool.execute(3, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 63);
+        drop(pool);
+    }
+
+    #[test]
+    fn test_job_context() {
+        fn deep() -> Option<u64> {
+            current_job_context::<u64>()
+        }
+        let pool = Pool::new(3);
+        let cancel = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let seen = AtomicUsize::new(0);
+        pool.execute_with_context(cancel.clone(), 100, |i| {
+            let cancel = current_job_context::<std::sync::Arc<std::sync::atomic::AtomicBool>>();
+            let cancel = cancel.unwrap();
+            if i == 10 {
+                cancel.store(true, Ordering::SeqCst);
+            }
+            assert_eq!(deep(), None);
+            seen.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(cancel.load(Ordering::SeqCst));
+        assert_eq!(seen.load(Ordering::SeqCst), 100);
+        assert_eq!(deep(), None);
+        pool.execute_with_context(7u64, 20, |_| {
+            assert_eq!(deep(), Some(7));
+            // nested jobs see the outer context, unless they bring their own
+            pool.execute(2, |_| assert_eq!(deep(), Some(7)));
+            pool.execute_with_context(8u64, 2, |_| assert_eq!(deep(), Some(8)));
+            assert_eq!(deep(), Some(7));
+        });
+        pool.execute(20, |_| assert_eq!(deep(), None));
+        execute_with_context(String::from("request-1"), 5, |_| {
+            assert_eq!(
+                current_job_context::<String>().as_deref(),
+                Some("request-1")
+            );
+        });
+    }
+
+    #[test]
+    fn test_inline_threshold() {
+        let caller = thread::current().id();
+        let pool = Pool::builder().num_threads(3).inline_threshold(8).build();
+        let cnt = AtomicUsize::new(0);
+        pool.execute(7, |_| {
+            assert_eq!(thread::current().id(), caller);
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        pool.execute(0, |_| unreachable!());
+        assert_eq!(pool.stats_snapshot().jobs, 0);
+        pool.execute(8, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.into_inner(), 15);
+        assert_eq!(pool.stats_snapshot().jobs, 1);
+        // empty jobs never reach the workers
+        let pool = Pool::new(2);
+        pool.execute(0, |_| unreachable!());
+        assert_eq!(pool.stats_snapshot().jobs, 0);
+        // jobs for every worker still reach them with any threshold
+        let pool = Pool::builder()
+            .num_threads(2)
+            .inline_threshold(usize::MAX)
+            .build();
+        pool.execute(10, |_| assert_eq!(thread::current().id(), caller));
+        assert_eq!(pool.broadcast_map(|index| index), [0, 1]);
+    }
+
+    #[test]
+    fn test_zero_size() {
+        let caller = thread::current().id();
+        let mut raw = RawPool::new(raw::StdThreads, 0);
+        let pool = Pool::new(0);
+        let order = std::sync::Mutex::new(Vec::new());
+        raw.execute(5, |i| {
+            assert_eq!(thread::current().id(), caller);
+            order.lock().unwrap().push(i);
+        });
+        pool.execute(5, |i| {
+            assert_eq!(thread::current().id(), caller);
+            order.lock().unwrap().push(i);
+        });
+        assert_eq!(*order.lock().unwrap(), [0, 1, 2, 3, 4, 0, 1, 2, 3, 4]);
+        let cnt = AtomicUsize::new(0);
+        pool.scope(|s| {
+            s.spawn(|_| {
+                assert_eq!(thread::current().id(), caller);
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+        });
+        assert_eq!(cnt.into_inner(), 1);
+        pool.set_active_threads(4);
+        assert_eq!(pool.active_threads(), 1);
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn test_ffi() {
+        extern "C" fn add(user_data: *mut std::ffi::c_void, index: usize) {
+            let cnt = unsafe { &*(user_data as *const AtomicUsize) };
+            cnt.fetch_add(index, Ordering::SeqCst);
+        }
+        let cnt = AtomicUsize::new(0);
+        let ptr = &cnt as *const AtomicUsize as *mut std::ffi::c_void;
+        unsafe {
+            let pool = ffi::ieu_pool_new(2);
+            ffi::ieu_pool_execute(pool, 5, add, ptr);
+            ffi::ieu_pool_free(pool);
+            ffi::ieu_execute(5, add, ptr);
+        }
+        assert_eq!(cnt.load(Ordering::SeqCst), 20);
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_python() {
+        use pyo3::{prelude::*, types::PyModule};
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "ieu").unwrap();
+            python::register(&module).unwrap();
+            let locals = pyo3::types::PyDict::new(py);
+            locals.set_item("ieu", module).unwrap();
+            py.run(
+                c"seen = []\npool = ieu.Pool(2)\npool.execute(5, seen.append)\n",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            let mut seen = locals
+                .get_item("seen")
+                .unwrap()
+                .unwrap()
+                .extract::<Vec<usize>>()
+                .unwrap();
+            seen.sort();
+            assert_eq!(seen, [0, 1, 2, 3, 4]);
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_execute_blocking_async() {
+        let cnt = std::sync::Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new(2);
+        let job_cnt = cnt.clone();
+        pool.execute_blocking_async(100, move |_| {
+            job_cnt.fetch_add(1, Ordering::SeqCst);
+        })
+        .await;
+        assert_eq!(cnt.load(Ordering::SeqCst), 100);
+        crate::execute_blocking_async(0, |_| {}).await;
+        let job = tokio::spawn(pool.execute_blocking_async(10, |i| assert_ne!(i, 3)));
+        assert!(job.await.unwrap_err().is_panic());
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_log() {
+        struct Logger(std::sync::Mutex<Vec<String>>);
+
+        impl log::Log for Logger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: Logger = Logger(std::sync::Mutex::new(Vec::new()));
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+        Pool::new(2).execute_labeled("resize", 10, |_| {});
+        let records = LOGGER.0.lock().unwrap();
+        let ours = records.iter().filter(|r| r.contains("(resize)"));
+        assert!(ours
+            .clone()
+            .any(|r| r.ends_with("submitted with 10 indices")));
+        assert!(ours.clone().any(|r| r.contains("started on worker")));
+        assert!(ours.clone().any(|r| r.contains("completed in")));
+    }
+
+    #[cfg(feature = "puffin")]
+    #[test]
+    fn test_puffin() {
+        let names = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = names.clone();
+        puffin::GlobalProfiler::lock().add_sink(Box::new(move |frame| {
+            let scopes = frame.scope_delta.iter();
+            let mut names = sink.lock().unwrap();
+            names.extend(
+                scopes.filter_map(|scope| scope.scope_name.as_ref().map(|n| n.to_string())),
+            );
+        }));
+        puffin::set_scopes_on(true);
+        Pool::new(2).execute_labeled("resize", 10, |_| thread::sleep(Duration::from_millis(1)));
+        puffin::GlobalProfiler::lock().new_frame();
+        let names = names.lock().unwrap();
+        assert!(names.iter().any(|name| name == "ieu_job"));
+        assert!(names.iter().any(|name| name == "ieu_worker"));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_install() {
+        let pool = Pool::new(3);
+        let (threads, sum) =
+            pool.install(|| (rayon::current_num_threads(), rayon::join(|| 1, || 2)));
+        assert_eq!((threads, sum), (3, (1, 2)));
+        assert_eq!(install(|| rayon::join(|| 'a', || 'b')), ('a', 'b'));
+    }
+
+    #[test]
+    fn test_par_iter() {
+        let cnt = AtomicUsize::new(0);
+        (0..100).par_for_each(|i| {
+            cnt.fetch_add(i, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 4950);
+        let v = (0..100).collect::<Vec<_>>();
+        let doubled = v.par_iter().map(|x| x * 2).collect::<Vec<_>>();
+        assert_eq!(doubled, v.iter().map(|x| x * 2).collect::<Vec<_>>());
+        let strings = v[..3].par_iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(strings, ["0", "1", "2"]);
+        assert!((5..5).par_iter().is_empty());
+    }
+
+    #[test]
+    fn test_try_for_each() {
+        let pool = Pool::new(4);
+        let cnt = AtomicUsize::new(0);
+        let ret = pool.try_for_each(1_000_000, |i| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+            match i {
+                10 => Err(i),
+                _ => Ok(()),
+            }
+        });
+        assert_eq!(ret, Err(10));
+        assert!(cnt.load(Ordering::SeqCst) < 1_000_000);
+        assert!(pool.try_for_each(100, |i| i < 100));
+        assert!(!try_for_each(100, |i| i != 50));
+        let found = pool.try_for_each(100_000, |i| {
+            match i * i > 1000 {
+                true => std::ops::ControlFlow::Break(i),
+                false => std::ops::ControlFlow::Continue(()),
+            }
+        });
+        // every index past 31 breaks, which one wins depends on the timing
+        assert!(matches!(found, std::ops::ControlFlow::Break(i) if i > 31));
+        cnt.store(0, Ordering::SeqCst);
+        let ret = Pool::new(0).try_for_each(10, |i| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+            i < 5
+        });
+        assert!(!ret);
+        assert_eq!(cnt.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn test_try_for_each_counted() {
+        let pool = Pool::new(4);
+        let ran = (0..100_000)
+            .map(|_| std::sync::atomic::AtomicBool::new(false))
+            .collect::<Vec<_>>();
+        let (ret, count) = pool.try_for_each_counted(ran.len(), |i| {
+            ran[i].store(true, Ordering::SeqCst);
+            i != 500
+        });
+        assert!(!ret);
+        // exactly the indices below the count ran
+        assert!(count > 500 && count < ran.len());
+        assert!(ran
+            .iter()
+            .enumerate()
+            .all(|(i, ran)| ran.load(Ordering::SeqCst) == (i < count)));
+        assert_eq!(pool.try_for_each_counted(100, |_| true), (true, 100));
+        assert_eq!(Pool::new(0).try_for_each_counted(10, |i| i < 5), (false, 6));
+        let (ret, count) = try_for_each_counted(10, |i| {
+            match i {
+                0 => Err(i),
+                _ => Ok(()),
+            }
+        });
+        assert_eq!(ret, Err(0));
+        assert!(count >= 1);
+        // the count of an aborted submitted job covers the indices that ran
+        let cnt = std::sync::Arc::new(AtomicUsize::new(0));
+        let counter = cnt.clone();
+        let job = pool.submit(1000, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(1));
+        });
+        thread::sleep(Duration::from_millis(10));
+        let count = job.abort_handle().abort();
+        assert!(matches!(job.wait(), JobResult::Aborted));
+        assert_eq!(cnt.load(Ordering::SeqCst), count);
+    }
+
+    #[test]
+    fn test_find() {
+        let pool = Pool::new(4);
+        let data = (0..10_000).map(|i| i * 3).collect::<Vec<_>>();
+        assert_eq!(pool.find_any(data.len(), |i| data[i] == 300), Some(100));
+        assert_eq!(pool.find_any(data.len(), |i| data[i] == 301), None);
+        assert_eq!(
+            pool.find_map_any(data.len(), |i| (data[i] == 30).then(|| format!("{}", i))),
+            Some("10".to_string())
+        );
+        assert!(pool.any(data.len(), |i| data[i] % 2 == 1));
+        assert!(!pool.all(data.len(), |i| data[i] % 2 == 0));
+        assert!(all(data.len(), |i| data[i] % 3 == 0));
+        assert!(!any(data.len(), |i| data[i] == 1));
+        assert_eq!(find_any(5, |i| i == 4), Some(4));
+        assert_eq!(find_map_any(5, |_| None::<()>), None);
+    }
+
+    #[test]
+    fn test_reduce() {
+        let pool = Pool::new(3);
+        let data = (1..=1000u64).collect::<Vec<_>>();
+        assert_eq!(pool.par_sum(&data), 500500);
+        assert_eq!(pool.par_sum::<u64>(&[]), 0);
+        assert_eq!(par_sum(&[1.5f64, 2.5]), 4.0);
+        let words = ["pear", "fig", "banana", "kiwi", "apple"];
+        assert_eq!(
+            pool.par_min_by(&words, |a, b| a.len().cmp(&b.len())),
+            Some(&"fig")
+        );
+        assert_eq!(
+            par_max_by(&words, |a, b| a.len().cmp(&b.len())),
+            Some(&"banana")
+        );
+        // ties resolve to the first minimum and the last maximum like std
+        let pairs = [(1, 'a'), (0, 'b'), (0, 'c'), (1, 'd')];
+        assert_eq!(par_min_by(&pairs, |a, b| a.0.cmp(&b.0)), Some(&(0, 'b')));
+        assert_eq!(par_max_by(&pairs, |a, b| a.0.cmp(&b.0)), Some(&(1, 'd')));
+        assert_eq!(par_min_by(&[] as &[u8], |a, b| a.cmp(b)), None);
+    }
+
+    #[test]
+    fn test_histogram() {
+        let pool = Pool::new(3);
+        let hist = pool.par_histogram(1000, 10, |i| i % 10);
+        assert_eq!(hist, vec![100; 10]);
+        assert_eq!(par_histogram(7, 2, |i| (i >= 5) as usize), vec![5, 2]);
+        let words = ["a", "bb", "cc", "ddd"];
+        let lens = par_accumulate(
+            words.len(),
+            std::collections::BTreeMap::new,
+            |map, i| *map.entry(words[i].len()).or_insert(0) += 1,
+            |acc, map| {
+                for (len, count) in map {
+                    *acc.entry(len).or_insert(0) += count;
+                }
+            },
+        );
+        assert_eq!(lens.into_iter().collect::<Vec<_>>(), vec![
+            (1, 1),
+            (2, 2),
+            (3, 1)
+        ]);
+    }
+
+    #[test]
+    fn test_map_reduce() {
+        #[derive(Debug, PartialEq)]
+        struct MinMax(Option<(usize, usize)>);
+
+        impl Combiner for MinMax {
+            type Item = usize;
+
+            fn identity() -> MinMax {
+                MinMax(None)
+            }
+
+            fn accumulate(&mut self, item: usize) {
+                self.merge(MinMax(Some((item, item))));
+            }
+
+            fn merge(&mut self, other: MinMax) {
+                self.0 = match (self.0, other.0) {
+                    (Some((lo, hi)), Some((olo, ohi))) => Some((lo.min(olo), hi.max(ohi))),
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+
+        let pool = Pool::new(3);
+        let range = pool.map_reduce(1000, |i| (i * 7919) % 1000 + 5, MinMax::identity);
+        assert_eq!(range, MinMax(Some((5, 1004))));
+        assert_eq!(map_reduce(0, |i| i, MinMax::identity), MinMax(None));
+    }
+
+    #[test]
+    fn test_execute_u64() {
+        let pool = Pool::new(3);
+        let sum = std::sync::atomic::AtomicU64::new(0);
+        pool.execute_u64(1000, |i| {
+            sum.fetch_add(i, Ordering::SeqCst);
+        });
+        assert_eq!(sum.load(Ordering::SeqCst), 999 * 1000 / 2);
+        // more indices than claims, as for domains past `usize::MAX`
+        let seen = (0..1000).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        pool.run_u64(1000, 7, |i| {
+            seen[i as usize].fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(seen.iter().all(|cnt| cnt.load(Ordering::SeqCst) == 1));
+        execute_u64(0, |_| unreachable!());
+        // a stopped job bumps the claim counter past its end, which must not
+        // wrap around with a claim count near the cap
+        let first = AtomicUsize::new(0);
+        let res = std::panic::catch_unwind(|| {
+            pool.execute_u64(u64::MAX, |i| {
+                if i == 0 {
+                    first.fetch_add(1, Ordering::SeqCst);
+                }
+                assert!(i < 64);
+            })
+        });
+        assert!(res.is_err());
+        assert_eq!(first.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_par_binary_search() {
+        let haystack = (0..10_000).map(|i| i * 3).collect::<Vec<u32>>();
+        let needles = (0..1000).rev().map(|i| i * 31).collect::<Vec<u32>>();
+        let pool = Pool::new(3);
+        let found = pool.par_binary_search(&haystack, &needles);
+        let points = pool.par_partition_points(&haystack, &needles, |x, needle| x < needle);
+        for (i, needle) in needles.iter().enumerate() {
+            assert_eq!(found[i], haystack.binary_search(needle));
+            assert_eq!(points[i], haystack.partition_point(|x| x < needle));
+        }
+        assert_eq!(par_binary_search(&[1, 3], &[3, 2]), [Ok(1), Err(1)]);
+        assert!(par_partition_points(&[1], &[] as &[u8], |_, _| true).is_empty());
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    #[test]
+    fn test_execute_timed() {
+        let pool = Pool::new(3);
+        let histogram = pool.execute_timed(200, |i| {
+            if i % 100 == 7 {
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+        assert_eq!(histogram.len(), 200);
+        // the two slow indices are the top 1%
+        assert!(histogram.value_at_quantile(0.999) >= 5_000_000);
+        assert!(histogram.value_at_quantile(0.9) < 5_000_000);
+        let histogram = execute_batches_timed(10, 3, |range| assert!(range.len() <= 3));
+        assert_eq!(histogram.len(), 4);
+        assert!(execute_timed(0, |_| {}).is_empty());
+    }
+
+    #[test]
+    fn test_par_reduce_buffers() {
+        let pool = Pool::new(3);
+        // per-bucket sums over more elements than one chunk of the combine
+        let len = 10_000;
+        let sums = pool.par_reduce_buffers(
+            100_000,
+            len,
+            0u64,
+            |buffer, i| buffer[i * 7 % len] += i as u64,
+            |acc, other| *acc += other,
+        );
+        let mut expected = vec![0u64; len];
+        for i in 0..100_000 {
+            expected[i * 7 % len] += i as u64;
+        }
+        assert_eq!(sums, expected);
+        let max = par_reduce_buffers(
+            10,
+            2,
+            i32::MIN,
+            |buffer, i| buffer[i % 2] = buffer[i % 2].max(i as i32),
+            |acc, other| *acc = (*acc).max(*other),
+        );
+        assert_eq!(max, [8, 9]);
+        let empty = pool.par_reduce_buffers(0, 3, 1.5, |_, _| unreachable!(), |_, _| {});
+        assert_eq!(empty, [1.5; 3]);
+    }
+
+    #[cfg(all(feature = "perf", target_os = "linux"))]
+    #[test]
+    fn test_execute_perf() {
+        let pool = Pool::new(3);
+        let cnt = AtomicUsize::new(0);
+        let perf = pool.execute_perf(1000, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 1000);
+        assert_eq!(perf.workers.len(), 3);
+        // the counters may not be available where the tests run
+        if perf.workers.iter().any(Option::is_some) {
+            assert!(perf.total().instructions > 0);
+        }
+        let perf = execute_perf(0, |_| unreachable!());
+        assert_eq!(perf.total(), PerfCounts::default());
+    }
+
+    #[test]
+    fn test_par_hash_tree() {
+        let fnv = |bytes: &[u8]| {
+            bytes.iter().fold(0xcbf29ce484222325u64, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+        };
+        let node = |a: &u64, b: &u64| fnv(&[a.to_le_bytes(), b.to_le_bytes()].concat());
+        let data = (0..100_000u32).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+        let pool = Pool::new(3);
+        let leaves = data.chunks(1000).map(fnv).collect::<Vec<u64>>();
+        assert_eq!(pool.par_hash_chunks(&data, 1000, fnv), leaves);
+        // 100 leaves, so levels of 50, 25, 13 with one moved up, 7, 4, 2 and 1
+        let mut level = leaves;
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| pair.get(1).map_or(pair[0], |right| node(&pair[0], right)))
+                .collect();
+        }
+        assert_eq!(pool.par_hash_tree(&data, 1000, fnv, node), level[0]);
+        assert_eq!(par_hash_tree(&[], 1000, fnv, node), fnv(&[]));
+        assert_eq!(par_hash_chunks(b"abc", 0, |chunk| chunk.to_vec()), [
+            b"a", b"b", b"c"
+        ]);
+        assert!(par_hash_chunks(&[], 4, fnv).is_empty());
+    }
+
+    #[test]
+    fn test_par_windows_with_halo() {
+        let data = (0..10_000)
+            .map(|i| (i * i % 101) as i64)
+            .collect::<Vec<i64>>();
+        // a 5 point sum, with the missing neighbours at the ends as 0
+        let expected = (0..data.len())
+            .map(|i| {
+                (i.saturating_sub(2)..(i + 3).min(data.len()))
+                    .map(|j| data[j])
+                    .sum()
+            })
+            .collect::<Vec<i64>>();
+        let pool = Pool::new(3);
+        for chunk in [0, 1, 7, 1000, 20_000] {
+            let mut out = vec![0; data.len()];
+            pool.par_windows_with_halo(&data, &mut out, chunk, 2, |window, out| {
+                for (i, out) in out.iter_mut().enumerate() {
+                    *out = (-2..3)
+                        .filter_map(|offset| window.get(i as isize + offset))
+                        .sum();
+                }
+            });
+            assert_eq!(out, expected);
+        }
+        // rows of a 4 wide grid, two rows per chunk with a row of halo
+        let grid = (0..24).collect::<Vec<u32>>();
+        let mut out = vec![0; 24];
+        par_windows_with_halo(&grid, &mut out, 8, 4, |window, out| {
+            assert_eq!(window.before.len(), if window.start == 0 { 0 } else { 4 });
+            assert_eq!(window.after.len(), if window.start == 16 { 0 } else { 4 });
+            out.fill(window.start as u32);
+        });
+        assert_eq!(out[..8], [0; 8]);
+        assert_eq!(out[16..], [16; 8]);
+    }
+
+    #[test]
+    fn test_par_find_all() {
+        let mut rng = rng_for_index(11, 0);
+        // a small alphabet, so there are matches across chunk boundaries
+        let haystack = (0..100_000)
+            .map(|_| b"ab"[rng.below(2) as usize])
+            .collect::<Vec<u8>>();
+        let pool = Pool::new(3);
+        for needle in [&b"a"[..], b"abba", b"aaaaaa", b"babababab"] {
+            let naive = (0..=haystack.len() - needle.len())
+                .filter(|&i| haystack[i..].starts_with(needle))
+                .collect::<Vec<usize>>();
+            assert_eq!(pool.par_find_all(&haystack, needle), naive);
+        }
+        assert_eq!(par_find_all(b"aaaa", b"aa"), [0, 1, 2]);
+        assert_eq!(par_find_all(b"ab", b""), [0, 1, 2]);
+        assert!(par_find_all(b"ab", b"abc").is_empty());
+    }
+
+    #[test]
+    fn test_par_merge() {
+        let mut rng = rng_for_index(7, 0);
+        // (key, id) pairs sorted by key only, so ties show whether the merge
+        // is stable across runs
+        let runs = (0..5)
+            .map(|run| {
+                let mut items = (0..rng.next_u64() % 300)
+                    .map(|i| (rng.next_u64() % 50, run * 1000 + i))
+                    .collect::<Vec<_>>();
+                items.sort_by_key(|item| item.0);
+                items
+            })
+            .collect::<Vec<_>>();
+        let slices = runs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let mut expected = runs.concat();
+        expected.sort_by_key(|item| item.0);
+        for threads in [1, 3] {
+            let pool = Pool::new(threads);
+            let mut out = vec![(0, 0); expected.len()];
+            pool.par_merge(&slices, &mut out, |a, b| a.0.cmp(&b.0));
+            assert_eq!(out, expected);
+        }
+        let mut out = [0; 3];
+        par_merge(&[&[2][..], &[], &[1, 3]], &mut out, |a, b| a.cmp(b));
+        assert_eq!(out, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_tree_reduce() {
+        let data = (0..100_000)
+            .map(|i| 1.0 / (i as f64 + 1.0))
+            .collect::<Vec<_>>();
+        let expected = par_sum_tree(&data);
+        for size in [0, 1, 3, 8] {
+            let pool = Pool::new(size);
+            for _ in 0..5 {
+                assert_eq!(pool.par_sum_tree(&data).to_bits(), expected.to_bits());
+            }
+        }
+        let concat = par_tree_reduce(
+            3000,
+            |i| vec![i],
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+        );
+        assert_eq!(concat, Some((0..3000).collect::<Vec<_>>()));
+        assert_eq!(par_tree_reduce(0, |i| i, |a, b| a + b), None);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let pool = Pool::new(4);
+        for costs in [
+            vec![1; 100],
+            vec![0; 10],
+            (0..500).map(|i| i * i).collect(),
+            vec![],
+        ] {
+            let hits = (0..costs.len())
+                .map(|_| AtomicUsize::new(0))
+                .collect::<Vec<_>>();
+            pool.execute_weighted_by(
+                costs.len(),
+                |i| costs[i],
+                |i| {
+                    hits[i].fetch_add(1, Ordering::Relaxed);
+                },
+            );
+            pool.execute_weighted(&costs, |i| {
+                hits[i].fetch_add(1, Ordering::Relaxed);
+            });
+            assert!(hits.iter().all(|h| h.load(Ordering::Relaxed) == 2));
+        }
+        // without any weight the indices are split evenly rather than one
+        // range per index
+        assert_eq!(schedule::weighted_bounds(&[0; 1000], 4), [
+            0, 250, 500, 750, 1000
+        ]);
+        assert_eq!(schedule::weighted_bounds(&[0; 3], 4), [0, 1, 2, 3]);
+        let cnt = AtomicUsize::new(0);
+        pool.execute_weighted(&vec![0; 100_000], |_| {
+            cnt.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(cnt.into_inner(), 100_000);
+
+        let sum = AtomicUsize::new(0);
+        execute_weighted_by(
+            10,
+            |i| if i == 3 { 1000 } else { 1 },
+            |i| {
+                sum.fetch_add(i, Ordering::Relaxed);
+            },
+        );
+        assert_eq!(sum.into_inner(), 45);
+    }
+
+    #[test]
+    fn test_strided() {
+        let pool = Pool::new(3);
+        let hits = (0..100).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        pool.execute_strided(1, 3, 33, |i| {
+            hits[i].fetch_add(1, Ordering::SeqCst);
+        });
+        for (i, hit) in hits.iter().enumerate() {
+            assert_eq!(hit.load(Ordering::SeqCst), (i % 3 == 1) as usize);
+        }
+        let sum = AtomicUsize::new(0);
+        execute_strided(10, 0, 4, |i| {
+            sum.fetch_add(i, Ordering::SeqCst);
+        });
+        assert_eq!(sum.into_inner(), 40);
+    }
+
+    #[test]
+    fn test_rev() {
+        // a single worker runs the indices in claim order
+        let pool = Pool::new(1);
+        let order = std::sync::Mutex::new(Vec::new());
+        pool.execute_rev(5, |i| order.lock().unwrap().push(i));
+        assert_eq!(order.into_inner().unwrap(), [4, 3, 2, 1, 0]);
+        let sum = AtomicUsize::new(0);
+        execute_rev(10, |i| {
+            sum.fetch_add(i, Ordering::SeqCst);
+        });
+        assert_eq!(sum.into_inner(), 45);
+        execute_rev(0, |_| unreachable!());
+    }
+
+    #[test]
+    fn test_shuffled() {
+        let pool = Pool::new(1);
+        for num in [0, 1, 2, 10, 97, 1000] {
+            let order = std::sync::Mutex::new(Vec::new());
+            pool.execute_shuffled(num, |i| order.lock().unwrap().push(i));
+            let mut order = order.into_inner().unwrap();
+            if num >= 10 {
+                assert_ne!(order, (0..num).collect::<Vec<_>>());
+            }
+            order.sort();
+            assert_eq!(order, (0..num).collect::<Vec<_>>());
+        }
+        let sum = AtomicUsize::new(0);
+        execute_shuffled(10, |i| {
+            sum.fetch_add(i, Ordering::SeqCst);
+        });
+        assert_eq!(sum.into_inner(), 45);
+    }
+
+    #[test]
+    fn test_ordered() {
+        let pool = Pool::new(4);
+        let ordered = Ordered::new();
+        let out = std::sync::Mutex::new(Vec::new());
+        pool.execute(200, |i| {
+            let square = i * i;
+            ordered.run(i, || out.lock().unwrap().push(square));
+        });
+        assert_eq!(
+            out.into_inner().unwrap(),
+            (0..200).map(|i| i * i).collect::<Vec<_>>()
+        );
+        let ordered = Ordered::starting_at(50);
+        let out = std::sync::Mutex::new(Vec::new());
+        execute_from(&Checkpoint::new(50), 100, |i| {
+            ordered.run(i, || out.lock().unwrap().push(i))
+        });
+        assert_eq!(out.into_inner().unwrap(), (50..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let pool = Pool::new(4);
+        let seen = (0..4).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        let onces = AtomicUsize::new(0);
+        pool.broadcast(|team| {
+            assert_eq!(team.num_threads(), 4);
+            seen[team.index()].fetch_add(1, Ordering::SeqCst);
+            for round in 0..3 {
+                team.single(|| onces.fetch_add(1, Ordering::SeqCst));
+                assert_eq!(onces.load(Ordering::SeqCst), round + 1);
+                team.barrier();
+            }
+            team.master(|| assert_eq!(team.index(), 0));
+        });
+        assert!(seen.iter().all(|s| s.load(Ordering::SeqCst) == 1));
+        assert_eq!(onces.into_inner(), 3);
+        let cnt = AtomicUsize::new(0);
+        Pool::new(0).broadcast(|team| {
+            team.single(|| cnt.fetch_add(1, Ordering::SeqCst));
+        });
+        assert_eq!(cnt.into_inner(), 1);
+        broadcast(|team| {
+            team.barrier();
+        });
+    }
+
+    #[test]
+    fn test_parallel_for() {
+        let hits = (0..100).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        parallel_for!(i in 10..100 => {
+            hits[i].fetch_add(1, Ordering::SeqCst);
+        });
+        parallel_for!(i in 0..hits.len(), chunk = 7 => {
+            hits[i].fetch_add(1, Ordering::SeqCst);
+        });
+        for (i, hit) in hits.iter().enumerate() {
+            assert_eq!(hit.load(Ordering::SeqCst), 1 + (i >= 10) as usize);
+        }
+        parallel_for!(i in 0..0, chunk = 0 => {
+            hits[i].fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    #[test]
+    fn test_sticky() {
+        let pool = Pool::new(4);
+        for _ in 0..5 {
+            let owners = std::sync::Mutex::new(vec![None; 1000]);
+            pool.execute_sticky(1000, |i| {
+                owners.lock().unwrap()[i] = Some(thread::current().id());
+            });
+            // every range of 250 ran whole on a single worker
+            let owners = owners.into_inner().unwrap();
+            for range in owners.chunks(250) {
+                assert!(range[0].is_some());
+                assert!(range.iter().all(|owner| *owner == range[0]));
+            }
+        }
+        let sum = AtomicUsize::new(0);
+        execute_sticky(7, |i| {
+            sum.fetch_add(i, Ordering::Relaxed);
+        });
+        assert_eq!(sum.into_inner(), 21);
+    }
+
+    #[test]
+    fn test_join() {
+        let pool = Pool::new(2);
+        let data = [1, 2, 3, 4];
+        let (a, b) = pool.join(|| data[..2].iter().sum::<i32>(), || data[2..].len());
+        assert_eq!((a, b), (3, 2));
+        assert_eq!(join(|| "a", || 1), ("a", 1));
+    }
+
+    #[test]
+    fn test_spawn() {
+        let pool = Pool::new(2);
+        let (tx, rx) = std::sync::mpsc::channel();
+        for i in 0..4 {
+            let tx = tx.clone();
+            pool.spawn(move || tx.send(i).unwrap());
+        }
+        let cnt = AtomicUsize::new(0);
+        pool.execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut got = rx.iter().take(4).collect::<Vec<_>>();
+        got.sort();
+        assert_eq!(got, [0, 1, 2, 3]);
+        assert_eq!(cnt.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_progress() {
+        let pool = Pool::new(3);
+        let reports = std::sync::Mutex::new(Vec::new());
+        pool.execute_with_progress(
+            1000,
+            100,
+            |_| {},
+            |done, total| reports.lock().unwrap().push((done, total)),
+        );
+        let reports = reports.into_inner().unwrap();
+        assert!(reports.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(reports
+            .iter()
+            .all(|&(done, total)| done.is_multiple_of(100) && total == 1000));
+        assert_eq!(reports.last(), Some(&(1000, 1000)));
+        let last = AtomicUsize::new(0);
+        execute_with_progress(
+            7,
+            3,
+            |_| {},
+            |done, _| {
+                last.fetch_max(done, Ordering::SeqCst);
+            },
+        );
+        assert_eq!(last.into_inner(), 7);
+    }
+
+    #[test]
+    fn test_execute_from() {
+        // a single worker completes indices in order, so the mark always
+        // sits right at the index being run
+        let pool = Pool::new(1);
+        let checkpoint = Checkpoint::new(10);
+        pool.execute_from(&checkpoint, 100, |i| assert_eq!(checkpoint.get(), i));
+        assert_eq!(checkpoint.get(), 100);
+        let pool = Pool::new(4);
+        let checkpoint = Checkpoint::new(0);
+        let hits = (0..1000).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        pool.execute_from(&checkpoint, 1000, |i| {
+            hits[i].fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(checkpoint.get(), 1000);
+        pool.execute_from(&Checkpoint::new(400), 1000, |i| {
+            hits[i].fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(hits[..400].iter().all(|h| h.load(Ordering::SeqCst) == 1));
+        assert!(hits[400..].iter().all(|h| h.load(Ordering::SeqCst) == 2));
+        // the other job left this checkpoint alone
+        assert_eq!(checkpoint.get(), 1000);
+        // an interrupted job resumes from its own checkpoint
+        let checkpoint = Checkpoint::new(0);
+        let res = std::panic::catch_unwind(|| {
+            Pool::new(1).execute_from(&checkpoint, 100, |i| assert!(i < 30));
+        });
+        assert!(res.is_err());
+        assert_eq!(checkpoint.get(), 30);
+        let seen = (0..100).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        pool.execute_from(&checkpoint, 100, |i| {
+            seen[i].fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(checkpoint.get(), 100);
+        assert!(seen[..30].iter().all(|h| h.load(Ordering::SeqCst) == 0));
+        assert!(seen[30..].iter().all(|h| h.load(Ordering::SeqCst) == 1));
+        pool.execute_from(&Checkpoint::new(5), 3, |_| unreachable!());
+        execute_from(&Checkpoint::new(0), 10, |_| {});
+    }
+
+    #[test]
+    fn test_is_worker_thread() {
+        let pool = Pool::new(2);
+        assert!(!is_worker_thread());
+        pool.execute(4, |_| assert!(is_worker_thread()));
+        let (tx, rx) = std::sync::mpsc::channel();
+        pool.spawn(move || tx.send(is_worker_thread()).unwrap());
+        assert!(rx.recv().unwrap());
+        Pool::new(0).execute(2, |_| assert!(!is_worker_thread()));
+        assert!(!thread::spawn(is_worker_thread).join().unwrap());
+    }
+
+    #[test]
+    fn test_debug() {
+        let pool = Pool::new(2);
+        pool.execute(2, |_| {});
+        let state = format!("{pool:?}");
+        assert!(state.starts_with("Pool { num_threads: 2, alive_workers: [0, 1], jobs: []"));
+        let (tx, rx) = std::sync::mpsc::channel();
+        thread::scope(|s| {
+            s.spawn(|| {
+                pool.execute(4, |i| {
+                    if i == 0 {
+                        tx.send(()).unwrap();
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                })
+            });
+            rx.recv().unwrap();
+            let state = pool.dump_state();
+            assert!(state.contains("num: 4"), "{state}");
+            assert!(state.contains("workers_inside: "), "{state}");
+        });
+    }
+
+    #[test]
+    fn test_static_pool() {
+        static_pool!(static DEFAULT = "IEU_TEST_UNSET");
+        static_pool! {
+            /// a pool with its own size
+            static SIZED = "IEU_TEST_UNSET", |builder| builder.num_threads(3);
+        }
+        assert_eq!(SIZED.num_threads(), 3);
+        assert!(DEFAULT.num_threads() >= 1);
+        assert!(std::ptr::eq(SIZED.get(), &*SIZED));
+        let cnt = AtomicUsize::new(0);
+        SIZED.execute(10, |_| {
+            cnt.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(cnt.into_inner(), 10);
+    }
+
+    #[test]
+    fn test_wait_idle() {
+        let pool = Pool::new(2);
+        pool.wait_idle();
+        let cnt = std::sync::Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let cnt = cnt.clone();
+            pool.execute_detached(
+                20,
+                move |_| {
+                    thread::sleep(Duration::from_millis(1));
+                    cnt.fetch_add(1, Ordering::SeqCst);
+                },
+                |_| {},
+            );
+        }
+        let spawned = cnt.clone();
+        pool.spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            spawned.fetch_add(1, Ordering::SeqCst);
+        });
+        pool.wait_idle();
+        assert_eq!(cnt.load(Ordering::SeqCst), 61);
+        let started = std::sync::atomic::AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                pool.execute(4, |_| {
+                    started.store(true, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    cnt.fetch_add(1, Ordering::SeqCst);
+                })
+            });
+            while !started.load(Ordering::SeqCst) {
+                thread::yield_now();
+            }
+            pool.wait_idle();
+            assert_eq!(cnt.load(Ordering::SeqCst), 65);
+        });
+        let done = cnt.clone();
+        execute_detached(
+            5,
+            move |_| {
+                done.fetch_add(1, Ordering::SeqCst);
+            },
+            |_| {},
+        );
+        wait_idle();
+        assert_eq!(cnt.load(Ordering::SeqCst), 70);
+    }
+
+    #[test]
+    fn test_execute_detached() {
+        let pool = Pool::new(3);
+        let (tx, rx) = std::sync::mpsc::channel();
+        for num in [0, 1, 100] {
+            let cnt = std::sync::Arc::new(AtomicUsize::new(0));
+            let tx = tx.clone();
+            let done = cnt.clone();
+            pool.execute_detached(
+                num,
+                move |_| {
+                    cnt.fetch_add(1, Ordering::SeqCst);
+                },
+                move |res: Result<(), _>| {
+                    assert!(res.is_ok());
+                    tx.send(done.load(Ordering::SeqCst)).unwrap()
+                },
+            );
+            assert_eq!(rx.recv().unwrap(), num);
+        }
+        execute_detached(5, |_| {}, move |_| tx.send(5).unwrap());
+        assert_eq!(rx.recv().unwrap(), 5);
+        // a panicking index still completes the job, with its payload
+        let (tx, rx) = std::sync::mpsc::channel();
+        pool.execute_detached(
+            100,
+            |i| assert_ne!(i, 10),
+            move |res| tx.send(res.is_err()).unwrap(),
+        );
+        assert!(rx.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+
+    #[test]
+    fn test_max_queued_tasks() {
+        use std::sync::{atomic::AtomicBool, Arc};
+        // the only worker busy until `release`, so two more tasks fill the queue
+        let occupy = |pool: &Pool| {
+            let (started, release) = (
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(AtomicBool::new(false)),
+            );
+            let (s, r) = (started.clone(), release.clone());
+            pool.spawn(move || {
+                s.store(true, Ordering::SeqCst);
+                while !r.load(Ordering::SeqCst) {
+                    thread::yield_now();
+                }
+            });
+            while !started.load(Ordering::SeqCst) {
+                thread::yield_now();
+            }
+            pool.spawn(|| {});
+            pool.spawn(|| {});
+            release
+        };
+        let pool = Pool::builder()
+            .num_threads(1)
+            .max_queued_tasks(2, QueuePolicy::Reject)
+            .build();
+        let release = occupy(&pool);
+        assert_eq!(pool.try_spawn(|| {}), Err(QueueFull));
+        assert!(pool.try_submit(10, |_| {}).is_err());
+        assert!(
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pool.spawn(|| {}))).is_err()
+        );
+        release.store(true, Ordering::SeqCst);
+        pool.wait_idle();
+        let job = pool.try_submit(10, |_| {}).unwrap();
+        assert!(matches!(job.wait(), JobResult::Completed));
+
+        let pool = Pool::builder()
+            .num_threads(1)
+            .max_queued_tasks(2, QueuePolicy::RunInline)
+            .build();
+        let release = occupy(&pool);
+        let caller = thread::current().id();
+        let ran = Arc::new(AtomicBool::new(false));
+        let r = ran.clone();
+        pool.spawn(move || {
+            assert_eq!(thread::current().id(), caller);
+            r.store(true, Ordering::SeqCst);
+        });
+        assert!(ran.load(Ordering::SeqCst));
+        release.store(true, Ordering::SeqCst);
+        pool.wait_idle();
+
+        let pool = Pool::builder()
+            .num_threads(1)
+            .max_queued_tasks(2, QueuePolicy::Block)
+            .build();
+        let release = occupy(&pool);
+        let ran = Arc::new(AtomicBool::new(false));
+        thread::scope(|s| {
+            s.spawn(|| {
+                let r = ran.clone();
+                pool.spawn(move || r.store(true, Ordering::SeqCst));
+            });
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!(pool.queued_tasks(), 2);
+            release.store(true, Ordering::SeqCst);
+        });
+        pool.wait_idle();
+        assert!(ran.load(Ordering::SeqCst));
+        assert_eq!("inline".parse(), Ok(QueuePolicy::RunInline));
+    }
+
+    #[test]
+    fn test_submit() {
+        let pool = Pool::new(2);
+        let cnt = std::sync::Arc::new(AtomicUsize::new(0));
+        let counter = cnt.clone();
+        let job = pool.submit(100, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(matches!(job.wait(), JobResult::Completed));
+        assert_eq!(cnt.load(Ordering::SeqCst), 100);
+        let job = pool.submit(0, |_| unreachable!());
+        assert!(job.is_finished());
+        assert_eq!(job.progress(), (0, 0));
+        assert!(matches!(job.wait(), JobResult::Completed));
+
+        let counter = cnt.clone();
+        let job = pool.submit(1000, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(1));
+        });
+        let abort = job.abort_handle();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            abort.abort();
+        });
+        while !job.is_finished() {
+            let (done, total) = job.progress();
+            assert!(done <= total && total == 1000);
+            thread::sleep(Duration::from_millis(1));
+        }
+        let progress = job.progress();
+        assert!(matches!(job.wait(), JobResult::Aborted));
+        // nothing runs after the job ended
+        let ran = cnt.load(Ordering::SeqCst) - 100;
+        assert!(ran < 1000);
+        assert_eq!(progress, (ran, 1000));
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(cnt.load(Ordering::SeqCst) - 100, ran);
+
+        let job = pool.submit(1000, |i| assert_ne!(i, 3));
+        match job.wait() {
+            JobResult::Panicked(payload) => assert!(payload.downcast_ref::<String>().is_some()),
+            result => panic!("{result:?}"),
+        }
+        // the panicking index doesn't count as run
+        let single = Pool::new(1);
+        let job = single.submit(10, |i| assert_ne!(i, 3));
+        while !job.is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(job.progress(), (3, 10));
+        // dropping the handle stops the job, at most the running indices
+        // finish after that
+        let counter = cnt.clone();
+        let job = pool
+            .submit(1000, move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(1));
+            })
+            .abort_on_drop();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _job = job;
+            thread::sleep(Duration::from_millis(20));
+            panic!("the scope unwinds");
+        }));
+        assert!(result.is_err());
+        let ran = cnt.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(cnt.load(Ordering::SeqCst), ran);
+        drop(pool.submit(10, |_| {}).abort_on_drop());
+        // aborting a finished job changes nothing
+        let job = submit(10, |_| {});
+        let abort = job.abort_handle();
+        thread::sleep(Duration::from_millis(10));
+        abort.abort();
+        assert!(matches!(job.wait(), JobResult::Completed));
+    }
+
+    #[test]
+    fn test_scope() {
+        let pool = Pool::new(3);
+        let mut data = [0usize; 8];
+        pool.scope(|s| {
+            for (i, chunk) in data.chunks_mut(2).enumerate() {
+                s.spawn(move |s| {
+                    chunk.fill(i);
+                    s.spawn(|_| {});
+                });
+            }
+        });
+        assert_eq!(data, [0, 0, 1, 1, 2, 2, 3, 3]);
+        let cnt = AtomicUsize::new(0);
+        scope(|s| {
+            s.spawn(|_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 1);
+        // a panic in the closure still waits for the tasks borrowing locals
+        let mut data = vec![0usize; 4];
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.spawn(|_| {
+                    thread::sleep(Duration::from_millis(20));
+                    data.fill(1);
+                });
+                panic!("boom");
+            })
+        }));
+        assert!(res.is_err());
+        assert_eq!(data, [1, 1, 1, 1]);
+        // a scope on the only worker runs its own tasks
+        let single = Pool::new(1);
+        let cnt = AtomicUsize::new(0);
+        single.execute(1, |_| {
+            single.scope(|s| {
+                s.spawn(|_| {
+                    cnt.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 1);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use crate::sync::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_pool() {
+        loom::model(|| {
+            let pool = Pool::new(4);
+            let cnt = AtomicUsize::new(0);
+            pool.execute(10, |_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+            pool.execute(20, |_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+            pool.execute(50, |_| {
+                cnt.fetch_add(1, Ordering::SeqCst);
+            });
+            assert_eq!(cnt.load(Ordering::SeqCst), 80);
+        });
+    }
+
+    #[test]
+    fn test_scope_execute() {
+        loom::model(|| {
+            let pool = Pool::new(2);
+            let data = vec![1, 2, 3];
+            let sum = AtomicUsize::new(0);
+            pool.scope_execute(3, |i| {
+                sum.fetch_add(data[i], Ordering::SeqCst);
+            });
+            // the borrow of `data` has ended, moving it out is fine
+            drop(data);
+            assert_eq!(sum.load(Ordering::SeqCst), 6);
         });
     }
 }
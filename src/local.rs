@@ -0,0 +1,40 @@
+use std::cell::UnsafeCell;
+
+#[repr(align(128))]
+struct Padded<T>(UnsafeCell<T>);
+
+// One value per worker of a pool, each only ever touched by its own worker
+// while a job runs, padded so neighbouring workers don't share cache lines.
+pub(crate) struct PerWorker<T> {
+    slots: Vec<Padded<T>>,
+}
+
+unsafe impl<T: Send> Sync for PerWorker<T> {}
+
+impl<T> PerWorker<T> {
+    pub(crate) fn new(workers: usize, mut init: impl FnMut() -> T) -> PerWorker<T> {
+        PerWorker {
+            slots: (0..workers.max(1))
+                .map(|_| Padded(UnsafeCell::new(init())))
+                .collect(),
+        }
+    }
+
+    // Safety: must only be called by the worker with index `worker` while it
+    // is running a job, and the reference must not escape that call.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn get(&self, worker: usize) -> &mut T {
+        unsafe { &mut *self.slots[worker].0.get() }
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().map(|slot| slot.0.get_mut())
+    }
+
+    pub(crate) fn into_inner(self) -> Vec<T> {
+        self.slots
+            .into_iter()
+            .map(|slot| slot.0.into_inner())
+            .collect()
+    }
+}
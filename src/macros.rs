@@ -0,0 +1,43 @@
+/// Runs the body for every `i` in a range on the global pool, sugar for
+/// `ieu::execute`: `parallel_for!(i in 0..n => { ... })`. With
+/// `parallel_for!(i in 0..n, chunk = 64 => { ... })` each job index covers 64
+/// consecutive values of the range instead of one.
+#[macro_export]
+macro_rules! parallel_for {
+    ($i:pat in $range:expr,chunk = $chunk:expr => $body:block) => {{
+        let range: ::core::ops::Range<usize> = $range;
+        let chunk: usize = ::core::cmp::max($chunk, 1);
+        $crate::execute(range.len().div_ceil(chunk), |c| {
+            let start = range.start + c * chunk;
+            for k in start..::core::cmp::min(start + chunk, range.end) {
+                let $i = k;
+                $body
+            }
+        });
+    }};
+    ($i:pat in $range:expr => $body:block) => {{
+        let range: ::core::ops::Range<usize> = $range;
+        $crate::execute(range.len(), |k| {
+            let $i = range.start + k;
+            $body
+        });
+    }};
+}
+
+/// Declares a `StaticPool`, a pool private to a library that leaves the
+/// application's global pool alone: `static_pool!(pub static POOL = "MYLIB");`
+/// builds it on first use, honoring `MYLIB_NUM_THREADS` and the other
+/// variables of `Builder::env`. A function after the prefix configures the
+/// builder first, `static_pool!(static POOL = "MYLIB", |b| b.num_threads(4));`,
+/// the environment still overrides it. Attributes and doc comments go before
+/// `static`.
+#[macro_export]
+macro_rules! static_pool {
+    ($(#[$attr:meta])* $vis:vis static $name:ident = $prefix:literal $(,)? $(;)?) => {
+        $crate::static_pool!($(#[$attr])* $vis static $name = $prefix, |builder| builder);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident = $prefix:literal, $configure:expr $(,)? $(;)?) => {
+        $(#[$attr])*
+        $vis static $name: $crate::StaticPool = $crate::StaticPool::new($prefix, $configure);
+    };
+}
@@ -0,0 +1,82 @@
+use std::cmp::Ordering;
+
+use crate::{iter::SyncPtr, Pool};
+
+impl Pool {
+    // Merges the sorted `runs` into `out` like a k-way merge would, stable
+    // across runs: equal elements keep the order of the runs they came from.
+    // `out` is cut into one range of ranks per worker, and every worker finds
+    // where its range starts in each run by binary search, so the merge
+    // needs no serial pass. Panics if `out` isn't as long as the runs
+    // together.
+    pub fn par_merge<T: Clone + Send + Sync>(
+        &self,
+        runs: &[&[T]],
+        out: &mut [T],
+        cmp: impl Fn(&T, &T) -> Ordering + Sync,
+    ) {
+        let len = runs.iter().map(|run| run.len()).sum::<usize>();
+        assert_eq!(
+            out.len(),
+            len,
+            "the output must be as long as the runs together"
+        );
+        let parts = self.num_threads().max(1);
+        let ptr = SyncPtr(out.as_mut_ptr());
+        self.run(parts, |_, part| {
+            let (start, end) = (len * part / parts, len * (part + 1) / parts);
+            let mut heads = split(runs, start, &cmp);
+            let tails = split(runs, end, &cmp);
+            // every part writes its own range of `out`
+            let out = unsafe { std::slice::from_raw_parts_mut(ptr.get().add(start), end - start) };
+            for slot in out {
+                // the first run wins ties, which keeps the merge stable
+                let mut best: Option<usize> = None;
+                for run in 0..runs.len() {
+                    if heads[run] < tails[run]
+                        && best.is_none_or(|best| {
+                            cmp(&runs[run][heads[run]], &runs[best][heads[best]]) == Ordering::Less
+                        })
+                    {
+                        best = Some(run);
+                    }
+                }
+                let best = best.unwrap();
+                slot.clone_from(&runs[best][heads[best]]);
+                heads[best] += 1;
+            }
+        });
+    }
+}
+
+// How many elements of each run come before rank `rank` of the merged
+// output. Elements are ordered by value, then by run, then by position, and
+// the rank of an element in that order grows with its position in its run,
+// so each run's share is found by binary search over its positions.
+fn split<T>(runs: &[&[T]], rank: usize, cmp: &impl Fn(&T, &T) -> Ordering) -> Vec<usize> {
+    let rank_of = |run: usize, pos: usize| {
+        let item = &runs[run][pos];
+        (0..runs.len())
+            .map(|other| {
+                match other.cmp(&run) {
+                    Ordering::Less => runs[other].partition_point(|x| cmp(x, item).is_le()),
+                    Ordering::Equal => pos,
+                    Ordering::Greater => runs[other].partition_point(|x| cmp(x, item).is_lt()),
+                }
+            })
+            .sum::<usize>()
+    };
+    (0..runs.len())
+        .map(|run| {
+            let (mut lo, mut hi) = (0, runs[run].len());
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                match rank_of(run, mid) < rank {
+                    true => lo = mid + 1,
+                    false => hi = mid,
+                }
+            }
+            lo
+        })
+        .collect()
+}
@@ -0,0 +1,35 @@
+use crate::sync::{thread, AtomicUsize, Ordering};
+
+/// Runs small sections of a job in index order while the rest of each index
+/// runs in parallel, like OpenMP's `ordered`. `run(i, ..)` waits until the
+/// sections of every index below `i` (down to the starting index) have run.
+///
+/// Every index from the start up to the last one that calls `run` must call
+/// it exactly once, and indices must be claimed in increasing order, as
+/// plain `execute` does. Orders like `execute_rev` or `execute_shuffled` would
+/// make workers wait for indices that nobody has claimed yet.
+#[derive(Debug, Default)]
+pub struct Ordered {
+    next: AtomicUsize,
+}
+
+impl Ordered {
+    pub fn new() -> Ordered {
+        Ordered::starting_at(0)
+    }
+
+    pub fn starting_at(start: usize) -> Ordered {
+        Ordered {
+            next: AtomicUsize::new(start),
+        }
+    }
+
+    pub fn run<R>(&self, index: usize, func: impl FnOnce() -> R) -> R {
+        while self.next.load(Ordering::Acquire) != index {
+            thread::yield_now();
+        }
+        let ret = func();
+        self.next.store(index + 1, Ordering::Release);
+        ret
+    }
+}
@@ -0,0 +1,121 @@
+use std::ptr;
+
+use crate::{iter::SyncPtr, Pool};
+
+// chunks per worker, so a slow chunk doesn't hold up the whole pass
+const CHUNKS_PER_WORKER: usize = 4;
+
+impl Pool {
+    fn partition_chunk_len(&self, len: usize) -> usize {
+        len.div_ceil(self.num_threads().max(1) * CHUNKS_PER_WORKER)
+            .max(1)
+    }
+
+    // evaluates `flag` for every index in chunks, returning the flags and how
+    // many are set in each chunk of `chunk_len`
+    fn flags(
+        &self,
+        len: usize,
+        chunk_len: usize,
+        flag: impl Fn(usize) -> bool + Send + Sync,
+    ) -> (Vec<bool>, Vec<usize>) {
+        let mut flags = vec![false; len];
+        let ptr = SyncPtr(flags.as_mut_ptr());
+        let counts = self.vec_from_fn(len.div_ceil(chunk_len), |c| {
+            let start = c * chunk_len;
+            let end = (start + chunk_len).min(len);
+            // every chunk writes its own part of `flags`
+            let flags =
+                unsafe { std::slice::from_raw_parts_mut(ptr.get().add(start), end - start) };
+            let mut count = 0;
+            for (i, flag_i) in (start..end).zip(flags) {
+                *flag_i = flag(i);
+                count += *flag_i as usize;
+            }
+            count
+        });
+        (flags, counts)
+    }
+
+    // Moves the elements whose flag is set to the front of `data` and the
+    // others behind them, both in their original order, and returns how many
+    // were flagged. Elements are moved bitwise through a scratch buffer and
+    // no user code runs in between, so nothing can observe them half moved.
+    fn scatter<T: Send>(
+        &self,
+        data: &mut [T],
+        chunk_len: usize,
+        flags: &[bool],
+        counts: &[usize],
+    ) -> usize {
+        let len = data.len();
+        let total = counts.iter().sum::<usize>();
+        // where each chunk's flagged and unflagged elements go
+        let mut starts = Vec::with_capacity(counts.len());
+        let mut flagged = 0;
+        for (c, &count) in counts.iter().enumerate() {
+            starts.push((flagged, total + c * chunk_len - flagged));
+            flagged += count;
+        }
+        let mut scratch = Vec::<T>::with_capacity(len);
+        let src = SyncPtr(data.as_mut_ptr());
+        let dst = SyncPtr(scratch.as_mut_ptr());
+        // every element lands on its own slot of `scratch`
+        self.run(counts.len(), |_, c| {
+            let (mut flagged, mut unflagged) = starts[c];
+            let start = c * chunk_len;
+            let end = (start + chunk_len).min(len);
+            for (i, &flag) in (start..end).zip(&flags[start..end]) {
+                let to = match flag {
+                    true => &mut flagged,
+                    false => &mut unflagged,
+                };
+                unsafe { ptr::copy_nonoverlapping(src.get().add(i), dst.get().add(*to), 1) };
+                *to += 1;
+            }
+        });
+        self.run(counts.len(), |_, c| {
+            let start = c * chunk_len;
+            let end = (start + chunk_len).min(len);
+            unsafe {
+                ptr::copy_nonoverlapping(dst.get().add(start), src.get().add(start), end - start)
+            };
+        });
+        // `scratch` never owned the elements, only its buffer is freed
+        total
+    }
+
+    // Reorders `data` so that the elements matching `pred` come first, both
+    // groups keeping their order, and returns how many matched. `pred` runs
+    // in parallel, moving the elements takes two more passes over `data`.
+    pub fn par_partition<T: Send + Sync>(
+        &self,
+        data: &mut [T],
+        pred: impl Fn(&T) -> bool + Send + Sync,
+    ) -> usize {
+        let chunk_len = self.partition_chunk_len(data.len());
+        let (flags, counts) = self.flags(data.len(), chunk_len, |i| pred(&data[i]));
+        self.scatter(data, chunk_len, &flags, &counts)
+    }
+
+    // keeps only the elements matching `pred`, in order, like `Vec::retain`
+    // but evaluating `pred` in parallel; returns the new length
+    pub fn par_retain<T: Send + Sync>(
+        &self,
+        vec: &mut Vec<T>,
+        pred: impl Fn(&T) -> bool + Send + Sync,
+    ) -> usize {
+        let kept = self.par_partition(vec, pred);
+        vec.truncate(kept);
+        kept
+    }
+
+    // removes consecutive repeated elements like `Vec::dedup`, comparing the
+    // neighbours in parallel
+    pub fn par_dedup<T: PartialEq + Send + Sync>(&self, vec: &mut Vec<T>) {
+        let chunk_len = self.partition_chunk_len(vec.len());
+        let (flags, counts) = self.flags(vec.len(), chunk_len, |i| i == 0 || vec[i] != vec[i - 1]);
+        let kept = self.scatter(vec, chunk_len, &flags, &counts);
+        vec.truncate(kept);
+    }
+}
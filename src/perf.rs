@@ -0,0 +1,194 @@
+use std::{
+    ops::AddAssign,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    local::PerWorker,
+    sync::{AtomicUsize, Ordering},
+    Pool,
+};
+
+/// Hardware counter readings, or the difference between two.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PerfCounts {
+    pub cycles:       u64,
+    pub instructions: u64,
+    /// Cache misses, which most CPUs count at the last level cache.
+    pub llc_misses:   u64,
+}
+
+impl PerfCounts {
+    /// Instructions per cycle, 0 without any cycles.
+    pub fn ipc(&self) -> f64 {
+        match self.cycles {
+            0 => 0.0,
+            cycles => self.instructions as f64 / cycles as f64,
+        }
+    }
+}
+
+impl AddAssign for PerfCounts {
+    fn add_assign(&mut self, other: PerfCounts) {
+        self.cycles += other.cycles;
+        self.instructions += other.instructions;
+        self.llc_misses += other.llc_misses;
+    }
+}
+
+/// What the hardware counters of the workers saw during a job, returned by
+/// `Pool::execute_perf`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobPerf {
+    pub duration: Duration,
+    /// The counts of every worker while it ran indices of the job, `None`
+    /// for workers that ran none or whose counters couldn't be opened, e.g.
+    /// because `perf_event_paranoid` forbids it or inside a VM without a PMU.
+    pub workers:  Vec<Option<PerfCounts>>,
+}
+
+impl JobPerf {
+    /// The counts summed over the workers.
+    pub fn total(&self) -> PerfCounts {
+        let mut total = PerfCounts::default();
+        for counts in self.workers.iter().flatten() {
+            total += *counts;
+        }
+        total
+    }
+}
+
+// the counters of a thread, opened the first time it measures a job and
+// closed when it exits
+struct Counters {
+    // the group leader (cycles) first
+    fds: [i32; 3],
+}
+
+thread_local! {
+    static COUNTERS: Option<Counters> = Counters::open();
+}
+
+// `struct perf_event_attr` up to `PERF_ATTR_SIZE_VER0`, the kernel takes the
+// fields after it as zero
+#[repr(C)]
+struct Attr {
+    kind:          u32,
+    size:          u32,
+    config:        u64,
+    sample_period: u64,
+    sample_type:   u64,
+    read_format:   u64,
+    flags:         u64,
+    wakeup_events: u32,
+    bp_type:       u32,
+    config1:       u64,
+}
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+const PERF_FORMAT_GROUP: u64 = 1 << 3;
+// `exclude_kernel` and `exclude_hv`, which unprivileged processes need
+const EXCLUDE_KERNEL_HV: u64 = 1 << 5 | 1 << 6;
+
+impl Counters {
+    fn open() -> Option<Counters> {
+        let mut fds = [-1; 3];
+        let events = [
+            PERF_COUNT_HW_CPU_CYCLES,
+            PERF_COUNT_HW_INSTRUCTIONS,
+            PERF_COUNT_HW_CACHE_MISSES,
+        ];
+        for (k, config) in events.into_iter().enumerate() {
+            let attr = Attr {
+                kind: PERF_TYPE_HARDWARE,
+                size: size_of::<Attr>() as u32,
+                config,
+                sample_period: 0,
+                sample_type: 0,
+                read_format: PERF_FORMAT_GROUP,
+                flags: EXCLUDE_KERNEL_HV,
+                wakeup_events: 0,
+                bp_type: 0,
+                config1: 0,
+            };
+            // the calling thread on any CPU, in the group of the first one
+            let group = if k == 0 { -1 } else { fds[0] };
+            let fd = unsafe { libc::syscall(libc::SYS_perf_event_open, &attr, 0, -1, group, 0) };
+            if fd < 0 {
+                // the ones opened so far are closed on drop
+                drop(Counters { fds });
+                return None;
+            }
+            fds[k] = fd as i32;
+        }
+        Some(Counters { fds })
+    }
+
+    fn read(&self) -> Option<PerfCounts> {
+        // the number of counters, then their values in the order they joined
+        // the group
+        let mut values = [0u64; 4];
+        let len = size_of_val(&values);
+        let read = unsafe { libc::read(self.fds[0], values.as_mut_ptr().cast(), len) };
+        (read == len as isize).then_some(PerfCounts {
+            cycles:       values[1],
+            instructions: values[2],
+            llc_misses:   values[3],
+        })
+    }
+}
+
+impl Drop for Counters {
+    fn drop(&mut self) {
+        for &fd in self.fds.iter().filter(|&&fd| fd >= 0) {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+fn read_counters() -> Option<PerfCounts> {
+    COUNTERS.with(|counters| counters.as_ref().and_then(Counters::read))
+}
+
+impl Pool {
+    // Like `execute`, reading the cycles, instructions and cache misses of
+    // every worker around the indices it runs, to see how the schedule
+    // affects cache behaviour without running the process under `perf`.
+    // Every worker opens its counters the first time it takes part in such a
+    // job and keeps them until it exits, they count in user space only.
+    pub fn execute_perf(&self, num: usize, func: impl Fn(usize) + Send + Sync) -> JobPerf {
+        let workers = self.num_threads();
+        let counts = PerWorker::new(workers, || None::<PerfCounts>);
+        let next = AtomicUsize::new(0);
+        let start = Instant::now();
+        // a claim loop per worker, so the counters are read once around all
+        // the indices a worker runs rather than around every one
+        self.run(workers.clamp(1, num.max(1)), |worker, _| {
+            let before = read_counters();
+            let mut ran = false;
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= num {
+                    break;
+                }
+                func(i);
+                ran = true;
+            }
+            if let (true, Some(before), Some(after)) = (ran, before, read_counters()) {
+                let delta = PerfCounts {
+                    cycles:       after.cycles - before.cycles,
+                    instructions: after.instructions - before.instructions,
+                    llc_misses:   after.llc_misses - before.llc_misses,
+                };
+                *unsafe { counts.get(worker) }.get_or_insert_default() += delta;
+            }
+        });
+        JobPerf {
+            duration: start.elapsed(),
+            workers:  counts.into_inner(),
+        }
+    }
+}
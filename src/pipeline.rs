@@ -0,0 +1,183 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    sync::{Arc, AtomicUsize, Condvar, Mutex, Ordering},
+    Pool,
+    Scope,
+};
+
+// stages that may be queued on a pipeline: the running one and one staged
+const BUFFERS: usize = 2;
+
+/// Runs a sequence of jobs on a pool without a gap between them: while one
+/// job runs, the caller can already stage the next one, and the workers roll
+/// straight into it once the current job is done. Created by `Pool::pipeline`.
+pub struct Pipeline<'env> {
+    pool:    *const Pool,
+    inline:  bool,
+    shared:  Shared,
+    _marker: PhantomData<&'env mut &'env ()>,
+}
+
+unsafe impl Sync for Pipeline<'_> {}
+
+struct Shared {
+    state:   Mutex<State>,
+    changed: Condvar,
+    // the first panic of a stage, resumed once the pipeline is done
+    panic:   Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+struct State {
+    // the unfinished stages in order, `first` is the number of the front one
+    first:  usize,
+    stages: VecDeque<Arc<Stage>>,
+    closed: bool,
+}
+
+struct Stage {
+    func: Box<dyn Fn(usize) + Send + Sync>,
+    num:  usize,
+    next: AtomicUsize,
+    done: AtomicUsize,
+}
+
+impl<'env> Pipeline<'env> {
+    pub(crate) fn new(pool: &Pool) -> Pipeline<'env> {
+        Pipeline {
+            pool:    pool as *const Pool,
+            inline:  pool.runs_inline(),
+            shared:  Shared {
+                state:   Mutex::new(State {
+                    first:  0,
+                    stages: VecDeque::new(),
+                    closed: false,
+                }),
+                changed: Condvar::new(),
+                panic:   Mutex::new(None),
+            },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Stages a job running `func` over `0..num` after the jobs staged before
+    /// it and returns as soon as it is queued, which waits only while both
+    /// the running and the staged job are unfinished.
+    pub fn execute(&self, num: usize, func: impl Fn(usize) + Send + Sync + 'env) {
+        if self.inline {
+            return unsafe { &*self.pool }.scope_execute(num, func);
+        }
+        if num == 0 {
+            return;
+        }
+        let func = Box::new(func) as Box<dyn Fn(usize) + Send + Sync + 'env>;
+        // the pipeline waits for every stage before returning
+        let func = unsafe {
+            std::mem::transmute::<
+                Box<dyn Fn(usize) + Send + Sync + 'env>,
+                Box<dyn Fn(usize) + Send + Sync + 'static>,
+            >(func)
+        };
+        let stage = Arc::new(Stage {
+            func,
+            num,
+            next: AtomicUsize::new(0),
+            done: AtomicUsize::new(0),
+        });
+        let mut state = self.shared.state.lock().unwrap();
+        while state.stages.len() >= BUFFERS {
+            state = self.shared.changed.wait(state).unwrap();
+        }
+        state.stages.push_back(stage);
+        self.shared.changed.notify_all();
+    }
+
+    /// Waits until every job staged so far is done.
+    pub fn wait(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        while !state.stages.is_empty() {
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+
+    fn close(&self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+    }
+
+    // run by one task per worker until the pipeline is closed and drained
+    fn drive(&self) {
+        let mut number = 0;
+        loop {
+            let stage = {
+                let mut state = self.shared.state.lock().unwrap();
+                loop {
+                    // stages this worker missed are done already
+                    number = number.max(state.first);
+                    // a stage only starts once the one before it is done
+                    if let Some(stage) = state.stages.front().filter(|_| number == state.first) {
+                        break stage.clone();
+                    }
+                    if state.closed && state.stages.len() <= number - state.first {
+                        return;
+                    }
+                    state = self.shared.changed.wait(state).unwrap();
+                }
+            };
+            loop {
+                let i = stage.next.fetch_add(1, Ordering::SeqCst);
+                if i >= stage.num {
+                    break;
+                }
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| (stage.func)(i))) {
+                    self.shared.panic.lock().unwrap().get_or_insert(payload);
+                }
+                if stage.done.fetch_add(1, Ordering::SeqCst) + 1 == stage.num {
+                    let mut state = self.shared.state.lock().unwrap();
+                    state.stages.pop_front();
+                    state.first += 1;
+                    self.shared.changed.notify_all();
+                }
+            }
+            number += 1;
+        }
+    }
+}
+
+impl Pool {
+    // runs `func` with a `Pipeline` whose jobs may borrow anything that
+    // outlives the call, the workers stay inside the pipeline (and take no
+    // other jobs or tasks) until `func` has returned and every staged job is
+    // done; a panic in a job is resumed here afterwards
+    pub fn pipeline<'env, R>(&self, func: impl FnOnce(&Pipeline<'env>) -> R) -> R {
+        struct Close<'a, 'env, 'scope>(&'a Pipeline<'env>, &'a Scope<'scope>);
+
+        impl Drop for Close<'_, '_, '_> {
+            fn drop(&mut self) {
+                self.0.close();
+                self.1.wait();
+            }
+        }
+
+        let pipeline = Pipeline::new(self);
+        let scope = Scope::new(self);
+        if !pipeline.inline {
+            for _ in 0..self.num_threads() {
+                scope.spawn(|_| pipeline.drive());
+            }
+        }
+        let close = Close(&pipeline, &scope);
+        let ret = func(&pipeline);
+        drop(close);
+        scope.resume_panic();
+        if let Some(payload) = pipeline.shared.panic.lock().unwrap().take() {
+            panic::resume_unwind(payload);
+        }
+        ret
+    }
+}
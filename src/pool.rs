@@ -0,0 +1,1306 @@
+use core::sync::atomic::AtomicU64;
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    io,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    admission::Admission,
+    autoscale::Autoscale,
+    builder::{HangHook, JobHook},
+    cpu,
+    dispatch::{self, Dispatch, Func, Waiter},
+    global,
+    local::PerWorker,
+    quota::Quota,
+    signal::Signal,
+    stats::Counters,
+    sync::{self, thread, thread_local, Arc, AtomicBool, AtomicUsize, Condvar, Mutex, Ordering},
+    sys,
+    Builder,
+    Hang,
+    QueuePolicy,
+    Scope,
+    ThreadPriority,
+};
+
+// wasm32-unknown-unknown can't spawn threads, pools there have no workers and
+// run everything on the calling thread
+const THREADS_SUPPORTED: bool = !cfg!(all(target_arch = "wasm32", target_os = "unknown"));
+
+// how many jobs can run on a pool at once, submitters past that wait for one
+// of them to finish
+const SLOTS: usize = 4;
+
+// loom's thread_local! doesn't take const initializers
+thread_local! {
+    // the pool this thread runs job indices for (as one of its workers, or as
+    // the caller of a job running inline) and its worker index there
+    #[allow(clippy::missing_const_for_thread_local)]
+    static WORKER: Cell<(usize, usize)> = Cell::new((0, 0));
+    // the slot of the innermost job this thread is running indices of
+    #[allow(clippy::missing_const_for_thread_local)]
+    static INLINE: Cell<*const Dispatch> = Cell::new(std::ptr::null());
+    // set for the whole life of a worker thread of any pool
+    #[allow(clippy::missing_const_for_thread_local)]
+    static IS_WORKER: Cell<bool> = Cell::new(false);
+}
+
+// Whether the calling thread is a worker of some ieu pool, e.g. for library
+// code deciding between running inline and dispatching, or asserting that
+// it's not about to block a worker. Callers running a job inline don't
+// count, even while inside it.
+pub fn is_worker_thread() -> bool {
+    IS_WORKER.with(Cell::get)
+}
+
+pub struct Pool {
+    pub(crate) inner: Pin<Box<PoolInner>>,
+}
+
+// Workers reach their pool through the address of `inner`, which is pinned
+// on the heap and outlives them: dropping the pool joins every worker before
+// `inner` is freed, and moving a `Pool` only moves the box. So pools can be
+// moved to other threads, kept in statics or shared like any `Send + Sync`
+// value, which this keeps from regressing.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Pool>();
+};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+pub(crate) struct PoolInner {
+    slots: [Slot; SLOTS],
+    // hands the slots to submitters in the order they arrive
+    admission: Admission,
+    // per-worker jobs wait for every worker, two at once would deadlock
+    pub(crate) teams: Mutex<()>,
+    signal: Signal,
+    // indices per chunk of `execute_chunked`, see `calibrate`
+    pub(crate) grain: AtomicUsize,
+    // whether the pool still exists, for wakers of spawned futures that may
+    // outlive it; held while they push a task so the pool can't go away
+    pub(crate) live: Arc<Mutex<bool>>,
+    // workers that aren't alive, or are about to retire; while there are
+    // none, waking the pool needs no respawning and skips the workers lock
+    dead: AtomicUsize,
+    // jobs and tasks that are queued or running, `idle` is notified when
+    // the last of them is done
+    pending: AtomicUsize,
+    idle_lock: Mutex<()>,
+    idle: Condvar,
+    pub(crate) stats: Counters,
+    // set by `pause`, workers wait on `resumed` before their next index or
+    // task while it is
+    paused: AtomicBool,
+    pause_lock: Mutex<()>,
+    resumed: Condvar,
+    shutdown: AtomicBool,
+    tasks: Mutex<VecDeque<Task>>,
+    // the bound on `tasks` set with `max_queued_tasks`, `room` is notified
+    // whenever a task comes off it
+    pub(crate) queue_limit: Option<(usize, QueuePolicy)>,
+    room: Condvar,
+    // tasks only worker `i` may run, see `spawn_broadcast`
+    worker_tasks: Vec<Mutex<VecDeque<Task>>>,
+    next: AtomicUsize,
+    // numbers the jobs in log records
+    #[cfg(feature = "log")]
+    jobs: AtomicUsize,
+    // the latest health check, and the latest one each worker has answered
+    ping: AtomicUsize,
+    pongs: Vec<AtomicUsize>,
+    // kernel thread ids of the workers, 0 until a worker has started
+    #[cfg(target_os = "linux")]
+    tids: Vec<AtomicUsize>,
+    pub(crate) workers: Mutex<Vec<Worker>>,
+    // the workers that take part in jobs, see `set_active_threads`
+    pub(crate) active: AtomicUsize,
+    pub(crate) quota: Option<Quota>,
+    pub(crate) autoscale: Option<Autoscale>,
+    priority: Option<ThreadPriority>,
+    idle_timeout: Option<Duration>,
+    pub(crate) size: usize,
+    sequential: bool,
+    yield_every: Option<usize>,
+    yield_sleep: Option<Duration>,
+    inline_below: usize,
+    help_when_busy: bool,
+    pub(crate) power_saving: bool,
+    stack_size: Option<usize>,
+    // the CPU of every worker, for pools pinned to physical cores
+    cores: Option<Vec<usize>>,
+    job_start: Option<JobHook>,
+    job_end: Option<JobHook>,
+    worker_exit: Option<JobHook>,
+    on_hang: Option<HangHook>,
+    #[cfg(feature = "chaos")]
+    chaos: Option<crate::chaos::Chaos>,
+    #[cfg(feature = "rayon")]
+    rayon: std::sync::OnceLock<rayon::ThreadPool>,
+}
+
+// A job slot and the submitter waiting on it. Workers pull indices from
+// whichever slots have a job open, so jobs submitted concurrently share them.
+struct Slot {
+    dispatch: Dispatch,
+    waiter:   Waiter<thread::Thread>,
+    // the label of the open job, for the workers' profiler scopes
+    #[cfg(feature = "puffin")]
+    label:    Waiter<Arc<str>>,
+}
+
+impl Slot {
+    fn new() -> Slot {
+        Slot {
+            dispatch: Dispatch::new(),
+            waiter: Waiter::new(),
+            #[cfg(feature = "puffin")]
+            label: Waiter::new(),
+        }
+    }
+}
+
+pub(crate) struct Worker {
+    handle:           Option<thread::JoinHandle<()>>,
+    pub(crate) alive: bool,
+}
+
+// a job that panicked leaves the pool ready for the next one
+impl std::panic::UnwindSafe for Pool {}
+impl std::panic::RefUnwindSafe for Pool {}
+
+impl Pool {
+    // a pool of size 0 has no workers and runs every job and task on the
+    // calling thread, e.g. for a configuration switch that turns off
+    // parallelism
+    pub fn new(size: usize) -> Pool {
+        Builder::new().num_threads(size).build()
+    }
+
+    // like `new`, but fails instead of panicking when the OS refuses to
+    // spawn a worker (thread or pid limits)
+    pub fn try_new(size: usize) -> io::Result<Pool> {
+        Builder::new().num_threads(size).try_build()
+    }
+
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    pub(crate) fn from_builder(builder: Builder) -> io::Result<Pool> {
+        let cores = (builder.physical || global::physical_cores())
+            .then(cpu::physical_cores)
+            .flatten();
+        #[cfg(feature = "hwloc")]
+        let cores = match &builder.placement {
+            Some(placement) => {
+                crate::Topology::detect()
+                    .map(|topology| topology.place(placement))
+                    .filter(|cpus| !cpus.is_empty())
+            },
+            None => cores,
+        };
+        // power saving prefers the efficiency cores, when there's no size
+        // or placement asked for
+        let cores = match builder.power_saving
+            && cores.is_none()
+            && builder
+                .num_threads
+                .or_else(global::env_num_threads)
+                .is_none()
+        {
+            true => cpu::efficiency_cores(),
+            false => cores,
+        };
+        let size = match THREADS_SUPPORTED {
+            true => {
+                builder
+                    .num_threads
+                    .or_else(global::env_num_threads)
+                    .or(cores.as_ref().map(Vec::len))
+                    .or(builder.power_saving.then(|| cpu::available().div_ceil(2)))
+                    .unwrap_or_else(|| {
+                        match builder.quota_interval {
+                            // room to grow when the quota goes up
+                            Some(_) => cpu::machine(),
+                            None => cpu::available(),
+                        }
+                    })
+            },
+            false => 0,
+        };
+        // pinning alone doesn't size the pool
+        let cores = cores.or_else(|| builder.pin.then(cpu::allowed).flatten());
+        let autoscale = builder
+            .autoscale
+            .map(|(min, interval)| Autoscale::new(min, interval, size));
+        let pool = Pool {
+            inner: Box::pin(PoolInner {
+                slots: std::array::from_fn(|_| Slot::new()),
+                admission: Admission::new(SLOTS),
+                teams: Mutex::new(()),
+                signal: match builder.power_saving {
+                    true => Signal::new(false, None),
+                    false => Signal::new(builder.busy_wait, builder.linger),
+                },
+                grain: AtomicUsize::new(match builder.power_saving {
+                    true => 16,
+                    false => 1,
+                }),
+                live: Arc::new(Mutex::new(true)),
+                dead: AtomicUsize::new(size),
+                pending: AtomicUsize::new(0),
+                idle_lock: Mutex::new(()),
+                idle: Condvar::new(),
+                stats: Counters::new(size),
+                paused: AtomicBool::new(false),
+                pause_lock: Mutex::new(()),
+                resumed: Condvar::new(),
+                shutdown: AtomicBool::new(false),
+                tasks: Mutex::new(VecDeque::new()),
+                queue_limit: builder.queue_limit,
+                room: Condvar::new(),
+                worker_tasks: (0..size).map(|_| Mutex::new(VecDeque::new())).collect(),
+                next: AtomicUsize::new(0),
+                #[cfg(feature = "log")]
+                jobs: AtomicUsize::new(0),
+                ping: AtomicUsize::new(0),
+                pongs: (0..size).map(|_| AtomicUsize::new(0)).collect(),
+                #[cfg(target_os = "linux")]
+                tids: (0..size).map(|_| AtomicUsize::new(0)).collect(),
+                workers: Mutex::new(
+                    (0..size)
+                        .map(|_| {
+                            Worker {
+                                handle: None,
+                                alive:  false,
+                            }
+                        })
+                        .collect(),
+                ),
+                active: AtomicUsize::new(match (&autoscale, builder.quota_interval) {
+                    // starts small and grows with the load
+                    (Some(autoscale), _) => autoscale.min(),
+                    (None, Some(_)) => cpu::available().clamp(1, size.max(1)),
+                    (None, None) => size.max(1),
+                }),
+                quota: builder
+                    .quota_interval
+                    .map(|interval| (interval, std::sync::Mutex::new(Instant::now()))),
+                autoscale,
+                priority: builder
+                    .priority
+                    .or(builder.power_saving.then_some(ThreadPriority::Background)),
+                power_saving: builder.power_saving,
+                idle_timeout: builder.idle_timeout,
+                size,
+                sequential: builder.sequential,
+                yield_every: builder.yield_every,
+                yield_sleep: builder.yield_sleep,
+                inline_below: builder.inline_below,
+                help_when_busy: builder.help_when_busy,
+                stack_size: builder.stack_size,
+                cores,
+                job_start: builder.job_start,
+                job_end: builder.job_end,
+                worker_exit: builder.worker_exit,
+                on_hang: builder.on_hang,
+                #[cfg(feature = "chaos")]
+                chaos: builder.chaos.map(crate::chaos::Chaos::new),
+                #[cfg(feature = "rayon")]
+                rayon: std::sync::OnceLock::new(),
+            }),
+        };
+        if !builder.lazy {
+            let mut workers = pool.inner.workers.lock().unwrap();
+            for index in 0..size {
+                // the workers spawned so far are joined when `pool` drops
+                pool.inner.spawn_worker(&mut workers, index)?;
+            }
+        }
+        Ok(pool)
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.inner.size
+    }
+
+    // ids of the running workers in index order; lazy pools and idle
+    // timeouts can leave some workers unspawned, those are skipped
+    pub fn worker_ids(&self) -> Vec<thread::ThreadId> {
+        self.inner
+            .workers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|worker| worker.alive)
+            .filter_map(|worker| worker.handle.as_ref())
+            .map(|handle| handle.thread().id())
+            .collect()
+    }
+
+    // kernel thread ids of the running workers in index order, e.g. for
+    // `perf -t` or a cgroup's `cgroup.threads`
+    #[cfg(target_os = "linux")]
+    pub fn worker_tids(&self) -> Vec<i32> {
+        let workers = self.inner.workers.lock().unwrap();
+        (0..workers.len())
+            .filter(|&index| workers[index].alive)
+            .map(|index| {
+                // a worker that was just spawned may not have stored it yet
+                loop {
+                    match self.inner.tids[index].load(Ordering::SeqCst) {
+                        0 => thread::yield_now(),
+                        tid => return tid as i32,
+                    }
+                }
+            })
+            .collect()
+    }
+
+    // the `Debug` output spread over several lines, to log when a job seems
+    // to hang
+    pub fn dump_state(&self) -> String {
+        format!("{self:#?}")
+    }
+
+    pub fn execute(&self, num: usize, func: impl Fn(usize) + Send + Sync) {
+        self.run(num, |_, i| func(i));
+    }
+
+    // `execute` with the borrowing made explicit: `func` may capture anything
+    // that outlives the call. This is sound because the call doesn't return,
+    // or unwind, before the job is closed and every worker that entered it
+    // has left (see `Finish`), and workers only reach the closure through the
+    // open job. Borrowed data is therefore never touched after `'env` ends,
+    // and `Sync` makes sharing it across the workers fine.
+    pub fn scope_execute<'env>(&self, num: usize, func: impl Fn(usize) + Send + Sync + 'env) {
+        self.run(num, |_, i| func(i));
+    }
+
+    // like `execute`, but only the first `num_threads` workers (at least one)
+    // take part in the job, e.g. for memory-bound loops that stop scaling
+    // past a few threads
+    pub fn execute_on(&self, num_threads: usize, num: usize, func: impl Fn(usize) + Send + Sync) {
+        let workers = num_threads.clamp(1, self.job_threads());
+        self.run_on(None, workers, num, |_, i| func(i));
+    }
+
+    // like `execute`, naming the job in the records of the `log` feature
+    pub fn execute_labeled(&self, label: &str, num: usize, func: impl Fn(usize) + Send + Sync) {
+        self.run_on(Some(label), self.job_threads(), num, |_, i| func(i));
+    }
+
+    // the workers the next job may use, fewer for the global pool inside
+    // `with_num_threads`
+    fn job_threads(&self) -> usize {
+        let workers = self.inner.job_threads();
+        global::num_threads_limit(self).map_or(workers, |limit| workers.min(limit))
+    }
+
+    // `func` gets the index of the worker running it (in `0..size`, or 0 for
+    // the caller of a pool without workers) along with the job index; returns
+    // how many indices ran, which is less than `num` if the job was stopped
+    // and always covers `0..` that many
+    pub(crate) fn run(&self, num: usize, func: impl Fn(usize, usize) + Send + Sync) -> usize {
+        self.run_on(None, self.job_threads(), num, func)
+    }
+
+    pub(crate) fn run_on(
+        &self,
+        label: Option<&str>,
+        workers: usize,
+        num: usize,
+        func: impl Fn(usize, usize) + Send + Sync,
+    ) -> usize {
+        self.run_for(label, workers, None, num, func)
+    }
+
+    // Like `run_on`, only the workers flagged in `allowed` take part (and
+    // get woken), the others stay parked. Such a job is tied to those
+    // workers, so it never runs on the calling thread because it's small or
+    // because the pool is busy, it waits for a slot instead.
+    pub(crate) fn run_for(
+        &self,
+        label: Option<&str>,
+        workers: usize,
+        allowed: Option<&[bool]>,
+        num: usize,
+        func: impl Fn(usize, usize) + Send + Sync,
+    ) -> usize {
+        // nothing to wake the workers for
+        if num == 0 {
+            return 0;
+        }
+        #[cfg(feature = "log")]
+        let job = JobName(self.inner.jobs.fetch_add(1, Ordering::Relaxed), label);
+        #[cfg(feature = "log")]
+        log::debug!("{job} submitted with {num} indices");
+        #[cfg(feature = "log")]
+        let submitted = Instant::now();
+        #[cfg(feature = "puffin")]
+        let _scope = crate::profile::job(label.unwrap_or_default());
+        // A job started from one of this pool's own jobs or tasks would wait
+        // forever for the job slot or for itself, so it runs on the calling
+        // worker instead.
+        if let Some(worker) = self.worker_index() {
+            return self.run_alone(worker, num, func);
+        }
+        // too small to be worth the round trip to the workers
+        if num < self.inner.inline_below && allowed.is_none() {
+            #[cfg(feature = "log")]
+            log::trace!("{job} runs on the calling thread");
+            return self.run_alone(0, num, func);
+        }
+        let inner = &*self.inner;
+        let queued = Instant::now();
+        let _pending = Pending::new(inner);
+        // jobs submitted from several threads at once run side by side, up to
+        // one per slot, the rest wait for a free slot in the order they came,
+        // or run on their own with `help_when_busy`
+        let admitted = match inner.help_when_busy && allowed.is_none() {
+            true => {
+                match inner.admission.try_enter() {
+                    Some(admitted) => admitted,
+                    None => {
+                        #[cfg(feature = "log")]
+                        log::trace!("{job} runs on the calling thread, the pool is busy");
+                        return self.run_alone(0, num, func);
+                    },
+                }
+            },
+            false => inner.admission.enter(),
+        };
+        let slot = &inner.slots[admitted.slot];
+        if self.runs_inline() {
+            #[cfg(feature = "log")]
+            log::trace!("{job} runs on the calling thread");
+            return self.run_alone(0, num, func);
+        }
+        let queue_wait = queued.elapsed();
+        // nanoseconds from submission to the first index a worker ran, 0
+        // until one did
+        let pickup = AtomicU64::new(0);
+        // indices run by each worker, to yield every `yield_every` of them
+        // and for the logs
+        let ran = (inner.yield_every.is_some() || cfg!(feature = "log"))
+            .then(|| PerWorker::new(inner.size, || 0usize));
+        // for `on_hang`: what every worker is up to, 0 before its first index,
+        // `i + 2` while it runs index `i` and 1 between and after them
+        let states = inner.on_hang.as_ref().map(|_| {
+            (0..inner.size)
+                .map(|_| AtomicUsize::new(0))
+                .collect::<Vec<_>>()
+        });
+        // a panicking index stops the job, the panic is resumed on the
+        // caller once the workers are done with it
+        let panicked = Mutex::new(None);
+        let func = |worker: usize, i: usize| {
+            if pickup.load(Ordering::Relaxed) == 0 {
+                let nanos = (queued.elapsed().as_nanos() as u64).max(1);
+                let _ = pickup.compare_exchange(0, nanos, Ordering::Relaxed, Ordering::Relaxed);
+            }
+            inner.hold();
+            #[cfg(feature = "chaos")]
+            if let Some(chaos) = &inner.chaos {
+                chaos.delay();
+                if chaos.roll(64) {
+                    inner.wake_waiter(slot);
+                }
+            }
+            if let Some(states) = &states {
+                states[worker].store(i + 2, Ordering::Relaxed);
+            }
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| func(worker, i))) {
+                panicked.lock().unwrap().get_or_insert(payload);
+                slot.dispatch.stop();
+            }
+            if let Some(states) = &states {
+                states[worker].store(1, Ordering::Relaxed);
+            }
+            if let Some(ran) = &ran {
+                let ran = unsafe { ran.get(worker) };
+                *ran += 1;
+                #[cfg(feature = "log")]
+                if *ran == 1 {
+                    log::trace!("{job} started on worker {worker}");
+                }
+                if inner
+                    .yield_every
+                    .is_some_and(|every| ran.is_multiple_of(every))
+                {
+                    match inner.yield_sleep {
+                        Some(duration) => std::thread::sleep(duration),
+                        None => thread::yield_now(),
+                    }
+                }
+            }
+        };
+        let func = unsafe { dispatch::erase(func) };
+        slot.waiter.set(Some(thread::current()));
+        #[cfg(feature = "puffin")]
+        slot.label.set(label.map(Arc::from));
+        let (workers, flags) = match allowed {
+            Some(allowed) => (workers.min(allowed.len()), allowed.as_ptr()),
+            None => (workers, std::ptr::null()),
+        };
+        unsafe { slot.dispatch.open_for(num, workers, flags, func) };
+        let finish = Finish {
+            pool: inner,
+            dispatch: &slot.dispatch,
+            func,
+        };
+        inner.wake_job(num, workers, allowed);
+        let start = Instant::now();
+        let mut report = inner.on_hang.as_ref().map(|hook| start + hook.0);
+        while !slot.dispatch.is_done(num) {
+            if let (Some(hook), Some(states), Some(at)) = (&inner.on_hang, &states, report)
+                && Instant::now() >= at
+            {
+                report = Some(at + hook.0);
+                let mut hang = Hang {
+                    label: label.map(str::to_owned),
+                    waited: start.elapsed(),
+                    num,
+                    handed_out: slot.dispatch.handed_out(),
+                    running: Vec::new(),
+                    finished: Vec::new(),
+                };
+                for (worker, state) in states.iter().enumerate() {
+                    match state.load(Ordering::Relaxed) {
+                        0 => {},
+                        1 => hang.finished.push(worker),
+                        i => hang.running.push((worker, i - 2)),
+                    }
+                }
+                #[cfg(feature = "log")]
+                log::warn!("{job} is still running: {hang:?}");
+                (hook.1)(&hang);
+            }
+            inner.wait_for_job(start, report);
+        }
+        drop(finish);
+        slot.waiter.set(None);
+        #[cfg(feature = "puffin")]
+        slot.label.set(None);
+        let handed_out = slot.dispatch.handed_out();
+        drop(admitted);
+        let pickup = Some(pickup.into_inner())
+            .filter(|&nanos| nanos > 0)
+            .map(Duration::from_nanos);
+        inner.stats.job(queue_wait, pickup, handed_out);
+        let panicked = panicked.into_inner().unwrap();
+        #[cfg(feature = "log")]
+        match panicked {
+            Some(_) => log::debug!("{job} panicked after {:?}", submitted.elapsed()),
+            None => log::debug!("{job} completed in {:?}", submitted.elapsed()),
+        }
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+        handed_out
+    }
+
+    // Runs a job on the calling thread as `worker` of this pool. It gets a
+    // slot of its own that the workers never look at, so they can't join in.
+    fn run_alone(
+        &self,
+        worker: usize,
+        num: usize,
+        func: impl Fn(usize, usize) + Send + Sync,
+    ) -> usize {
+        struct Restore((usize, usize), *const Dispatch);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                WORKER.with(|cell| cell.set(self.0));
+                INLINE.with(|cell| cell.set(self.1));
+            }
+        }
+
+        let dispatch = Dispatch::new();
+        let func = unsafe { dispatch::erase(func) };
+        unsafe { dispatch.open(num, usize::MAX, func) };
+        let _job = Finish {
+            pool: &self.inner,
+            dispatch: &dispatch,
+            func,
+        };
+        let pool = &*self.inner as *const PoolInner as usize;
+        let _restore = Restore(
+            WORKER.with(|cell| cell.replace((pool, worker))),
+            INLINE.with(|cell| cell.replace(&dispatch)),
+        );
+        dispatch.run(worker);
+        dispatch.handed_out()
+    }
+
+    pub(crate) fn worker_index(&self) -> Option<usize> {
+        let (pool, index) = WORKER.with(Cell::get);
+        (pool == &*self.inner as *const PoolInner as usize).then_some(index)
+    }
+
+    // jobs run on the caller when there are no workers, in sequential mode or
+    // when started from inside the pool
+    pub(crate) fn runs_inline(&self) -> bool {
+        self.inner.size == 0
+            || self.inner.sequential
+            || global::sequential()
+            || self.worker_index().is_some()
+    }
+
+    // the slot of the job the calling thread is running indices of
+    pub(crate) fn dispatch(&self) -> &Dispatch {
+        let current = INLINE.with(Cell::get);
+        debug_assert!(!current.is_null(), "not running a job of this pool");
+        unsafe { &*current }
+    }
+
+    pub fn join<A, B, RA, RB>(&self, a: A, b: B) -> (RA, RB)
+    where
+        A: FnOnce() -> RA + Send,
+        B: FnOnce() -> RB + Send,
+        RA: Send,
+        RB: Send,
+    {
+        let a = Mutex::new(Some(a));
+        let b = Mutex::new(Some(b));
+        let ra = Mutex::new(None);
+        let rb = Mutex::new(None);
+        self.execute(2, |i| {
+            match i {
+                0 => *ra.lock().unwrap() = a.lock().unwrap().take().map(|a| a()),
+                _ => *rb.lock().unwrap() = b.lock().unwrap().take().map(|b| b()),
+            }
+        });
+        (
+            ra.into_inner().unwrap().unwrap(),
+            rb.into_inner().unwrap().unwrap(),
+        )
+    }
+
+    // Runs `func` in a rayon pool owned by this pool and sized like it,
+    // created on first use. Its threads are separate from the ieu workers:
+    // the rayon work of a single caller is capped at the pool's size, but ieu
+    // jobs running at the same time still compete with it for the cores.
+    #[cfg(feature = "rayon")]
+    pub fn install<R: Send>(&self, func: impl FnOnce() -> R + Send) -> R {
+        let priority = self.inner.priority;
+        self.inner
+            .rayon
+            .get_or_init(|| {
+                let mut builder = rayon::ThreadPoolBuilder::new();
+                if let Some(stack_size) = self.inner.stack_size {
+                    builder = builder.stack_size(stack_size);
+                }
+                builder
+                    .num_threads(self.inner.size.max(1))
+                    .thread_name(|i| format!("ieu-rayon-{}", i))
+                    .start_handler(move |_| {
+                        if let Some(priority) = priority {
+                            sys::set_priority(priority);
+                        }
+                    })
+                    .build()
+                    .unwrap()
+            })
+            .install(func)
+    }
+
+    pub fn spawn(&self, func: impl FnOnce() + Send + 'static) {
+        if let Err(err) = self.try_spawn(func) {
+            panic!("{err}");
+        }
+    }
+
+    // runs `func` with a `Scope` whose tasks may borrow anything that
+    // outlives the call and waits for them; on one of the pool's own workers
+    // it runs queued tasks while waiting, like `in_place_scope`, since the
+    // tasks may need that very worker
+    pub fn scope<'scope, R>(&self, func: impl FnOnce(&Scope<'scope>) -> R) -> R {
+        let helping = self.worker_index().is_some().then_some(&*self.inner);
+        Scope::new(self).run(func, helping)
+    }
+
+    // Like `scope`, but instead of blocking until the spawned tasks are done
+    // the calling thread runs queued tasks of the pool itself meanwhile, so
+    // it works as one more worker for the scope: `Pool::new(n - 1)` plus the
+    // caller keeps `n` cores busy. The caller may pick up any task of the
+    // pool, not only the ones of this scope.
+    pub fn in_place_scope<'scope, R>(&self, func: impl FnOnce(&Scope<'scope>) -> R) -> R {
+        Scope::new(self).run(func, Some(&self.inner))
+    }
+
+    pub(crate) fn push_task(&self, task: Box<dyn FnOnce() + Send>) {
+        self.inner.push_task(task);
+    }
+
+    // blocks until no job is running or waiting for a slot and every spawned
+    // task (including the ones behind `execute_detached` and `submit`) has
+    // run, e.g. to drain background work before shutting down; work that
+    // keeps coming in from other threads can keep it waiting
+    pub fn wait_idle(&self) {
+        assert!(
+            self.worker_index().is_none(),
+            "wait_idle called from a job or task of the same pool"
+        );
+        let inner = &*self.inner;
+        let mut guard = inner.idle_lock.lock().unwrap();
+        while inner.pending.load(Ordering::SeqCst) != 0 {
+            guard = inner.idle.wait(guard).unwrap();
+        }
+    }
+
+    // Stops the workers from starting further indices or tasks until
+    // `resume`, e.g. to cede every core to a latency-critical phase. Returns
+    // right away, the indices and tasks already running finish first. Jobs
+    // submitted meanwhile wait for `resume`, and so does a job on the
+    // calling thread, unless the pool runs it inline.
+    pub fn pause(&self) {
+        self.inner.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        let _guard = self.inner.pause_lock.lock().unwrap();
+        self.inner.paused.store(false, Ordering::SeqCst);
+        self.inner.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(Ordering::SeqCst)
+    }
+}
+
+impl PoolInner {
+    pub(crate) fn push_task(&self, task: Box<dyn FnOnce() + Send>) {
+        if self.size == 0 {
+            return task();
+        }
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.tasks.lock().unwrap().push_back(task);
+        self.wake_for_task();
+    }
+
+    // `push_task` if the queue is below `max_queued_tasks`, otherwise the
+    // task comes back
+    pub(crate) fn try_push_task(&self, task: Task) -> Result<(), Task> {
+        if self.size == 0 {
+            task();
+            return Ok(());
+        }
+        let limit = self.queue_limit.map_or(usize::MAX, |(limit, _)| limit);
+        {
+            let mut tasks = self.tasks.lock().unwrap();
+            if tasks.len() >= limit {
+                return Err(task);
+            }
+            self.pending.fetch_add(1, Ordering::SeqCst);
+            tasks.push_back(task);
+        }
+        self.wake_for_task();
+        Ok(())
+    }
+
+    // `push_task` once the queue is below `limit`
+    pub(crate) fn push_task_blocking(&self, limit: usize, task: Task) {
+        {
+            let mut tasks = self.tasks.lock().unwrap();
+            while tasks.len() >= limit {
+                tasks = self.room.wait(tasks).unwrap();
+            }
+            self.pending.fetch_add(1, Ordering::SeqCst);
+            tasks.push_back(task);
+        }
+        self.wake_for_task();
+    }
+
+    fn wake_for_task(&self) {
+        let mut workers = self.workers.lock().unwrap();
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % workers.len();
+        self.wake(&mut workers, index);
+    }
+
+    fn pop_task(&self) -> Option<Task> {
+        let task = self.tasks.lock().unwrap().pop_front();
+        if task.is_some() && self.queue_limit.is_some() {
+            self.room.notify_one();
+        }
+        task
+    }
+
+    pub(crate) fn queued_jobs(&self) -> usize {
+        self.admission.queued()
+    }
+
+    pub(crate) fn queued_tasks(&self) -> usize {
+        let tasks = self
+            .tasks
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .len();
+        let worker_tasks = self
+            .worker_tasks
+            .iter()
+            .map(|queue| queue.lock().unwrap_or_else(|err| err.into_inner()).len());
+        tasks + worker_tasks.sum::<usize>()
+    }
+
+    // queues `func(index)` for every worker `index`, or runs `func(0)` right
+    // away in a pool without workers
+    pub(crate) fn push_broadcast(&self, func: std::sync::Arc<dyn Fn(usize) + Send + Sync>) {
+        if self.size == 0 {
+            return func(0);
+        }
+        self.pending.fetch_add(self.size, Ordering::SeqCst);
+        for (index, queue) in self.worker_tasks.iter().enumerate() {
+            let func = func.clone();
+            queue
+                .lock()
+                .unwrap()
+                .push_back(Box::new(move || func(index)));
+        }
+        self.wake_all();
+    }
+
+    // the CPU worker `index` is pinned to, if the pool pins its workers
+    pub(crate) fn worker_cpu(&self, index: usize) -> Option<usize> {
+        self.cores.as_ref().map(|cores| cores[index % cores.len()])
+    }
+
+    fn spawn_worker(&self, workers: &mut [Worker], index: usize) -> io::Result<()> {
+        if let Some(handle) = workers[index].handle.take() {
+            // the previous worker in this slot retired or died and is exiting
+            let _ = handle.join();
+        }
+        let ptr = self as *const PoolInner as usize;
+        let priority = self.priority;
+        let cpu = self.worker_cpu(index);
+        let size = self.size;
+        #[cfg(target_os = "linux")]
+        self.tids[index].store(0, Ordering::SeqCst);
+        let mut builder = thread::Builder::new();
+        if let Some(stack_size) = self.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        let handle = builder.spawn(move || {
+            if let Some(priority) = priority {
+                sys::set_priority(priority);
+            }
+            match cpu {
+                Some(cpu) => sys::pin_to_cpu(cpu),
+                None => sys::spread_over_groups(index, size),
+            }
+            WORKER.with(|cell| cell.set((ptr, index)));
+            IS_WORKER.with(|cell| cell.set(true));
+            let inner = unsafe { &*(ptr as *const PoolInner) };
+            #[cfg(target_os = "linux")]
+            inner.tids[index].store(sys::gettid(), Ordering::SeqCst);
+            let _exit = Exit(inner, index);
+            inner.work(index);
+            if let Some(hook) = &inner.worker_exit {
+                (hook.0)(index);
+            }
+        })?;
+        if !workers[index].alive {
+            self.dead.fetch_sub(1, Ordering::SeqCst);
+        }
+        workers[index] = Worker {
+            handle: Some(handle),
+            alive:  true,
+        };
+        Ok(())
+    }
+
+    // workers spawned on demand have no caller to report a failure to
+    fn respawn_worker(&self, workers: &mut [Worker], index: usize) {
+        self.spawn_worker(workers, index)
+            .expect("failed to spawn ieu worker");
+    }
+
+    fn wake(&self, workers: &mut [Worker], index: usize) {
+        match workers[index].alive {
+            true => {
+                self.signal
+                    .wake_one(workers[index].handle.as_ref().unwrap().thread())
+            },
+            false => self.respawn_worker(workers, index),
+        }
+    }
+
+    fn wake_all(&self) {
+        self.wake_job(self.size, self.size, None);
+    }
+
+    // Wakes the workers a job of `num` claims can use, out of the first
+    // `workers`; any more would only find nothing left to claim. A wake by
+    // count reaches whichever workers sleep, so it's only cut short when
+    // every worker may take part. Workers left out by `allowed` are only
+    // woken where waking by count can't pick the threads, and go straight
+    // back to sleep then.
+    fn wake_job(&self, num: usize, workers: usize, allowed: Option<&[bool]>) {
+        let count = num.min(workers).min(self.size);
+        let takes_part = |index: usize| allowed.is_none_or(|allowed| allowed[index]);
+        if self.dead.load(Ordering::SeqCst) == 0 && self.signal.wakes_by_count() {
+            return self
+                .signal
+                .wake_count(match workers >= self.size && allowed.is_none() {
+                    true => count,
+                    false => self.size,
+                });
+        }
+        let count = match allowed {
+            Some(_) => workers.min(self.size),
+            None => count,
+        };
+        let mut workers = self.workers.lock().unwrap();
+        for index in (0..count).filter(|&index| takes_part(index)) {
+            if !workers[index].alive {
+                self.respawn_worker(&mut workers, index);
+            }
+        }
+        self.signal.wake_all(
+            (0..count)
+                .filter(|&index| takes_part(index))
+                .filter_map(|index| workers[index].handle.as_ref())
+                .map(|handle| handle.thread()),
+        );
+    }
+
+    fn work(&self, index: usize) {
+        loop {
+            let epoch = self.signal.epoch();
+            self.pongs[index].store(self.ping.load(Ordering::SeqCst), Ordering::SeqCst);
+            let start = Instant::now();
+            self.run_tasks(index);
+            if self.shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+            self.run_job(index);
+            let (busy, start) = (start.elapsed(), Instant::now());
+            let retired = self.idle(index, epoch);
+            self.stats.worker(index, busy, start.elapsed());
+            if retired {
+                break;
+            }
+        }
+    }
+
+    // waits for the next wake, returns whether the worker retired instead
+    fn idle(&self, index: usize, epoch: u32) -> bool {
+        #[cfg(feature = "chaos")]
+        if let Some(chaos) = &self.chaos
+            && chaos.roll(4)
+        {
+            // a spurious wakeup, back to looking for work without a wake
+            self.signal.wait(epoch, Some(chaos.pause()));
+            return false;
+        }
+        match self.idle_timeout {
+            Some(timeout) => {
+                let start = Instant::now();
+                self.signal.wait(epoch, Some(timeout));
+                start.elapsed() >= timeout && self.retire(index)
+            },
+            None => {
+                self.signal.wait(epoch, None);
+                false
+            },
+        }
+    }
+
+    // starts a health check, returning it along with the workers expected to
+    // answer; retired and not yet spawned workers come back on demand
+    pub(crate) fn ping(&self) -> (usize, Vec<usize>) {
+        let ping = self.ping.fetch_add(1, Ordering::SeqCst) + 1;
+        let workers = self.workers.lock().unwrap();
+        self.signal.wake_all(
+            workers
+                .iter()
+                .filter(|worker| worker.alive)
+                .filter_map(|worker| worker.handle.as_ref())
+                .map(|handle| handle.thread()),
+        );
+        let alive = (0..workers.len()).filter(|&index| workers[index].alive);
+        (ping, alive.collect())
+    }
+
+    pub(crate) fn answered(&self, index: usize, ping: usize) -> bool {
+        self.pongs[index].load(Ordering::SeqCst) >= ping
+    }
+
+    // waits while the pool is paused, see `Pool::pause`
+    fn hold(&self) {
+        if !self.paused.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut guard = self.pause_lock.lock().unwrap();
+        while self.paused.load(Ordering::SeqCst) && !self.shutdown.load(Ordering::SeqCst) {
+            guard = self.resumed.wait(guard).unwrap();
+        }
+    }
+
+    // runs the tasks queued for worker `index` alone, then the shared ones
+    fn run_tasks(&self, index: usize) {
+        loop {
+            self.hold();
+            let task = self.worker_tasks[index].lock().unwrap().pop_front();
+            match task.or_else(|| self.pop_task()) {
+                Some(task) => self.run_queued(task),
+                None => break,
+            }
+        }
+    }
+
+    // runs the oldest shared task, if there is one
+    pub(crate) fn run_task(&self) -> bool {
+        self.hold();
+        self.pop_task().map(|task| self.run_queued(task)).is_some()
+    }
+
+    fn run_queued(&self, task: Task) {
+        // the panic hook has reported it, the thread carries on
+        let _ = panic::catch_unwind(AssertUnwindSafe(task));
+        self.stats.task();
+        self.settle();
+    }
+
+    // a pass over the slots, starting at a different one per worker so
+    // concurrent jobs get a share of the workers each; a job opened behind
+    // the pass changes the epoch and the worker comes straight back
+    fn run_job(&self, index: usize) {
+        for k in 0..SLOTS {
+            let slot = &self.slots[(index + k) % SLOTS];
+            if !slot.dispatch.is_open() {
+                continue;
+            }
+            INLINE.with(|cell| cell.set(&slot.dispatch));
+            #[cfg(feature = "chaos")]
+            if let Some(chaos) = &self.chaos {
+                chaos.delay();
+            }
+            #[cfg(feature = "puffin")]
+            let scope = Cell::new(None);
+            let last = slot.dispatch.run_hooked(
+                index,
+                || {
+                    #[cfg(feature = "puffin")]
+                    scope.set(crate::profile::worker(
+                        slot.label.get().as_deref().unwrap_or_default(),
+                    ));
+                    if let Some(hook) = &self.job_start {
+                        (hook.0)(index);
+                    }
+                },
+                || {
+                    if let Some(hook) = &self.job_end {
+                        (hook.0)(index);
+                    }
+                    #[cfg(feature = "puffin")]
+                    drop(scope.take());
+                },
+            );
+            if last {
+                self.wake_waiter(slot);
+            }
+            INLINE.with(|cell| cell.set(std::ptr::null()));
+        }
+    }
+
+    fn wake_waiter(&self, slot: &Slot) {
+        if self.signal.spins() {
+            return;
+        }
+        if let Some(waiter) = slot.waiter.get() {
+            waiter.unpark();
+        }
+    }
+
+    // one job or task less is pending
+    fn settle(&self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            drop(self.idle_lock.lock().unwrap());
+            self.idle.notify_all();
+        }
+    }
+
+    // waits for a wakeup from the workers, at most until `deadline`
+    fn wait_for_job(&self, start: Instant, deadline: Option<Instant>) {
+        match (self.signal.lingers(start), deadline) {
+            (true, _) => self.signal.relax(),
+            (false, Some(deadline)) => {
+                sync::park_timeout(deadline.saturating_duration_since(Instant::now()))
+            },
+            (false, None) => thread::park(),
+        }
+    }
+
+    fn retire(&self, index: usize) -> bool {
+        // decided under the workers lock so that a submitter either sees this
+        // worker as retired and respawns it, or this worker sees the new work;
+        // submitters that skip the lock see `dead` raised before the check
+        let mut workers = self.workers.lock().unwrap();
+        self.dead.fetch_add(1, Ordering::SeqCst);
+        if self.slots.iter().any(|slot| slot.dispatch.is_open())
+            || !self.tasks.lock().unwrap().is_empty()
+            || !self.worker_tasks[index].lock().unwrap().is_empty()
+            || self.shutdown.load(Ordering::SeqCst)
+        {
+            self.dead.fetch_sub(1, Ordering::SeqCst);
+            return false;
+        }
+        workers[index].alive = false;
+        true
+    }
+}
+
+#[cfg(feature = "log")]
+struct JobName<'a>(usize, Option<&'a str>);
+
+#[cfg(feature = "log")]
+impl std::fmt::Display for JobName<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.1 {
+            Some(label) => write!(f, "ieu job {} ({})", self.0, label),
+            None => write!(f, "ieu job {}", self.0),
+        }
+    }
+}
+
+// Counts a job as pending from its submission until it is done, or its
+// submitter unwinds.
+struct Pending<'a>(&'a PoolInner);
+
+impl Pending<'_> {
+    fn new(pool: &PoolInner) -> Pending<'_> {
+        pool.pending.fetch_add(1, Ordering::SeqCst);
+        Pending(pool)
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        self.0.settle();
+    }
+}
+
+// Jobs and tasks can't take a worker down, but should one die anyway it's
+// marked dead so the next wake respawns it, and the submitter of the current
+// job rechecks it in case this was the last worker inside.
+struct Exit<'a>(&'a PoolInner, usize);
+
+impl Drop for Exit<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            let mut workers = self.0.workers.lock().unwrap_or_else(|err| err.into_inner());
+            workers[self.1].alive = false;
+            self.0.dead.fetch_add(1, Ordering::SeqCst);
+            for slot in &self.0.slots {
+                self.0.wake_waiter(slot);
+            }
+        }
+    }
+}
+
+// Ends a job and frees its closure once no worker is inside it anymore. This
+// also runs when the submitter unwinds out of an index it ran inline, so the
+// closure and its borrows always outlive every call into it.
+struct Finish<'a> {
+    pool:     &'a PoolInner,
+    dispatch: &'a Dispatch,
+    func:     *mut Func,
+}
+
+impl Drop for Finish<'_> {
+    fn drop(&mut self) {
+        // workers that entered before the job was closed may still be about
+        // to look at it, wait for them to leave before freeing the closure
+        self.dispatch.stop();
+        self.dispatch.close();
+        // checked first so jobs run inline never read the clock, which
+        // panics on wasm32-unknown-unknown
+        if !self.dispatch.is_idle() {
+            let start = Instant::now();
+            while !self.dispatch.is_idle() {
+                self.pool.wait_for_job(start, None);
+            }
+        }
+        unsafe { dispatch::free(self.func) };
+    }
+}
+
+// What a hung pool is up to: which workers are running, how far each open
+// job got and how many tasks are queued. Workers inside a job that has handed
+// out every index are still finishing theirs.
+impl std::fmt::Debug for Pool {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        struct Job {
+            slot:           usize,
+            handed_out:     usize,
+            num:            usize,
+            workers_inside: usize,
+        }
+
+        let inner = &*self.inner;
+        let alive_workers = {
+            let workers = inner.workers.lock().unwrap_or_else(|err| err.into_inner());
+            (0..workers.len())
+                .filter(|&index| workers[index].alive)
+                .collect::<Vec<_>>()
+        };
+        let jobs = (0..SLOTS)
+            .filter(|&slot| inner.slots[slot].dispatch.is_open())
+            .map(|slot| {
+                let (handed_out, num, workers_inside) = inner.slots[slot].dispatch.progress();
+                Job {
+                    slot,
+                    handed_out,
+                    num,
+                    workers_inside,
+                }
+            })
+            .collect::<Vec<_>>();
+        let queued_tasks = inner.queued_tasks();
+        f.debug_struct("Pool")
+            .field("num_threads", &inner.size)
+            .field("alive_workers", &alive_workers)
+            .field("jobs", &jobs)
+            .field("queued_jobs", &inner.queued_jobs())
+            .field("queued_tasks", &queued_tasks)
+            .field("pending", &inner.pending.load(Ordering::SeqCst))
+            .field("paused", &inner.paused.load(Ordering::SeqCst))
+            .field("shutdown", &inner.shutdown.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        *self.inner.live.lock().unwrap() = false;
+        self.inner.shutdown.store(true, Ordering::SeqCst);
+        // paused workers let go to exit
+        drop(self.inner.pause_lock.lock().unwrap());
+        self.inner.resumed.notify_all();
+        let handles = self
+            .inner
+            .workers
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter_map(|worker| worker.handle.take())
+            .collect::<Vec<_>>();
+        self.inner
+            .signal
+            .wake_all(handles.iter().map(|handle| handle.thread()));
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,13 @@
+// Scopes for the `puffin` profiler, tagged with the job's label (empty for
+// unlabeled jobs): `ieu_job` on the submitting thread from submission until
+// the job is done, and `ieu_worker` on every worker from the first to the
+// last index it runs of a job.
+use puffin::ProfilerScope;
+
+pub(crate) fn job(label: &str) -> Option<ProfilerScope> {
+    puffin::profile_scope_custom!("ieu_job", label)
+}
+
+pub(crate) fn worker(label: &str) -> Option<ProfilerScope> {
+    puffin::profile_scope_custom!("ieu_worker", label)
+}
@@ -0,0 +1,53 @@
+use std::sync::Mutex;
+
+use pyo3::prelude::*;
+
+#[pyclass(name = "Pool", module = "ieu")]
+pub struct PyPool {
+    pool: crate::Pool,
+}
+
+#[pymethods]
+impl PyPool {
+    #[new]
+    #[pyo3(signature = (num_threads = None))]
+    fn new(num_threads: Option<usize>) -> PyPool {
+        let builder = crate::Builder::new();
+        PyPool {
+            pool: match num_threads {
+                Some(num_threads) => builder.num_threads(num_threads),
+                None => builder,
+            }
+            .build(),
+        }
+    }
+
+    #[getter]
+    fn num_threads(&self) -> usize {
+        self.pool.num_threads()
+    }
+
+    // the GIL is released while the job runs and re-acquired by each worker
+    // around its call into `func`, the first exception raised is re-raised
+    fn execute(&self, py: Python<'_>, num: usize, func: PyObject) -> PyResult<()> {
+        let error = Mutex::new(None);
+        let pool = &self.pool;
+        py.allow_threads(|| {
+            pool.execute(num, |i| {
+                Python::with_gil(|py| {
+                    if let Err(err) = func.call1(py, (i,)) {
+                        error.lock().unwrap().get_or_insert(err);
+                    }
+                });
+            });
+        });
+        match error.into_inner().unwrap() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyPool>()
+}
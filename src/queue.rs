@@ -0,0 +1,94 @@
+use std::{fmt, str::FromStr};
+
+use crate::{pool::PoolInner, Pool};
+
+/// What `spawn` and `submit` do when the task queue of a pool built with
+/// `Builder::max_queued_tasks` is full. Parsed from `block`, `reject` or
+/// `inline` in any case.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Wait until a worker takes a task off the queue.
+    #[default]
+    Block,
+    /// Fail with `QueueFull` from `try_spawn` and `try_submit`, and panic
+    /// from `spawn` and `submit`.
+    Reject,
+    /// Run the task on the calling thread instead.
+    RunInline,
+}
+
+impl FromStr for QueuePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<QueuePolicy, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "block" => Ok(QueuePolicy::Block),
+            "reject" => Ok(QueuePolicy::Reject),
+            "inline" | "run_inline" => Ok(QueuePolicy::RunInline),
+            _ => Err(format!("unknown queue policy {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for QueuePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            QueuePolicy::Block => "block",
+            QueuePolicy::Reject => "reject",
+            QueuePolicy::RunInline => "inline",
+        })
+    }
+}
+
+/// The task queue was full and the pool's `QueuePolicy` is `Reject`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueFull;
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the task queue of the pool is full")
+    }
+}
+
+impl std::error::Error for QueueFull {}
+
+impl Pool {
+    // `spawn`, failing instead of panicking when the queue is full and the
+    // pool rejects tasks then
+    pub fn try_spawn(&self, func: impl FnOnce() + Send + 'static) -> Result<(), QueueFull> {
+        self.inner
+            .queue_task(Box::new(func), self.worker_index().is_some())
+    }
+}
+
+impl PoolInner {
+    // Queues a task through `max_queued_tasks`, if the pool has a limit.
+    // Workers never block on their own pool's queue, nobody might be left
+    // to empty it, so they run the task themselves instead.
+    pub(crate) fn queue_task(
+        &self,
+        task: Box<dyn FnOnce() + Send>,
+        on_worker: bool,
+    ) -> Result<(), QueueFull> {
+        let Some((limit, policy)) = self.queue_limit else {
+            self.push_task(task);
+            return Ok(());
+        };
+        match self.try_push_task(task) {
+            Ok(()) => Ok(()),
+            Err(task) => {
+                match policy {
+                    QueuePolicy::Reject => Err(QueueFull),
+                    QueuePolicy::Block if !on_worker => {
+                        self.push_task_blocking(limit, task);
+                        Ok(())
+                    },
+                    _ => {
+                        task();
+                        Ok(())
+                    },
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+use crate::{cpu, pool::PoolInner, sync::Ordering, Pool};
+
+// how often a pool re-reads the CPU quota and when it last did
+pub(crate) type Quota = (Duration, std::sync::Mutex<Instant>);
+
+impl Pool {
+    // the workers that take part in jobs: `num_threads`, unless lowered by
+    // `set_active_threads` or a CPU quota (see `Builder::track_cpu_quota`)
+    pub fn active_threads(&self) -> usize {
+        self.inner.active.load(Ordering::SeqCst)
+    }
+
+    // Lets only the first `num` workers (at least one, at most
+    // `num_threads`) take part in the jobs started from now on, the others
+    // stay asleep. Broadcasts still run on every worker.
+    pub fn set_active_threads(&self, num: usize) {
+        self.inner
+            .active
+            .store(num.clamp(1, self.inner.size.max(1)), Ordering::SeqCst);
+    }
+
+    // re-reads the CPU quota of the process, e.g. after a container's limit
+    // changed at runtime, and lets as many workers take part in jobs as it
+    // allows; returns that number
+    pub fn refresh_cpu_quota(&self) -> usize {
+        self.set_active_threads(cpu::available());
+        self.active_threads()
+    }
+}
+
+impl PoolInner {
+    // the workers the next job may use, re-reading the CPU quota first if
+    // the pool tracks it and the last read is older than its interval
+    pub(crate) fn job_threads(&self) -> usize {
+        self.autoscale();
+        // whoever holds the lock is already reading it
+        if let Some((interval, read)) = &self.quota
+            && let Ok(mut read) = read.try_lock()
+            && read.elapsed() >= *interval
+        {
+            *read = Instant::now();
+            self.active.store(
+                cpu::available().clamp(1, self.size.max(1)),
+                Ordering::SeqCst,
+            );
+        }
+        self.active.load(Ordering::SeqCst)
+    }
+}
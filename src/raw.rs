@@ -0,0 +1,171 @@
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+
+use crate::{
+    dispatch::{self, Dispatch, Waiter},
+    sync::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// Supplies the threads for a [`RawPool`], for platforms where they don't come
+/// from `std`.
+///
+/// `park` and `unpark` must follow `std::thread` token semantics: an `unpark`
+/// issued before the target parks makes its next `park` return immediately,
+/// and `park` may also return spuriously.
+pub trait ThreadProvider: Send + Sync + 'static {
+    type Thread: Clone + Send + Sync + 'static;
+
+    fn spawn(&self, func: Box<dyn FnOnce() + Send>) -> Self::Thread;
+
+    fn current(&self) -> Self::Thread;
+
+    fn park(&self);
+
+    fn unpark(&self, thread: &Self::Thread);
+}
+
+/// A pool running on threads from a [`ThreadProvider`], usable without `std`.
+/// It only supports `execute`, everything else needs the `std` [`Pool`].
+///
+/// [`Pool`]: crate::Pool
+pub struct RawPool<P: ThreadProvider> {
+    threads: Vec<P::Thread>,
+    inner:   Arc<RawInner<P>>,
+}
+
+struct RawInner<P: ThreadProvider> {
+    provider: P,
+    dispatch: Dispatch,
+    waiter:   Waiter<P::Thread>,
+    shutdown: AtomicBool,
+    exited:   AtomicUsize,
+}
+
+impl<P: ThreadProvider> RawPool<P> {
+    pub fn new(provider: P, size: usize) -> RawPool<P> {
+        let inner = Arc::new(RawInner {
+            provider,
+            dispatch: Dispatch::new(),
+            waiter: Waiter::new(),
+            shutdown: AtomicBool::new(false),
+            exited: AtomicUsize::new(0),
+        });
+        let threads = (0..size)
+            .map(|index| {
+                let worker = inner.clone();
+                inner.provider.spawn(Box::new(move || worker.work(index)))
+            })
+            .collect();
+        RawPool { threads, inner }
+    }
+
+    /// Runs `func` for every index in `0..num` on the pool's threads, or on
+    /// the calling thread in a pool of size 0. With `std`, a panicking index
+    /// stops the job and the panic is resumed here once the threads are done
+    /// with it.
+    pub fn execute(&mut self, num: usize, func: impl Fn(usize) + Send + Sync) {
+        if self.threads.is_empty() {
+            return (0..num).for_each(func);
+        }
+        let inner = &*self.inner;
+        #[cfg(feature = "std")]
+        let panicked = crate::sync::Mutex::new(None::<Box<dyn core::any::Any + Send>>);
+        let func = |_, i| {
+            #[cfg(feature = "std")]
+            if let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| func(i)))
+            {
+                panicked.lock().unwrap().get_or_insert(payload);
+                inner.dispatch.stop();
+            }
+            #[cfg(not(feature = "std"))]
+            func(i);
+        };
+        let func = unsafe { dispatch::erase(func) };
+        inner.waiter.set(Some(inner.provider.current()));
+        unsafe { inner.dispatch.open(num, usize::MAX, func) };
+        for thread in &self.threads {
+            inner.provider.unpark(thread);
+        }
+        while !inner.dispatch.is_done(num) {
+            inner.provider.park();
+        }
+        inner.dispatch.close();
+        while !inner.dispatch.is_idle() {
+            inner.provider.park();
+        }
+        inner.waiter.set(None);
+        unsafe { dispatch::free(func) };
+        #[cfg(feature = "std")]
+        if let Some(payload) = panicked.into_inner().unwrap() {
+            std::panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl<P: ThreadProvider> RawInner<P> {
+    fn work(&self, index: usize) {
+        // counted even when a job unwinds out of the worker, or dropping the
+        // pool would wait for it forever
+        let _exit = Exit(self);
+        while !self.shutdown.load(Ordering::SeqCst) {
+            if self.dispatch.run(index) {
+                self.wake_waiter();
+            }
+            self.provider.park();
+        }
+    }
+
+    fn wake_waiter(&self) {
+        if let Some(waiter) = self.waiter.get() {
+            self.provider.unpark(&waiter);
+        }
+    }
+}
+
+struct Exit<'a, P: ThreadProvider>(&'a RawInner<P>);
+
+impl<P: ThreadProvider> Drop for Exit<'_, P> {
+    fn drop(&mut self) {
+        self.0.exited.fetch_add(1, Ordering::SeqCst);
+        self.0.wake_waiter();
+    }
+}
+
+impl<P: ThreadProvider> Drop for RawPool<P> {
+    fn drop(&mut self) {
+        let inner = &*self.inner;
+        inner.waiter.set(Some(inner.provider.current()));
+        inner.shutdown.store(true, Ordering::SeqCst);
+        for thread in &self.threads {
+            inner.provider.unpark(thread);
+        }
+        while inner.exited.load(Ordering::SeqCst) < self.threads.len() {
+            inner.provider.park();
+        }
+    }
+}
+
+/// A [`ThreadProvider`] backed by `std::thread`.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdThreads;
+
+#[cfg(feature = "std")]
+impl ThreadProvider for StdThreads {
+    type Thread = std::thread::Thread;
+
+    fn spawn(&self, func: Box<dyn FnOnce() + Send>) -> std::thread::Thread {
+        std::thread::spawn(func).thread().clone()
+    }
+
+    fn current(&self) -> std::thread::Thread {
+        std::thread::current()
+    }
+
+    fn park(&self) {
+        std::thread::park();
+    }
+
+    fn unpark(&self, thread: &std::thread::Thread) {
+        thread.unpark();
+    }
+}
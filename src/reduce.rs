@@ -0,0 +1,300 @@
+use std::{cmp::Ordering, collections::HashMap, hash::Hash, iter::Sum};
+
+use crate::{iter::SyncPtr, local::PerWorker, Pool};
+
+/// A reusable aggregate for `Pool::map_reduce`: every worker accumulates the
+/// mapped items into a combiner of its own, and those are merged into one
+/// once the job is done. Merging must not depend on which items ended up in
+/// which combiner, e.g. sums, counts, min/max or sketches like HyperLogLog.
+pub trait Combiner: Send {
+    type Item;
+
+    /// The combiner that hasn't seen any items, and merges into any other
+    /// without changing it.
+    fn identity() -> Self;
+
+    fn accumulate(&mut self, item: Self::Item);
+
+    fn merge(&mut self, other: Self);
+}
+
+// indices per leaf of `par_tree_reduce`, fixed so the shape of the tree only
+// depends on the number of indices
+const TREE_LEAF: usize = 1024;
+
+// elements per claim when `par_reduce_buffers` combines two buffers
+const COMBINE_CHUNK: usize = 4096;
+
+impl Pool {
+    // splits `data` into a few chunks per worker, folds every chunk and
+    // combines the results into one partial per worker, then combines those
+    pub(crate) fn fold_chunks<'a, T: Sync, A: Send>(
+        &self,
+        data: &'a [T],
+        fold: impl Fn(&'a [T]) -> A + Send + Sync,
+        combine: impl Fn(A, A) -> A + Send + Sync,
+    ) -> Option<A> {
+        let chunk_len = data.len().div_ceil(self.num_threads().max(1) * 4).max(1);
+        let chunks = data.len().div_ceil(chunk_len);
+        let partials = PerWorker::new(self.num_threads(), || None);
+        self.run(chunks, |worker, i| {
+            let part = fold(&data[i * chunk_len..((i + 1) * chunk_len).min(data.len())]);
+            let partial = unsafe { partials.get(worker) };
+            *partial = Some(match partial.take() {
+                Some(acc) => combine(acc, part),
+                None => part,
+            });
+        });
+        partials.into_inner().into_iter().flatten().reduce(combine)
+    }
+
+    // every worker accumulates into its own `B`, the per-worker values are
+    // merged on the calling thread once the job is done
+    pub fn par_accumulate<B: Send>(
+        &self,
+        num: usize,
+        init: impl FnMut() -> B,
+        func: impl Fn(&mut B, usize) + Send + Sync,
+        merge: impl Fn(&mut B, B),
+    ) -> B {
+        let partials = PerWorker::new(self.num_threads(), init);
+        self.run(num, |worker, i| func(unsafe { partials.get(worker) }, i));
+        let mut partials = partials.into_inner().into_iter();
+        let mut acc = partials.next().unwrap();
+        for partial in partials {
+            merge(&mut acc, partial);
+        }
+        acc
+    }
+
+    // maps every index to an item and folds the items into one combiner, see
+    // `Combiner`; `init` is usually `C::identity`, or a closure for combiners
+    // that need parameters only known at runtime
+    pub fn map_reduce<C: Combiner>(
+        &self,
+        num: usize,
+        map: impl Fn(usize) -> C::Item + Send + Sync,
+        init: impl FnMut() -> C,
+    ) -> C {
+        self.par_accumulate(num, init, |acc, i| acc.accumulate(map(i)), C::merge)
+    }
+
+    // every worker collects into its own `C`, the per-worker collections are
+    // merged by extending one with the others, so no lock is shared
+    pub fn par_collect<C, T>(&self, num: usize, func: impl Fn(usize) -> T + Send + Sync) -> C
+    where C: Default + Extend<T> + IntoIterator<Item = T> + Send {
+        self.par_accumulate(
+            num,
+            C::default,
+            |acc, i| acc.extend(Some(func(i))),
+            |acc, part| acc.extend(part),
+        )
+    }
+
+    // appends `func(0), func(1), ..` to `target` in index order: the items are
+    // computed into a buffer per chunk of indices on the workers, and the
+    // buffers are appended in order on the calling thread
+    pub fn par_extend<C: Extend<T>, T: Send>(
+        &self,
+        target: &mut C,
+        num: usize,
+        func: impl Fn(usize) -> T + Send + Sync,
+    ) {
+        let chunk_len = num.div_ceil(self.num_threads().max(1) * 4).max(1);
+        let buffers = self.vec_from_fn(num.div_ceil(chunk_len), |c| {
+            (c * chunk_len..((c + 1) * chunk_len).min(num))
+                .map(&func)
+                .collect::<Vec<_>>()
+        });
+        target.extend(buffers.into_iter().flatten());
+    }
+
+    // like `par_collect` into a `HashMap`, but values for a key that comes up
+    // more than once are combined with `resolve(existing, new)`; which of two
+    // values counts as existing depends on the order the workers ran in
+    pub fn par_collect_map<K: Eq + Hash + Send, V: Send>(
+        &self,
+        num: usize,
+        func: impl Fn(usize) -> (K, V) + Send + Sync,
+        resolve: impl Fn(V, V) -> V + Send + Sync,
+    ) -> HashMap<K, V> {
+        let insert = |map: &mut HashMap<K, V>, (key, value)| {
+            let value = match map.remove(&key) {
+                Some(existing) => resolve(existing, value),
+                None => value,
+            };
+            map.insert(key, value);
+        };
+        self.par_accumulate(
+            num,
+            HashMap::new,
+            |map, i| insert(map, func(i)),
+            |acc, map| {
+                for entry in map {
+                    insert(acc, entry);
+                }
+            },
+        )
+    }
+
+    // counts how many indices `func` puts in each of `buckets` buckets
+    pub fn par_histogram(
+        &self,
+        num: usize,
+        buckets: usize,
+        func: impl Fn(usize) -> usize + Send + Sync,
+    ) -> Vec<u64> {
+        self.par_accumulate(
+            num,
+            || vec![0; buckets],
+            |hist, i| hist[func(i)] += 1,
+            |acc, hist| {
+                for (acc, count) in acc.iter_mut().zip(hist) {
+                    *acc += count;
+                }
+            },
+        )
+    }
+
+    // Every worker taking part fills a buffer of its own of `len` elements,
+    // starting out as `identity`, with `func(buffer, i)` for its indices,
+    // e.g. to accumulate gradients or per-bucket statistics. The buffers are
+    // then combined element-wise with `combine(acc, other)` pairwise, level
+    // by level, every level split into chunks across the workers, so no
+    // thread merges them all one after another.
+    pub fn par_reduce_buffers<T: Clone + Send + Sync>(
+        &self,
+        num: usize,
+        len: usize,
+        identity: T,
+        func: impl Fn(&mut [T], usize) + Send + Sync,
+        combine: impl Fn(&mut T, &T) + Send + Sync,
+    ) -> Vec<T> {
+        // only the workers that get an index allocate a buffer
+        let buffers = PerWorker::new(self.num_threads(), || None::<Vec<T>>);
+        self.run(num, |worker, i| {
+            let buffer = unsafe { buffers.get(worker) };
+            func(buffer.get_or_insert_with(|| vec![identity.clone(); len]), i);
+        });
+        let mut buffers = buffers
+            .into_inner()
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let ptrs = buffers
+            .iter_mut()
+            .map(|buffer| SyncPtr(buffer.as_mut_ptr()))
+            .collect::<Vec<_>>();
+        let chunks = len.div_ceil(COMBINE_CHUNK);
+        let mut stride = 1;
+        while stride < buffers.len() {
+            // buffer `2 * stride * pair` takes in the one `stride` after it
+            let pairs = (buffers.len() - stride).div_ceil(2 * stride);
+            self.run(pairs * chunks, |_, k| {
+                let (pair, chunk) = (k / chunks, k % chunks);
+                let (acc, other) = (2 * stride * pair, 2 * stride * pair + stride);
+                let start = chunk * COMBINE_CHUNK;
+                let end = (start + COMBINE_CHUNK).min(len);
+                // every claim combines its own range of a pair of buffers
+                let (acc, other) = unsafe {
+                    (
+                        std::slice::from_raw_parts_mut(ptrs[acc].get().add(start), end - start),
+                        std::slice::from_raw_parts(ptrs[other].get().add(start), end - start),
+                    )
+                };
+                for (acc, other) in acc.iter_mut().zip(other) {
+                    combine(acc, other);
+                }
+            });
+            stride *= 2;
+        }
+        match buffers.is_empty() {
+            true => vec![identity; len],
+            false => buffers.swap_remove(0),
+        }
+    }
+
+    // reduces the values of `map` over `0..num` in a fixed order: leaves of
+    // `TREE_LEAF` consecutive indices are folded left to right, then combined
+    // pairwise level by level, so results such as float sums come out bit for
+    // bit the same regardless of thread count or timing
+    pub fn par_tree_reduce<A: Send>(
+        &self,
+        num: usize,
+        map: impl Fn(usize) -> A + Send + Sync,
+        combine: impl Fn(A, A) -> A + Send + Sync,
+    ) -> Option<A> {
+        let mut level = self.vec_from_fn(num.div_ceil(TREE_LEAF), |leaf| {
+            (leaf * TREE_LEAF..((leaf + 1) * TREE_LEAF).min(num))
+                .map(&map)
+                .reduce(&combine)
+                .unwrap()
+        });
+        while level.len() > 1 {
+            let mut next = Vec::with_capacity(level.len().div_ceil(2));
+            let mut iter = level.into_iter();
+            while let Some(a) = iter.next() {
+                next.push(match iter.next() {
+                    Some(b) => combine(a, b),
+                    None => a,
+                });
+            }
+            level = next;
+        }
+        level.pop()
+    }
+
+    pub fn par_sum_tree<T: Copy + Send + Sync + Sum<T>>(&self, data: &[T]) -> T {
+        self.par_tree_reduce(data.len(), |i| data[i], |a, b| [a, b].into_iter().sum())
+            .unwrap_or_else(|| std::iter::empty().sum())
+    }
+
+    pub fn par_sum<T: Copy + Send + Sync + Sum<T>>(&self, data: &[T]) -> T {
+        self.fold_chunks(
+            data,
+            |chunk| chunk.iter().copied().sum::<T>(),
+            |a, b| [a, b].into_iter().sum(),
+        )
+        .unwrap_or_else(|| std::iter::empty().sum())
+    }
+
+    pub fn par_min_by<'a, T: Sync>(
+        &self,
+        data: &'a [T],
+        cmp: impl Fn(&T, &T) -> Ordering + Send + Sync,
+    ) -> Option<&'a T> {
+        self.fold_chunks(
+            data,
+            |chunk| chunk.iter().min_by(|a, b| cmp(a, b)).unwrap(),
+            |a, b| {
+                match cmp(a, b).then(position(a).cmp(&position(b))) {
+                    Ordering::Greater => b,
+                    _ => a,
+                }
+            },
+        )
+    }
+
+    pub fn par_max_by<'a, T: Sync>(
+        &self,
+        data: &'a [T],
+        cmp: impl Fn(&T, &T) -> Ordering + Send + Sync,
+    ) -> Option<&'a T> {
+        self.fold_chunks(
+            data,
+            |chunk| chunk.iter().max_by(|a, b| cmp(a, b)).unwrap(),
+            |a, b| {
+                match cmp(a, b).then(position(a).cmp(&position(b))) {
+                    Ordering::Greater => a,
+                    _ => b,
+                }
+            },
+        )
+    }
+}
+
+// chunks are combined in whatever order the workers finish them, ties are
+// broken by where the elements sit in the slice to match std's min_by/max_by
+fn position<T>(item: &T) -> usize {
+    item as *const T as usize
+}
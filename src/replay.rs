@@ -0,0 +1,113 @@
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+};
+
+use crate::{
+    sync::{thread, AtomicBool, AtomicUsize, Ordering},
+    Pool,
+};
+
+/// Which worker ran which index of a job run with `Pool::execute_recorded`,
+/// to run the same job again with exactly that assignment through
+/// `Pool::execute_replayed`, e.g. to reproduce a bug that only shows up
+/// under one interleaving. Indices are claimed in order, so the claim order
+/// is the index order and only the worker of every index needs recording.
+///
+/// A schedule prints as the worker of every index separated by commas, and
+/// parses back from that, so it can be kept in a file or an environment
+/// variable between runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schedule {
+    workers: Vec<usize>,
+}
+
+impl Schedule {
+    /// The number of indices of the recorded job.
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// The worker that ran index `i`.
+    pub fn worker(&self, i: usize) -> usize {
+        self.workers[i]
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, worker) in self.workers.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{worker}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Schedule, Self::Err> {
+        let workers = match s.trim() {
+            "" => Vec::new(),
+            s => {
+                s.split(',')
+                    .map(|w| w.trim().parse())
+                    .collect::<Result<_, _>>()?
+            },
+        };
+        Ok(Schedule { workers })
+    }
+}
+
+impl Pool {
+    // like `execute`, recording which worker ran which index
+    pub fn execute_recorded(&self, num: usize, func: impl Fn(usize) + Send + Sync) -> Schedule {
+        let workers = (0..num).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        self.run(num, |worker, i| {
+            workers[i].store(worker, Ordering::Relaxed);
+            func(i);
+        });
+        Schedule {
+            workers: workers.into_iter().map(AtomicUsize::into_inner).collect(),
+        }
+    }
+
+    // Runs `func` over the indices of `schedule` with the recorded
+    // assignment: every index runs on the worker that ran it before, and
+    // starts only once every index below it has started. Panics if the
+    // schedule names a worker this pool doesn't have.
+    pub fn execute_replayed(&self, schedule: &Schedule, func: impl Fn(usize) + Send + Sync) {
+        let team = self.team_size();
+        if let Some(&worker) = schedule.workers.iter().find(|&&worker| worker >= team) {
+            panic!("the schedule needs worker {worker}, but the pool runs jobs on {team}");
+        }
+        // the next index to start, and whether one of them panicked so the
+        // workers waiting for their turn give up
+        let turn = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        self.run_per_worker(|worker| {
+            let mine = (0..schedule.len()).filter(|&i| schedule.workers[i] == worker);
+            for i in mine {
+                while turn.load(Ordering::SeqCst) != i {
+                    if failed.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    thread::yield_now();
+                }
+                turn.store(i + 1, Ordering::SeqCst);
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| func(i))) {
+                    failed.store(true, Ordering::SeqCst);
+                    panic::resume_unwind(payload);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,69 @@
+/// A small, fast random number generator (xoshiro256++) whose stream only
+/// depends on a seed and a job index, see `rng_for_index`. Not suitable for
+/// cryptography.
+#[derive(Clone, Debug)]
+pub struct IndexRng {
+    state: [u64; 4],
+}
+
+/// Returns the generator for index `index` of a job seeded with `seed`.
+/// Every index gets its own stream, so Monte-Carlo style jobs produce the
+/// same numbers no matter which worker runs which index or how many workers
+/// there are.
+pub fn rng_for_index(seed: u64, index: usize) -> IndexRng {
+    // neighbouring seeds and indices are mixed into unrelated states, and the
+    // four state words are expanded from that with splitmix64 as recommended
+    // for seeding xoshiro
+    let mut mix = splitmix(splitmix(seed) ^ index as u64);
+    let mut state = [0; 4];
+    for word in &mut state {
+        mix = mix.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        *word = splitmix(mix);
+    }
+    IndexRng { state }
+}
+
+fn splitmix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl IndexRng {
+    pub fn next_u64(&mut self) -> u64 {
+        let [a, b, c, d] = &mut self.state;
+        let result = a.wrapping_add(*d).rotate_left(23).wrapping_add(*a);
+        let t = *b << 17;
+        *c ^= *a;
+        *d ^= *b;
+        *b ^= *c;
+        *a ^= *d;
+        *c ^= t;
+        *d = d.rotate_left(45);
+        result
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    // uniform in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    // uniform in `0..bound`, panics if `bound` is 0
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "bound must be positive");
+        // Lemire's multiply-shift, rejecting the few values that would bias
+        // the result
+        let threshold = bound.wrapping_neg() % bound;
+        loop {
+            let wide = self.next_u64() as u128 * bound as u128;
+            if wide as u64 >= threshold {
+                return (wide >> 64) as u64;
+            }
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use core::{ops::Range, sync::atomic::AtomicU64};
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    sync::{Arc, AtomicBool, AtomicUsize, Mutex, Ordering},
+    Pool,
+};
+
+// how long one chunk of `execute_chunked` should take after `calibrate`, long
+// enough that claiming it is noise but short enough to balance the workers
+const CHUNK_TIME: Duration = Duration::from_micros(50);
+// the same for pools built with `Builder::power_saving`, fewer claims keep
+// the workers asleep longer between bursts
+const POWER_SAVING_CHUNK_TIME: Duration = Duration::from_micros(200);
+// how long `calibrate` measures for, and the most indices it samples
+const SAMPLE_TIME: Duration = Duration::from_millis(2);
+const SAMPLE_MAX: usize = 1 << 20;
+
+/// The high-water mark of an `execute_from` job: every index below `get()`
+/// has completed, so an interrupted job can be resumed by passing the same
+/// checkpoint to `execute_from` again. Clones share the mark, which can be
+/// read from any thread while the job runs.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    mark: Arc<AtomicUsize>,
+}
+
+impl Checkpoint {
+    /// A checkpoint for a job that starts at index `start`.
+    pub fn new(start: usize) -> Checkpoint {
+        Checkpoint {
+            mark: Arc::new(AtomicUsize::new(start)),
+        }
+    }
+
+    pub fn get(&self) -> usize {
+        self.mark.load(Ordering::SeqCst)
+    }
+}
+
+impl Pool {
+    // like `execute`, but indices are handed out in chunks of roughly equal
+    // total `cost` rather than one by one, for jobs where a few indices are
+    // far more expensive than the rest
+    pub fn execute_weighted_by(
+        &self,
+        num: usize,
+        cost: impl Fn(usize) -> u64 + Send + Sync,
+        func: impl Fn(usize) + Send + Sync,
+    ) {
+        let bounds = weighted_bounds(&self.vec_from_fn(num, cost), self.num_threads().max(1) * 4);
+        self.run(bounds.len() - 1, |_, chunk| {
+            for i in bounds[chunk]..bounds[chunk + 1] {
+                func(i);
+            }
+        });
+    }
+
+    // Like `execute`, with factoring self-scheduling: the indices are handed
+    // out in rounds of one chunk per worker, each round's chunks covering
+    // half of the indices still left, so early claims are large and cheap
+    // and the small ones at the end even out irregular indices.
+    pub fn execute_factoring(&self, num: usize, func: impl Fn(usize) + Send + Sync) {
+        let bounds = factoring_bounds(num, self.num_threads().max(1));
+        self.run(bounds.len() - 1, |_, chunk| {
+            for i in bounds[chunk]..bounds[chunk + 1] {
+                func(i);
+            }
+        });
+    }
+
+    // like `execute`, but every claim hands out `grain()` consecutive indices,
+    // which amortizes the per-index overhead for very cheap bodies
+    pub fn execute_chunked(&self, num: usize, func: impl Fn(usize) + Send + Sync) {
+        let grain = self.grain();
+        self.run(num.div_ceil(grain), |_, chunk| {
+            for i in chunk * grain..((chunk + 1) * grain).min(num) {
+                func(i);
+            }
+        });
+    }
+
+    // like `execute` over a `u64` domain, for jobs with more indices than a
+    // `usize` holds on 32-bit targets. The job claims are still counted in a
+    // `usize`, so every claim covers a run of consecutive indices when there
+    // are more than that, on 64-bit targets it's one index per claim.
+    pub fn execute_u64(&self, num: u64, func: impl Fn(u64) + Send + Sync) {
+        // workers bump the claim counter once past the last claim, leave room
+        // for that so it never wraps around
+        self.run_u64(num, isize::MAX as usize, func);
+    }
+
+    // `execute_u64` with at most `claims` claims
+    pub(crate) fn run_u64(&self, num: u64, claims: usize, func: impl Fn(u64) + Send + Sync) {
+        let per_claim = num.div_ceil(claims as u64).max(1);
+        self.run(num.div_ceil(per_claim) as usize, |_, claim| {
+            let start = claim as u64 * per_claim;
+            for i in start..(start + per_claim).min(num) {
+                func(i);
+            }
+        });
+    }
+
+    // like `execute_chunked`, but `func` gets whole chunks whose boundaries
+    // are multiples of `align` (the grain rounded up to it), so it can use
+    // aligned SIMD loads without a scalar prologue per chunk; only the last
+    // chunk may end off the alignment, at `num`
+    pub fn execute_aligned(
+        &self,
+        num: usize,
+        align: usize,
+        func: impl Fn(Range<usize>) + Send + Sync,
+    ) {
+        let chunk_len = self.grain().next_multiple_of(align.max(1));
+        self.run(num.div_ceil(chunk_len), |_, chunk| {
+            func(chunk * chunk_len..((chunk + 1) * chunk_len).min(num))
+        });
+    }
+
+    // like `execute`, but `func` is called once per batch of `batch_size`
+    // consecutive indices (the last one may be shorter) rather than once per
+    // index, so a vectorized body sees whole ranges and the call overhead is
+    // paid per batch. A `batch_size` of 0 counts as 1.
+    pub fn execute_batches(
+        &self,
+        num: usize,
+        batch_size: usize,
+        func: impl Fn(Range<usize>) + Send + Sync,
+    ) {
+        let batch_size = batch_size.max(1);
+        self.run(num.div_ceil(batch_size), |_, batch| {
+            func(batch * batch_size..((batch + 1) * batch_size).min(num))
+        });
+    }
+
+    // runs `func(xs, ys)` over the `nx` by `ny` domain cut into blocks of
+    // `tile_w` by `tile_h` (smaller along the right and bottom edges), one
+    // block per claim, so every worker stays within a cache-sized rectangle
+    // rather than streaming through whole rows. Blocks are handed out row of
+    // blocks by row of blocks.
+    pub fn execute_tiles(
+        &self,
+        nx: usize,
+        ny: usize,
+        tile_w: usize,
+        tile_h: usize,
+        func: impl Fn(Range<usize>, Range<usize>) + Send + Sync,
+    ) {
+        let (tile_w, tile_h) = (tile_w.max(1), tile_h.max(1));
+        let across = nx.div_ceil(tile_w);
+        self.run(across * ny.div_ceil(tile_h), |_, tile| {
+            let (x, y) = (tile % across * tile_w, tile / across * tile_h);
+            func(x..(x + tile_w).min(nx), y..(y + tile_h).min(ny));
+        });
+    }
+
+    pub fn grain(&self) -> usize {
+        self.inner.grain.load(Ordering::Relaxed)
+    }
+
+    pub fn set_grain(&self, grain: usize) {
+        self.inner.grain.store(grain.max(1), Ordering::Relaxed);
+    }
+
+    // times `sample_body` over indices from 0 on the calling thread for a
+    // couple of milliseconds and sets the grain of `execute_chunked` so that
+    // a chunk takes about `CHUNK_TIME` on this machine (four times that for
+    // power-saving pools), returning it
+    pub fn calibrate(&self, sample_body: impl Fn(usize)) -> usize {
+        let start = Instant::now();
+        let mut sampled = 0;
+        while sampled < SAMPLE_MAX && start.elapsed() < SAMPLE_TIME {
+            sample_body(sampled);
+            sampled += 1;
+        }
+        let per_index = start.elapsed().as_nanos() / sampled as u128;
+        let chunk_time = match self.inner.power_saving {
+            true => POWER_SAVING_CHUNK_TIME,
+            false => CHUNK_TIME,
+        };
+        let grain = (chunk_time.as_nanos() / per_index.max(1)).clamp(1, SAMPLE_MAX as u128);
+        self.set_grain(grain as usize);
+        grain as usize
+    }
+
+    // runs `func` on `start + k * step` for every `k` in `0..count`
+    pub fn execute_strided(
+        &self,
+        start: usize,
+        step: usize,
+        count: usize,
+        func: impl Fn(usize) + Send + Sync,
+    ) {
+        self.run(count, |_, k| func(start + k * step));
+    }
+
+    // like `execute`, but indices are handed out from `num - 1` down to 0
+    pub fn execute_rev(&self, num: usize, func: impl Fn(usize) + Send + Sync) {
+        self.run(num, |_, i| func(num - 1 - i));
+    }
+
+    // like `execute`, but indices are handed out in a scrambled order so that
+    // costs that grow or shrink with the index even out across the workers.
+    // The order is `i * stride % num` for a fixed stride coprime to `num`,
+    // which visits every index once without allocating a permutation.
+    pub fn execute_shuffled(&self, num: usize, func: impl Fn(usize) + Send + Sync) {
+        let stride = shuffle_stride(num) as u128;
+        self.run(num, |_, i| {
+            func((i as u128 * stride % num as u128) as usize)
+        });
+    }
+
+    // like `execute`, but `0..num` is split into one contiguous range per
+    // worker and worker `w` always prefers range `w`, so repeated jobs over
+    // the same indices keep each range on the same core. A range whose worker
+    // is busy or asleep is taken over by whichever worker gets there first.
+    pub fn execute_sticky(&self, num: usize, func: impl Fn(usize) + Send + Sync) {
+        let parts = self.num_threads().max(1);
+        let bounds = (0..=parts)
+            .map(|part| num * part / parts)
+            .collect::<Vec<_>>();
+        self.run_sticky(&bounds, func);
+    }
+
+    // like `execute_sticky`, but the ranges are cut so that each worker gets
+    // about the same total weight, with `weights[i]` the cost of index `i`,
+    // e.g. the nonzero count of every row of a sparse matrix
+    pub fn execute_weighted(&self, weights: &[u64], func: impl Fn(usize) + Send + Sync) {
+        let bounds = weighted_bounds(weights, self.num_threads().max(1));
+        self.run_sticky(&bounds, func);
+    }
+
+    // runs the ranges between consecutive `bounds`, worker `w` taking range
+    // `w` unless someone else got to it first
+    fn run_sticky(&self, bounds: &[usize], func: impl Fn(usize) + Send + Sync) {
+        self.run_parts(bounds.len() - 1, |part| {
+            for i in bounds[part]..bounds[part + 1] {
+                func(i);
+            }
+        });
+    }
+
+    // runs `func` once for every part in `0..parts`, worker `w` taking part
+    // `w` unless someone else got to it first
+    pub(crate) fn run_parts(&self, parts: usize, func: impl Fn(usize) + Send + Sync) {
+        let taken = (0..parts)
+            .map(|_| AtomicBool::new(false))
+            .collect::<Vec<_>>();
+        let take = |part: usize| !taken[part].swap(true, Ordering::Relaxed);
+        self.run(parts, |worker, _| {
+            let part = match worker < parts && take(worker) {
+                true => worker,
+                false => (0..parts).find(|&part| take(part)).unwrap(),
+            };
+            func(part);
+        });
+    }
+
+    // Like `execute`, but only the workers pinned to one of `cpus` run
+    // indices, e.g. to keep a job off the cores isolated for a latency
+    // sensitive service on the same machine. Panics if none of the workers is
+    // pinned to one of them, which includes every pool that doesn't pin its
+    // workers (see `Builder::pin_threads`). A job started from inside another
+    // job runs on the calling worker as usual, but neither
+    // `Builder::inline_threshold` nor `Builder::help_when_busy` runs it on
+    // the calling thread, it waits for a free job slot instead.
+    pub fn execute_on_cpus(&self, cpus: &[usize], num: usize, func: impl Fn(usize) + Send + Sync) {
+        if self.runs_inline() {
+            return self.execute(num, func);
+        }
+        let allowed = (0..self.num_threads())
+            .map(|worker| {
+                self.inner
+                    .worker_cpu(worker)
+                    .is_some_and(|cpu| cpus.contains(&cpu))
+            })
+            .collect::<Vec<_>>();
+        if !allowed.contains(&true) {
+            panic!("no worker of the pool is pinned to one of the CPUs {cpus:?}");
+        }
+        // the other workers never claim an index and stay parked
+        self.run_for(None, allowed.len(), Some(&allowed), num, |_, i| func(i));
+    }
+
+    // like `execute`, calling `on_progress(completed, num)` whenever another
+    // `every` indices have completed and once all of them have. Calls come
+    // from the workers one at a time and `completed` only ever grows.
+    pub fn execute_with_progress(
+        &self,
+        num: usize,
+        every: usize,
+        func: impl Fn(usize) + Send + Sync,
+        on_progress: impl Fn(usize, usize) + Send + Sync,
+    ) {
+        let every = every.max(1);
+        let done = AtomicUsize::new(0);
+        let reported = Mutex::new(0);
+        self.run(num, |_, i| {
+            func(i);
+            let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+            if done.is_multiple_of(every) || done == num {
+                let mut reported = reported.lock().unwrap();
+                if done > *reported {
+                    *reported = done;
+                    on_progress(done, num);
+                }
+            }
+        });
+    }
+
+    // runs `func` over `checkpoint.get()..num`, advancing `checkpoint` as
+    // the completed indices form a contiguous run from where it started
+    pub fn execute_from(
+        &self,
+        checkpoint: &Checkpoint,
+        num: usize,
+        func: impl Fn(usize) + Send + Sync,
+    ) {
+        let mark = &*checkpoint.mark;
+        let start = mark.load(Ordering::SeqCst);
+        let len = num.saturating_sub(start);
+        let done = (0..len.div_ceil(64))
+            .map(|_| AtomicU64::new(0))
+            .collect::<Vec<_>>();
+        let is_done = |i: usize| done[i / 64].load(Ordering::SeqCst) & (1 << (i % 64)) != 0;
+        let next = AtomicUsize::new(0);
+        self.run(len, |_, i| {
+            func(start + i);
+            done[i / 64].fetch_or(1 << (i % 64), Ordering::SeqCst);
+            // whoever completes the index at the mark moves it past every
+            // completed index behind it
+            loop {
+                let at = next.load(Ordering::SeqCst);
+                if at == len || !is_done(at) {
+                    break;
+                }
+                if next
+                    .compare_exchange(at, at + 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    mark.fetch_max(start + at + 1, Ordering::SeqCst);
+                }
+            }
+        });
+    }
+
+    // like `execute`, but returns immediately and runs `on_complete` on the
+    // worker that finishes the last index. Indices are claimed through
+    // spawned tasks, so they queue behind earlier tasks rather than jobs. A
+    // panicking index stops the job, `on_complete` still runs once the
+    // indices already started are done and gets the panic payload.
+    pub fn execute_detached(
+        &self,
+        num: usize,
+        func: impl Fn(usize) + Send + Sync + 'static,
+        on_complete: impl FnOnce(Result<(), Box<dyn Any + Send>>) + Send + 'static,
+    ) {
+        let job = Arc::new(Detached {
+            func,
+            num,
+            next: AtomicUsize::new(0),
+            done: AtomicUsize::new(0),
+            panic: Mutex::new(None),
+            on_complete: Mutex::new(Some(on_complete)),
+        });
+        if num == 0 {
+            return self.push_task(Box::new(move || job.complete()));
+        }
+        for _ in 0..self.num_threads().clamp(1, num) {
+            let job = job.clone();
+            self.push_task(Box::new(move || job.run()));
+        }
+    }
+}
+
+struct Detached<F, C> {
+    func:        F,
+    num:         usize,
+    next:        AtomicUsize,
+    // indices that ran or were skipped after a panic
+    done:        AtomicUsize,
+    panic:       Mutex<Option<Box<dyn Any + Send>>>,
+    on_complete: Mutex<Option<C>>,
+}
+
+impl<F: Fn(usize), C: FnOnce(Result<(), Box<dyn Any + Send>>)> Detached<F, C> {
+    fn run(&self) {
+        loop {
+            let i = self.next.fetch_add(1, Ordering::SeqCst);
+            if i >= self.num {
+                break;
+            }
+            let mut count = 1;
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| (self.func)(i))) {
+                self.panic.lock().unwrap().get_or_insert(payload);
+                // the indices not handed out yet are skipped and count as done
+                let handed_out = self.next.fetch_max(self.num, Ordering::SeqCst);
+                count += self.num.saturating_sub(handed_out);
+            }
+            if self.done.fetch_add(count, Ordering::SeqCst) + count == self.num {
+                self.complete();
+            }
+        }
+    }
+
+    fn complete(&self) {
+        if let Some(on_complete) = self.on_complete.lock().unwrap().take() {
+            match self.panic.lock().unwrap().take() {
+                Some(payload) => on_complete(Err(payload)),
+                None => on_complete(Ok(())),
+            }
+        }
+    }
+}
+
+// splits the indices of `costs` into about `parts` consecutive ranges of
+// similar cost, returned as their boundaries
+pub(crate) fn weighted_bounds(costs: &[u64], parts: usize) -> Vec<usize> {
+    let total = costs.iter().map(|&c| c as u128).sum::<u128>();
+    // nothing to weigh, every index would end a range of its own below
+    if total == 0 {
+        let mut bounds = (0..=parts)
+            .map(|part| (part as u128 * costs.len() as u128 / parts as u128) as usize)
+            .collect::<Vec<_>>();
+        bounds.dedup();
+        return bounds;
+    }
+    let mut bounds = vec![0];
+    let mut acc = 0u128;
+    for (i, &c) in costs.iter().enumerate() {
+        acc += c as u128;
+        if acc * parts as u128 >= total * bounds.len() as u128 {
+            bounds.push(i + 1);
+        }
+    }
+    if *bounds.last().unwrap() != costs.len() {
+        bounds.push(costs.len());
+    }
+    bounds
+}
+
+// the first stride past the golden ratio point of `num` that is coprime to it
+fn shuffle_stride(num: usize) -> usize {
+    let gcd = |mut a: usize, mut b: usize| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    let mut stride = (num as f64 * 0.618_033_988_7) as usize;
+    while gcd(stride, num) > 1 {
+        stride += 1;
+    }
+    stride
+}
+
+// chunk boundaries of `execute_factoring`: rounds of `workers` equal chunks
+// of `left / (2 * workers)` indices (at least one) each
+pub(crate) fn factoring_bounds(num: usize, workers: usize) -> Vec<usize> {
+    let mut bounds = vec![0];
+    let mut start = 0;
+    while start < num {
+        let chunk = (num - start).div_ceil(2 * workers);
+        for _ in 0..workers {
+            if start == num {
+                break;
+            }
+            start = (start + chunk).min(num);
+            bounds.push(start);
+        }
+    }
+    bounds
+}
@@ -0,0 +1,129 @@
+use std::{
+    any::Any,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    time::Duration,
+};
+
+use crate::{
+    pool::PoolInner,
+    sync::{Arc, Condvar, Mutex},
+    Pool,
+};
+
+pub struct Scope<'scope> {
+    pool:    *const Pool,
+    state:   Arc<ScopeState>,
+    _marker: PhantomData<&'scope mut &'scope ()>,
+}
+
+unsafe impl Sync for Scope<'_> {}
+
+struct ScopeState {
+    pending: Mutex<usize>,
+    done:    Condvar,
+    // the first panic of a spawned task, resumed by `wait`
+    panic:   Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl<'scope> Scope<'scope> {
+    pub(crate) fn new(pool: &Pool) -> Scope<'scope> {
+        Scope {
+            pool:    pool as *const Pool,
+            state:   Arc::new(ScopeState {
+                pending: Mutex::new(0),
+                done:    Condvar::new(),
+                panic:   Mutex::new(None),
+            }),
+            _marker: PhantomData,
+        }
+    }
+
+    // runs `func` and then waits for the spawned tasks, also when `func`
+    // panics: the tasks may still borrow from the caller's stack frame, so
+    // unwinding past the scope before they are done would free it under them
+    pub(crate) fn run<R>(
+        &self,
+        func: impl FnOnce(&Scope<'scope>) -> R,
+        helping: Option<&PoolInner>,
+    ) -> R {
+        struct Wait<'a, 'scope>(&'a Scope<'scope>, Option<&'a PoolInner>);
+
+        impl Drop for Wait<'_, '_> {
+            fn drop(&mut self) {
+                match self.1 {
+                    Some(pool) => self.0.wait_helping(pool),
+                    None => self.0.wait(),
+                }
+            }
+        }
+
+        let wait = Wait(self, helping);
+        let ret = func(self);
+        drop(wait);
+        self.resume_panic();
+        ret
+    }
+
+    // blocks until every spawned task is done, a panic of one of them is
+    // kept for `resume_panic`
+    pub(crate) fn wait(&self) {
+        let mut pending = self.state.pending.lock().unwrap();
+        while *pending > 0 {
+            pending = self.state.done.wait(pending).unwrap();
+        }
+    }
+
+    // like `wait`, running queued tasks of the pool until there are none
+    // left and only then blocking; tasks spawned later are checked for
+    // every millisecond
+    pub(crate) fn wait_helping(&self, pool: &PoolInner) {
+        loop {
+            if *self.state.pending.lock().unwrap() == 0 {
+                break;
+            }
+            if pool.run_task() {
+                continue;
+            }
+            let pending = self.state.pending.lock().unwrap();
+            if *pending > 0 {
+                drop(
+                    self.state
+                        .done
+                        .wait_timeout(pending, Duration::from_millis(1)),
+                );
+            }
+        }
+    }
+
+    pub(crate) fn resume_panic(&self) {
+        if let Some(payload) = self.state.panic.lock().unwrap().take() {
+            panic::resume_unwind(payload);
+        }
+    }
+
+    pub fn spawn(&self, func: impl FnOnce(&Scope<'scope>) + Send + 'scope) {
+        *self.state.pending.lock().unwrap() += 1;
+        let scope = self as *const Scope<'scope> as usize;
+        let state = self.state.clone();
+        let task = Box::new(move || {
+            let scope = unsafe { &*(scope as *const Scope<'scope>) };
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| func(scope))) {
+                state.panic.lock().unwrap().get_or_insert(payload);
+            }
+            let mut pending = state.pending.lock().unwrap();
+            *pending -= 1;
+            if *pending == 0 {
+                state.done.notify_all();
+            }
+        }) as Box<dyn FnOnce() + Send + 'scope>;
+        // the scope waits for every spawned task before returning
+        let task = unsafe {
+            std::mem::transmute::<
+                Box<dyn FnOnce() + Send + 'scope>,
+                Box<dyn FnOnce() + Send + 'static>,
+            >(task)
+        };
+        unsafe { &*self.pool }.push_task(task);
+    }
+}
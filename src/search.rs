@@ -0,0 +1,176 @@
+use std::{ops::ControlFlow, sync::Mutex};
+
+use crate::{iter::SyncPtr, Pool};
+
+// needles looked up per claim by the batch searches, a single lookup is too
+// cheap to hand out on its own
+const LOOKUPS_PER_CLAIM: usize = 256;
+
+// match starts `par_find_all` checks per chunk
+const FIND_CHUNK: usize = 1 << 14;
+
+/// The return value of a fallible per-index closure, `false`, `Err` and
+/// `ControlFlow::Break` stop the job early.
+pub trait Outcome: Send {
+    fn ok() -> Self;
+
+    fn is_ok(&self) -> bool;
+}
+
+impl Outcome for bool {
+    fn ok() -> bool {
+        true
+    }
+
+    fn is_ok(&self) -> bool {
+        *self
+    }
+}
+
+impl<B: Send> Outcome for ControlFlow<B> {
+    fn ok() -> ControlFlow<B> {
+        ControlFlow::Continue(())
+    }
+
+    fn is_ok(&self) -> bool {
+        self.is_continue()
+    }
+}
+
+impl<E: Send> Outcome for Result<(), E> {
+    fn ok() -> Result<(), E> {
+        Ok(())
+    }
+
+    fn is_ok(&self) -> bool {
+        Result::is_ok(self)
+    }
+}
+
+impl Pool {
+    /// Runs `func` for each index until one of them fails, after which no
+    /// further indices are handed out. Returns the first failure, indices that
+    /// were already running still complete.
+    pub fn try_for_each<R: Outcome>(
+        &self,
+        num: usize,
+        func: impl Fn(usize) -> R + Send + Sync,
+    ) -> R {
+        self.try_for_each_counted(num, func).0
+    }
+
+    /// Like `try_for_each`, also returning how many indices ran. Indices are
+    /// handed out in order, so those are exactly `0..count` (the failing ones
+    /// included), and a later run can pick up from `count`, e.g. with
+    /// `execute_from` and `Checkpoint::new(count)`.
+    pub fn try_for_each_counted<R: Outcome>(
+        &self,
+        num: usize,
+        func: impl Fn(usize) -> R + Send + Sync,
+    ) -> (R, usize) {
+        let failure = Mutex::new(None);
+        let count = self.run(num, |_, i| {
+            let ret = func(i);
+            if !ret.is_ok() {
+                self.dispatch().stop();
+                failure.lock().unwrap().get_or_insert(ret);
+            }
+        });
+        (failure.into_inner().unwrap().unwrap_or_else(R::ok), count)
+    }
+
+    /// Returns the first value found by `func` in completion order, not
+    /// necessarily the one at the lowest index.
+    pub fn find_map_any<T: Send>(
+        &self,
+        num: usize,
+        func: impl Fn(usize) -> Option<T> + Send + Sync,
+    ) -> Option<T> {
+        self.try_for_each(num, |i| {
+            match func(i) {
+                Some(found) => Err(found),
+                None => Ok(()),
+            }
+        })
+        .err()
+    }
+
+    pub fn find_any(
+        &self,
+        num: usize,
+        pred: impl Fn(usize) -> bool + Send + Sync,
+    ) -> Option<usize> {
+        self.find_map_any(num, |i| pred(i).then_some(i))
+    }
+
+    pub fn any(&self, num: usize, pred: impl Fn(usize) -> bool + Send + Sync) -> bool {
+        self.find_any(num, pred).is_some()
+    }
+
+    pub fn all(&self, num: usize, pred: impl Fn(usize) -> bool + Send + Sync) -> bool {
+        self.try_for_each(num, pred)
+    }
+
+    /// `haystack.binary_search(needle)` for every needle, in parallel, e.g.
+    /// for joins looking up millions of keys in a sorted column.
+    pub fn par_binary_search<T: Ord + Sync>(
+        &self,
+        haystack: &[T],
+        needles: &[T],
+    ) -> Vec<Result<usize, usize>> {
+        self.lookup_all(needles, |needle| haystack.binary_search(needle))
+    }
+
+    /// `haystack.partition_point(|x| pred(x, needle))` for every needle, in
+    /// parallel; `pred` must be true for a prefix of `haystack` and false
+    /// for the rest for every needle.
+    pub fn par_partition_points<T: Sync, N: Sync>(
+        &self,
+        haystack: &[T],
+        needles: &[N],
+        pred: impl Fn(&T, &N) -> bool + Sync,
+    ) -> Vec<usize> {
+        self.lookup_all(needles, |needle| {
+            haystack.partition_point(|x| pred(x, needle))
+        })
+    }
+
+    /// The positions of every occurrence of `needle` in `haystack`, overlapping
+    /// ones included, in ascending order. Each chunk of start positions also
+    /// reads the `needle.len() - 1` bytes after it, so matches crossing chunk
+    /// boundaries are found exactly once. An empty needle matches everywhere.
+    pub fn par_find_all(&self, haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+        let Some((&first, rest)) = needle.split_first() else {
+            return (0..=haystack.len()).collect();
+        };
+        let Some(starts) = (haystack.len() + 1).checked_sub(needle.len()) else {
+            return Vec::new();
+        };
+        let chunks = self.vec_from_fn(starts.div_ceil(FIND_CHUNK), |chunk| {
+            let start = chunk * FIND_CHUNK;
+            let end = (start + FIND_CHUNK).min(starts);
+            (start..end)
+                .filter(|&i| haystack[i] == first && haystack[i + 1..].starts_with(rest))
+                .collect::<Vec<usize>>()
+        });
+        chunks.concat()
+    }
+
+    // `func` of every needle, in order, claiming `LOOKUPS_PER_CLAIM` of them
+    // at a time
+    fn lookup_all<N: Sync, R: Send>(&self, needles: &[N], func: impl Fn(&N) -> R + Sync) -> Vec<R> {
+        let len = needles.len();
+        let mut out = Vec::<R>::with_capacity(len);
+        let ptr = SyncPtr(out.as_mut_ptr());
+        // every chunk writes its own part of `out`
+        self.run(len.div_ceil(LOOKUPS_PER_CLAIM), |_, chunk| {
+            let start = chunk * LOOKUPS_PER_CLAIM;
+            let end = (start + LOOKUPS_PER_CLAIM).min(len);
+            for (i, needle) in (start..end).zip(&needles[start..end]) {
+                unsafe { ptr.get().add(i).write(func(needle)) };
+            }
+        });
+        unsafe { out.set_len(len) };
+        out
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+
+use crate::sync::{thread, AtomicU32, Ordering};
+
+// How idle workers wait for work. On Linux they all wait on one futex word,
+// so waking the whole pool is a single syscall instead of one unpark per
+// worker, and a wake for a single task goes to any worker that is actually
+// idle. Elsewhere this falls back to parking the worker threads. Busy-wait
+// pools skip the OS entirely and spin on the word.
+//
+// A worker reads `epoch` before looking for work and passes it to `wait`, any
+// wake in between makes `wait` return immediately.
+//
+// Pools with a `linger` spin for that long before sleeping, and on Linux a
+// wake skips the syscall while nobody sleeps, so back-to-back jobs find the
+// workers still awake and pay no syscalls at all.
+pub(crate) struct Signal {
+    epoch:    AtomicU32,
+    spin:     bool,
+    linger:   Option<Duration>,
+    #[cfg(all(target_os = "linux", not(loom)))]
+    sleepers: AtomicU32,
+}
+
+impl Signal {
+    pub(crate) fn new(spin: bool, linger: Option<Duration>) -> Signal {
+        Signal {
+            epoch: AtomicU32::new(0),
+            spin,
+            linger,
+            #[cfg(all(target_os = "linux", not(loom)))]
+            sleepers: AtomicU32::new(0),
+        }
+    }
+
+    // whether a caller that started waiting at `start` should still spin
+    pub(crate) fn lingers(&self, start: Instant) -> bool {
+        self.spin || self.linger.is_some_and(|linger| start.elapsed() < linger)
+    }
+
+    // one round of waiting without sleeping; lingering threads give up their
+    // time slice so they don't starve the threads they wait for when there
+    // are more of them than cores
+    pub(crate) fn relax(&self) {
+        match self.spin {
+            true => core::hint::spin_loop(),
+            false => thread::yield_now(),
+        }
+    }
+
+    pub(crate) fn spins(&self) -> bool {
+        self.spin
+    }
+
+    pub(crate) fn epoch(&self) -> u32 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn wait(&self, epoch: u32, timeout: Option<Duration>) {
+        match self.spin {
+            true => {
+                let start = Instant::now();
+                while self.epoch() == epoch
+                    && timeout.is_none_or(|timeout| start.elapsed() < timeout)
+                {
+                    core::hint::spin_loop();
+                }
+            },
+            false => {
+                let start = Instant::now();
+                while self.epoch() == epoch && self.lingers(start) {
+                    self.relax();
+                }
+                if self.epoch() == epoch {
+                    let timeout = timeout.map(|timeout| timeout.saturating_sub(start.elapsed()));
+                    self.sleep(epoch, timeout);
+                }
+            },
+        }
+    }
+
+    pub(crate) fn wake_one(&self, thread: &thread::Thread) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        if !self.spin {
+            self.unpark(Some(thread));
+        }
+    }
+
+    pub(crate) fn wake_all<'a>(&self, threads: impl IntoIterator<Item = &'a thread::Thread>) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        if !self.spin {
+            self.unpark(threads);
+        }
+    }
+
+    // whether `wake_count` can stand in for `wake_all`, which it can where
+    // waking doesn't need the threads themselves
+    pub(crate) fn wakes_by_count(&self) -> bool {
+        self.spin || cfg!(all(target_os = "linux", not(loom)))
+    }
+
+    // wakes up to `count` waiting threads, see `wakes_by_count`
+    pub(crate) fn wake_count(&self, count: usize) {
+        debug_assert!(self.wakes_by_count());
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        #[cfg(all(target_os = "linux", not(loom)))]
+        if !self.spin {
+            self.wake_sleepers(count);
+        }
+        #[cfg(not(all(target_os = "linux", not(loom))))]
+        let _ = count;
+    }
+
+    #[cfg(all(target_os = "linux", not(loom)))]
+    fn sleep(&self, epoch: u32, timeout: Option<Duration>) {
+        // a waker either sees this sleeper, or bumped `epoch` before the
+        // futex reads it and the wait returns right away
+        self.sleepers.fetch_add(1, Ordering::SeqCst);
+        core::sync::atomic::fence(Ordering::SeqCst);
+        crate::sys::futex_wait(&self.epoch, epoch, timeout);
+        self.sleepers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    #[cfg(all(target_os = "linux", not(loom)))]
+    fn unpark<'a>(&self, threads: impl IntoIterator<Item = &'a thread::Thread>) {
+        self.wake_sleepers(threads.into_iter().count());
+    }
+
+    #[cfg(all(target_os = "linux", not(loom)))]
+    fn wake_sleepers(&self, count: usize) {
+        if self.sleepers.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        crate::sys::futex_wake(&self.epoch, count.min(i32::MAX as usize) as i32);
+    }
+
+    #[cfg(not(all(target_os = "linux", not(loom))))]
+    fn sleep(&self, _epoch: u32, timeout: Option<Duration>) {
+        match timeout {
+            Some(timeout) => crate::sync::park_timeout(timeout),
+            None => thread::park(),
+        }
+    }
+
+    #[cfg(not(all(target_os = "linux", not(loom))))]
+    fn unpark<'a>(&self, threads: impl IntoIterator<Item = &'a thread::Thread>) {
+        for thread in threads {
+            thread.unpark();
+        }
+    }
+}
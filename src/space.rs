@@ -0,0 +1,146 @@
+use crate::Pool;
+
+/// A domain `Pool::execute_over` runs a job over: `len()` items numbered
+/// `0..len()`, with `decode` turning a number back into its item, so nested
+/// loops don't need flattening into one counter by hand.
+pub trait IndexSpace: Sync {
+    type Item;
+
+    fn len(&self) -> usize;
+
+    /// The item numbered `k`, for every `k` below `len()`.
+    fn decode(&self, k: usize) -> Self::Item;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// `n` is the plain space `0..n`
+impl IndexSpace for usize {
+    type Item = usize;
+
+    fn len(&self) -> usize {
+        *self
+    }
+
+    fn decode(&self, k: usize) -> usize {
+        k
+    }
+}
+
+/// The cartesian product of two spaces, every pair of their items in row
+/// major order, e.g. `Product(rows, cols)` for every `(row, col)`. Nests for
+/// higher dimensions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Product<A, B>(pub A, pub B);
+
+impl<A: IndexSpace, B: IndexSpace> IndexSpace for Product<A, B> {
+    type Item = (A::Item, B::Item);
+
+    fn len(&self) -> usize {
+        self.0.len() * self.1.len()
+    }
+
+    fn decode(&self, k: usize) -> (A::Item, B::Item) {
+        let cols = self.1.len();
+        (self.0.decode(k / cols), self.1.decode(k % cols))
+    }
+}
+
+/// The pairs `(i, j)` with `i < j < n`, e.g. to visit every unordered pair
+/// of `n` bodies once. Pairs are numbered by `j`, then by `i`, so the
+/// numbering doesn't depend on `n`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pairs(pub usize);
+
+impl IndexSpace for Pairs {
+    type Item = (usize, usize);
+
+    fn len(&self) -> usize {
+        self.0 * self.0.saturating_sub(1) / 2
+    }
+
+    fn decode(&self, k: usize) -> (usize, usize) {
+        // the pairs below column `j` number j * (j - 1) / 2, so `j` is the
+        // largest solution of that being at most `k`
+        let j = ((1 + 8 * k as u128).isqrt() as usize).div_ceil(2);
+        (k - j * (j - 1) / 2, j)
+    }
+}
+
+// a list of indices is the space of its entries, in order
+impl IndexSpace for &[usize] {
+    type Item = usize;
+
+    fn len(&self) -> usize {
+        <[usize]>::len(self)
+    }
+
+    fn decode(&self, k: usize) -> usize {
+        self[k]
+    }
+}
+
+/// The set bits of a bitset, bit `i` being `words[i / 64] >> (i % 64) & 1`,
+/// in ascending order, e.g. the dirty items of an incremental recomputation.
+/// `new` counts the bits of every word up front, so `decode` finds a word by
+/// binary search rather than scanning.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bits<'a> {
+    words: &'a [u64],
+    // the set bits in the words before each word, and in all of them last
+    ranks: Vec<usize>,
+}
+
+impl<'a> Bits<'a> {
+    pub fn new(words: &'a [u64]) -> Bits<'a> {
+        let mut ranks = Vec::with_capacity(words.len() + 1);
+        let mut rank = 0;
+        ranks.push(0);
+        for word in words {
+            rank += word.count_ones() as usize;
+            ranks.push(rank);
+        }
+        Bits { words, ranks }
+    }
+}
+
+impl IndexSpace for Bits<'_> {
+    type Item = usize;
+
+    fn len(&self) -> usize {
+        self.ranks[self.words.len()]
+    }
+
+    fn decode(&self, k: usize) -> usize {
+        // the last word with at most `k` set bits before it, which can't be
+        // empty since the next word then has more
+        let w = self.ranks.partition_point(|&rank| rank <= k) - 1;
+        let mut word = self.words[w];
+        for _ in self.ranks[w]..k {
+            word &= word - 1;
+        }
+        w * 64 + word.trailing_zeros() as usize
+    }
+}
+
+impl Pool {
+    // like `execute`, but `func` gets the items of `space` rather than their
+    // numbers, e.g. `pool.execute_over(Pairs(n), |(i, j)| ...)`
+    pub fn execute_over<S: IndexSpace>(&self, space: S, func: impl Fn(S::Item) + Send + Sync) {
+        self.run(space.len(), |_, k| func(space.decode(k)));
+    }
+
+    // runs `func` for every index in `indices`, which don't need to be
+    // contiguous or sorted, e.g. a dirty set of items to recompute
+    pub fn execute_indices(&self, indices: &[usize], func: impl Fn(usize) + Send + Sync) {
+        self.execute_over(indices, func);
+    }
+
+    // runs `func` for the index of every set bit of the bitset `words`, see
+    // `Bits`
+    pub fn execute_bits(&self, words: &[u64], func: impl Fn(usize) + Send + Sync) {
+        self.execute_over(Bits::new(words), func);
+    }
+}
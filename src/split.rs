@@ -0,0 +1,139 @@
+use std::{
+    any::Any,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    dispatch::{self, Dispatch, Waiter},
+    signal::Signal,
+    sync::{thread, Arc, AtomicBool, AtomicUsize, Mutex, Ordering},
+    Pool,
+};
+
+/// A share of a pool's workers that runs jobs on its own, concurrently with
+/// the other sub-pools of the same `Pool::split`. The workers go back to the
+/// parent pool once the sub-pool is dropped.
+pub struct SubPool<'a> {
+    inner:   Arc<SubInner>,
+    _marker: PhantomData<&'a Pool>,
+}
+
+struct SubInner {
+    dispatch: Dispatch,
+    waiter:   Waiter<thread::Thread>,
+    signal:   Signal,
+    lock:     Mutex<()>,
+    // the workers serving this sub-pool, registered as their tasks start
+    threads:  Mutex<Vec<thread::Thread>>,
+    next:     AtomicUsize,
+    size:     usize,
+    closed:   AtomicBool,
+}
+
+impl SubPool<'_> {
+    pub fn num_threads(&self) -> usize {
+        self.inner.size
+    }
+
+    /// Runs `func` over `0..num` on this sub-pool's workers, like
+    /// `Pool::execute`. A sub-pool without workers runs it on the caller.
+    pub fn execute(&self, num: usize, func: impl Fn(usize) + Send + Sync) {
+        let inner = &*self.inner;
+        let _guard = inner.lock.lock().unwrap_or_else(|err| err.into_inner());
+        if inner.size == 0 {
+            return (0..num).for_each(func);
+        }
+        let panicked = Mutex::new(None::<Box<dyn Any + Send>>);
+        let func = |_, i| {
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| func(i))) {
+                panicked.lock().unwrap().get_or_insert(payload);
+                inner.dispatch.stop();
+            }
+        };
+        let func = unsafe { dispatch::erase(func) };
+        inner.waiter.set(Some(thread::current()));
+        unsafe { inner.dispatch.open(num, usize::MAX, func) };
+        inner.wake();
+        while !inner.dispatch.is_done(num) {
+            thread::park();
+        }
+        inner.dispatch.close();
+        while !inner.dispatch.is_idle() {
+            thread::park();
+        }
+        inner.waiter.set(None);
+        unsafe { dispatch::free(func) };
+        if let Some(payload) = panicked.into_inner().unwrap() {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl Drop for SubPool<'_> {
+    fn drop(&mut self) {
+        self.inner.closed.store(true, Ordering::SeqCst);
+        self.inner.wake();
+    }
+}
+
+impl SubInner {
+    fn wake(&self) {
+        self.signal.wake_all(self.threads.lock().unwrap().iter());
+    }
+
+    // run by one task per worker of the sub-pool until it is dropped
+    fn serve(&self) {
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        self.threads.lock().unwrap().push(thread::current());
+        loop {
+            let epoch = self.signal.epoch();
+            if self.closed.load(Ordering::SeqCst) {
+                break;
+            }
+            if self.dispatch.run(index)
+                && let Some(waiter) = self.waiter.get()
+            {
+                waiter.unpark();
+            }
+            self.signal.wait(epoch, None);
+        }
+    }
+}
+
+impl Pool {
+    // hands the workers out to one sub-pool per entry of `sizes`, each runs
+    // its jobs on its own workers, concurrently with the others; the pool's
+    // own jobs and tasks wait until the sub-pools are dropped
+    pub fn split(&self, sizes: &[usize]) -> Vec<SubPool<'_>> {
+        assert!(
+            sizes.iter().sum::<usize>() <= self.num_threads(),
+            "can't split {} workers into {:?}",
+            self.num_threads(),
+            sizes
+        );
+        sizes
+            .iter()
+            .map(|&size| {
+                let inner = Arc::new(SubInner {
+                    dispatch: Dispatch::new(),
+                    waiter: Waiter::new(),
+                    signal: Signal::new(false, None),
+                    lock: Mutex::new(()),
+                    threads: Mutex::new(Vec::new()),
+                    next: AtomicUsize::new(0),
+                    size,
+                    closed: AtomicBool::new(false),
+                });
+                for _ in 0..size {
+                    let inner = inner.clone();
+                    self.push_task(Box::new(move || inner.serve()));
+                }
+                SubPool {
+                    inner,
+                    _marker: PhantomData,
+                }
+            })
+            .collect()
+    }
+}
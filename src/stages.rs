@@ -0,0 +1,197 @@
+use std::{
+    any::Any,
+    collections::{BTreeMap, VecDeque},
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    global::with_global,
+    sync::{thread, AtomicBool, AtomicUsize, Mutex, Ordering},
+    Pool,
+};
+
+type Item = Box<dyn Any + Send>;
+type Source<'a> = Box<dyn FnMut() -> Option<Item> + Send + 'a>;
+type Stage<'a> = Box<dyn Fn(Item) -> Item + Send + Sync + 'a>;
+type Sink<'a> = Box<dyn FnMut(Item) + Send + 'a>;
+
+/// A chain of stages that items flow through, like read → transform →
+/// compress → write, created by `Pool::stages` (or `ieu::stages` for the
+/// global pool). The source is pulled from one item at a time, every `map`
+/// stage runs on as many workers at once as it has items waiting, and the
+/// sink passed to `for_each` gets the items one at a time in the order the
+/// source produced them. All stages run concurrently, and at most `capacity`
+/// items are between the source and the sink at any time, so none of the
+/// queues between the stages grows past that. The stages may borrow from the
+/// caller, the items themselves must be `'static` (owned buffers, lines,
+/// paths and the like).
+pub struct Stages<'a, T> {
+    pool:     Option<&'a Pool>,
+    capacity: usize,
+    source:   Source<'a>,
+    stages:   Vec<Stage<'a>>,
+    _item:    PhantomData<fn() -> T>,
+}
+
+// what the workers share while the stages run
+struct Run<'a, 'r> {
+    stages:    &'r [Stage<'a>],
+    capacity:  usize,
+    // the source and the number of the next item it produces
+    source:    Mutex<(Source<'a>, usize)>,
+    exhausted: AtomicBool,
+    // items before every stage, with their number
+    queues:    Vec<Mutex<VecDeque<(usize, Item)>>>,
+    // the number of the next item for the sink, the items that are done but
+    // wait for an earlier one, and the sink
+    sink:      Mutex<(usize, BTreeMap<usize, Item>, Sink<'r>)>,
+    in_flight: AtomicUsize,
+    failed:    AtomicBool,
+    panic:     Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl<'a, T: Send + 'static> Stages<'a, T> {
+    pub(crate) fn new(
+        pool: Option<&'a Pool>,
+        capacity: usize,
+        mut source: impl Iterator<Item = T> + Send + 'a,
+    ) -> Stages<'a, T> {
+        Stages {
+            pool,
+            capacity: capacity.max(1),
+            source: Box::new(move || source.next().map(|item| Box::new(item) as Item)),
+            stages: Vec::new(),
+            _item: PhantomData,
+        }
+    }
+
+    /// Adds a stage that turns every item into `func(item)`.
+    pub fn map<U: Send + 'static>(
+        mut self,
+        func: impl Fn(T) -> U + Send + Sync + 'a,
+    ) -> Stages<'a, U> {
+        self.stages.push(Box::new(move |item| {
+            Box::new(func(*item.downcast::<T>().unwrap())) as Item
+        }));
+        Stages {
+            pool:     self.pool,
+            capacity: self.capacity,
+            source:   self.source,
+            stages:   self.stages,
+            _item:    PhantomData,
+        }
+    }
+
+    /// Runs the stages until the source is exhausted and every item has
+    /// reached `sink`. A panic in any stage stops the others and is resumed
+    /// here once the workers are done.
+    pub fn for_each(self, sink: impl FnMut(T) + Send) {
+        match self.pool {
+            Some(pool) => self.run(pool, sink),
+            None => with_global(|pool| self.run(pool, sink)),
+        }
+    }
+
+    fn run(self, pool: &Pool, mut sink: impl FnMut(T) + Send) {
+        let sink = Box::new(move |item: Item| sink(*item.downcast::<T>().unwrap()));
+        let run = Run {
+            stages:    &self.stages,
+            capacity:  self.capacity,
+            source:    Mutex::new((self.source, 0)),
+            exhausted: AtomicBool::new(false),
+            queues:    self
+                .stages
+                .iter()
+                .map(|_| Mutex::new(VecDeque::new()))
+                .collect(),
+            sink:      Mutex::new((0, BTreeMap::new(), sink)),
+            in_flight: AtomicUsize::new(0),
+            failed:    AtomicBool::new(false),
+            panic:     Mutex::new(None),
+        };
+        pool.run(pool.num_threads().max(1), |_, _| run.drive());
+        if let Some(payload) = run.panic.into_inner().unwrap() {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl Run<'_, '_> {
+    // run by every worker until all items went through or a stage panicked
+    fn drive(&self) {
+        while !self.failed.load(Ordering::SeqCst) {
+            if self.exhausted.load(Ordering::SeqCst) && self.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            match panic::catch_unwind(AssertUnwindSafe(|| self.step())) {
+                Ok(true) => {},
+                Ok(false) => thread::yield_now(),
+                Err(payload) => {
+                    self.panic.lock().unwrap().get_or_insert(payload);
+                    self.failed.store(true, Ordering::SeqCst);
+                },
+            }
+        }
+    }
+
+    // moves one item a stage further, returns whether there was one to move;
+    // the later stages go first so items leave before new ones come in
+    fn step(&self) -> bool {
+        for (k, queue) in self.queues.iter().enumerate().rev() {
+            let next = queue.lock().unwrap().pop_front();
+            if let Some((number, item)) = next {
+                self.deliver(k + 1, number, (self.stages[k])(item));
+                return true;
+            }
+        }
+        if self.in_flight.load(Ordering::SeqCst) >= self.capacity {
+            return false;
+        }
+        let Ok(mut source) = self.source.try_lock() else {
+            return false;
+        };
+        match (source.0)() {
+            Some(item) => {
+                let number = source.1;
+                source.1 += 1;
+                self.in_flight.fetch_add(1, Ordering::SeqCst);
+                drop(source);
+                self.deliver(0, number, item);
+                true
+            },
+            None => {
+                self.exhausted.store(true, Ordering::SeqCst);
+                false
+            },
+        }
+    }
+
+    // queues an item for stage `k`, or hands it and every item after it that
+    // is done to the sink once everything before it has been
+    fn deliver(&self, k: usize, number: usize, item: Item) {
+        if k < self.queues.len() {
+            return self.queues[k].lock().unwrap().push_back((number, item));
+        }
+        let mut sink = self.sink.lock().unwrap();
+        let (next, done, sink) = &mut *sink;
+        done.insert(number, item);
+        while let Some(item) = done.remove(next) {
+            *next += 1;
+            sink(item);
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Pool {
+    // starts a chain of `Stages` pulling its items from `source`, with at
+    // most `capacity` of them between the source and the sink at any time
+    pub fn stages<'a, T: Send + 'static>(
+        &'a self,
+        capacity: usize,
+        source: impl Iterator<Item = T> + Send + 'a,
+    ) -> Stages<'a, T> {
+        Stages::new(Some(self), capacity, source)
+    }
+}
@@ -0,0 +1,43 @@
+use std::{ops::Deref, sync::OnceLock};
+
+use crate::{Builder, Pool};
+
+/// A pool owned by a library instead of the application, built on first use
+/// and never torn down. Declared with `static_pool!`, it dereferences to the
+/// `Pool`.
+pub struct StaticPool {
+    prefix:    &'static str,
+    configure: fn(Builder) -> Builder,
+    pool:      OnceLock<Pool>,
+}
+
+impl StaticPool {
+    #[doc(hidden)]
+    pub const fn new(prefix: &'static str, configure: fn(Builder) -> Builder) -> StaticPool {
+        StaticPool {
+            prefix,
+            configure,
+            pool: OnceLock::new(),
+        }
+    }
+
+    /// The pool, built the first time it's needed: lazily spawning its
+    /// workers, configured by the declaration and then by the environment
+    /// variables under the declared prefix (see `Builder::env`), so they can
+    /// override the library's defaults.
+    pub fn get(&self) -> &Pool {
+        self.pool.get_or_init(|| {
+            (self.configure)(Builder::new().lazy(true))
+                .env(self.prefix)
+                .build()
+        })
+    }
+}
+
+impl Deref for StaticPool {
+    type Target = Pool;
+
+    fn deref(&self) -> &Pool {
+        self.get()
+    }
+}
@@ -0,0 +1,171 @@
+use core::sync::atomic::AtomicU64;
+use std::time::Duration;
+
+use crate::{sync::Ordering, Pool};
+
+/// What a pool did since it was built or since the last `Pool::reset_stats`,
+/// from `Pool::stats_snapshot`. Jobs that run inline on the caller aren't
+/// counted, and a worker's busy and idle time are added when it goes to
+/// sleep and wakes up again.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Jobs run on the workers.
+    pub jobs:       u64,
+    /// Indices those jobs ran.
+    pub indices:    u64,
+    /// Spawned tasks the workers ran, including the ones behind `submit`.
+    pub tasks:      u64,
+    /// How long the jobs waited for a job slot in total.
+    pub queue_wait: Duration,
+    /// How long the jobs waited from submission until a worker ran their
+    /// first index, in total and at most.
+    pub pickup:     Duration,
+    pub max_pickup: Duration,
+    /// The time each worker spent running jobs and tasks.
+    pub busy:       Vec<Duration>,
+    /// The time each worker spent waiting for them.
+    pub idle:       Vec<Duration>,
+}
+
+// the counters behind `Stats`, kept by every pool
+pub(crate) struct Counters {
+    jobs:       AtomicU64,
+    indices:    AtomicU64,
+    tasks:      AtomicU64,
+    queue_wait: AtomicU64,
+    pickup:     AtomicU64,
+    max_pickup: AtomicU64,
+    busy:       Vec<AtomicU64>,
+    idle:       Vec<AtomicU64>,
+}
+
+impl Counters {
+    pub(crate) fn new(size: usize) -> Counters {
+        Counters {
+            jobs:       AtomicU64::new(0),
+            indices:    AtomicU64::new(0),
+            tasks:      AtomicU64::new(0),
+            queue_wait: AtomicU64::new(0),
+            pickup:     AtomicU64::new(0),
+            max_pickup: AtomicU64::new(0),
+            busy:       (0..size).map(|_| AtomicU64::new(0)).collect(),
+            idle:       (0..size).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    // `pickup` is `None` for jobs no worker ran an index of
+    pub(crate) fn job(&self, queue_wait: Duration, pickup: Option<Duration>, indices: usize) {
+        self.jobs.fetch_add(1, Ordering::Relaxed);
+        self.indices.fetch_add(indices as u64, Ordering::Relaxed);
+        self.queue_wait
+            .fetch_add(queue_wait.as_nanos() as u64, Ordering::Relaxed);
+        if let Some(pickup) = pickup {
+            self.pickup
+                .fetch_add(pickup.as_nanos() as u64, Ordering::Relaxed);
+            self.max_pickup
+                .fetch_max(pickup.as_nanos() as u64, Ordering::Relaxed);
+        }
+        #[cfg(feature = "metrics")]
+        metrics_job(queue_wait, pickup, indices);
+    }
+
+    pub(crate) fn task(&self) {
+        self.tasks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn worker(&self, index: usize, busy: Duration, idle: Duration) {
+        self.busy[index].fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+        self.idle[index].fetch_add(idle.as_nanos() as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics_worker(index, busy, idle);
+    }
+
+    // how long each worker has been busy
+    pub(crate) fn busy(&self) -> Vec<Duration> {
+        self.busy
+            .iter()
+            .map(|busy| Duration::from_nanos(busy.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    fn snapshot(&self) -> Stats {
+        let nanos = |counter: &AtomicU64| Duration::from_nanos(counter.load(Ordering::Relaxed));
+        Stats {
+            jobs:       self.jobs.load(Ordering::Relaxed),
+            indices:    self.indices.load(Ordering::Relaxed),
+            tasks:      self.tasks.load(Ordering::Relaxed),
+            queue_wait: nanos(&self.queue_wait),
+            pickup:     nanos(&self.pickup),
+            max_pickup: nanos(&self.max_pickup),
+            busy:       self.busy.iter().map(nanos).collect(),
+            idle:       self.idle.iter().map(nanos).collect(),
+        }
+    }
+
+    fn reset(&self) {
+        let counters = [
+            &self.jobs,
+            &self.indices,
+            &self.tasks,
+            &self.queue_wait,
+            &self.pickup,
+            &self.max_pickup,
+        ];
+        for counter in counters.into_iter().chain(&self.busy).chain(&self.idle) {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Pool {
+    // the pool's counters so far, e.g. to compare phases of a program after
+    // a `reset_stats` at the start of each
+    pub fn stats_snapshot(&self) -> Stats {
+        self.inner.stats.snapshot()
+    }
+
+    pub fn reset_stats(&self) {
+        self.inner.stats.reset();
+    }
+
+    // jobs submitted right now that wait for a job slot behind the ones
+    // running, e.g. to decide whether a service needs a bigger pool
+    pub fn queued_jobs(&self) -> usize {
+        self.inner.queued_jobs()
+    }
+
+    // spawned tasks waiting for a worker, including the ones behind `submit`
+    pub fn queued_tasks(&self) -> usize {
+        self.inner.queued_tasks()
+    }
+}
+
+// With the `metrics` feature, the counters are also reported to the
+// `metrics` facade, so the numbers end up in whatever exporter the
+// application installed:
+//
+// - `ieu.jobs` (counter): jobs run on the workers
+// - `ieu.indices` (counter): indices those jobs ran
+// - `ieu.job.queue_wait` (histogram, seconds): how long a job waited for the
+//   job slot while other jobs ran
+// - `ieu.job.pickup` (histogram, seconds): how long a job waited from
+//   submission until a worker ran its first index
+// - `ieu.worker.busy_ns` and `ieu.worker.idle_ns` (counters, labelled with
+//   `worker`): time each worker spent running tasks and jobs, or waiting
+#[cfg(feature = "metrics")]
+fn metrics_job(queue_wait: Duration, pickup: Option<Duration>, indices: usize) {
+    metrics::counter!("ieu.jobs").increment(1);
+    metrics::counter!("ieu.indices").increment(indices as u64);
+    metrics::histogram!("ieu.job.queue_wait").record(queue_wait);
+    if let Some(pickup) = pickup {
+        metrics::histogram!("ieu.job.pickup").record(pickup);
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn metrics_worker(index: usize, busy: Duration, idle: Duration) {
+    let worker = index.to_string();
+    metrics::counter!("ieu.worker.busy_ns", "worker" => worker.clone())
+        .increment(busy.as_nanos() as u64);
+    metrics::counter!("ieu.worker.idle_ns", "worker" => worker).increment(idle.as_nanos() as u64);
+}
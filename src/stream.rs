@@ -0,0 +1,136 @@
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc::{self, Receiver},
+};
+
+use crate::{JobHandle, Pool};
+
+type Item<T> = Result<(usize, T), Box<dyn Any + Send>>;
+
+/// The results of a job started with `Pool::execute_stream`, as
+/// `(index, result)` pairs in the order they complete. Dropping the stream
+/// aborts the job, indices that are already running still finish.
+pub struct ResultStream<T> {
+    rx:        Receiver<Item<T>>,
+    job:       JobHandle,
+    remaining: usize,
+}
+
+impl<T> Iterator for ResultStream<T> {
+    type Item = (usize, T);
+
+    // blocks until the next index completes; a panic in the job aborts the
+    // rest of it and is resumed here
+    fn next(&mut self) -> Option<(usize, T)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match self
+            .rx
+            .recv()
+            .expect("job ended before sending every result")
+        {
+            Ok(item) => {
+                self.remaining -= 1;
+                Some(item)
+            },
+            Err(payload) => {
+                self.remaining = 0;
+                self.job.abort_handle().abort();
+                panic::resume_unwind(payload)
+            },
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for ResultStream<T> {}
+
+impl<T> ResultStream<T> {
+    /// Releases the results strictly in index order instead, see
+    /// `OrderedStream`.
+    pub fn ordered(self) -> OrderedStream<T> {
+        OrderedStream {
+            stream:  self,
+            next:    0,
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+/// The results of a job in index order, from `Pool::execute_stream_ordered`
+/// or `ResultStream::ordered`. Results that complete ahead of their turn are
+/// buffered until every index below them has been released; the indices are
+/// handed out in order, so the buffer stays around the number of workers.
+pub struct OrderedStream<T> {
+    stream:  ResultStream<T>,
+    next:    usize,
+    pending: BTreeMap<usize, T>,
+}
+
+impl<T> Iterator for OrderedStream<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            if let Some(item) = self.pending.remove(&self.next) {
+                self.next += 1;
+                return Some(item);
+            }
+            let (i, item) = self.stream.next()?;
+            self.pending.insert(i, item);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.stream.remaining + self.pending.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for OrderedStream<T> {}
+
+impl<T> Drop for ResultStream<T> {
+    fn drop(&mut self) {
+        self.job.abort_handle().abort();
+    }
+}
+
+impl Pool {
+    // Starts running `func` over `0..num` in the background and returns an
+    // iterator over the results as they complete, so the caller can consume
+    // them (e.g. write them out) while the job still runs. Results come with
+    // their index, in completion order.
+    pub fn execute_stream<T: Send + 'static>(
+        &self,
+        num: usize,
+        func: impl Fn(usize) -> T + Send + Sync + 'static,
+    ) -> ResultStream<T> {
+        let (tx, rx) = mpsc::channel();
+        let job = self.submit(num, move |i| {
+            let item = panic::catch_unwind(AssertUnwindSafe(|| (i, func(i))));
+            // the stream may have been dropped already
+            let _ = tx.send(item);
+        });
+        ResultStream {
+            rx,
+            job,
+            remaining: num,
+        }
+    }
+
+    // like `execute_stream`, but the results come in index order without
+    // their index, e.g. for a writer that must emit them in sequence
+    pub fn execute_stream_ordered<T: Send + 'static>(
+        &self,
+        num: usize,
+        func: impl Fn(usize) -> T + Send + Sync + 'static,
+    ) -> OrderedStream<T> {
+        self.execute_stream(num, func).ordered()
+    }
+}
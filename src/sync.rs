@@ -0,0 +1,29 @@
+#[cfg(all(feature = "std", not(loom)))]
+pub(crate) use core::sync::atomic::AtomicU32;
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(all(feature = "std", not(loom)))]
+pub(crate) use std::thread::park_timeout;
+#[cfg(all(feature = "std", not(loom)))]
+pub(crate) use std::{
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    thread_local,
+};
+
+#[cfg(loom)]
+pub(crate) use loom::{
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering},
+        Arc,
+        Condvar,
+        Mutex,
+    },
+    thread,
+    thread_local,
+};
+
+#[cfg(loom)]
+pub(crate) fn park_timeout(_timeout: std::time::Duration) {
+    thread::park();
+}
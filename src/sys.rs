@@ -0,0 +1,218 @@
+use crate::ThreadPriority;
+
+// failures (e.g. missing permissions to raise priority) are ignored, the
+// worker just keeps the default priority
+
+#[cfg(target_os = "linux")]
+pub(crate) fn set_priority(priority: ThreadPriority) {
+    let nice = match priority {
+        ThreadPriority::Background => 19,
+        ThreadPriority::Low => 10,
+        ThreadPriority::Normal => 0,
+        ThreadPriority::High => -5,
+        ThreadPriority::Nice(nice) => nice.clamp(-20, 19) as i32,
+        ThreadPriority::Realtime(priority) => {
+            let param = libc::sched_param {
+                sched_priority: priority.clamp(1, 99) as i32,
+            };
+            if unsafe {
+                libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+            } == 0
+            {
+                return;
+            }
+            // not permitted, the next best thing is `High`
+            -5
+        },
+    };
+    // on linux the nice value is per thread when given a tid
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, nice);
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn gettid() -> usize {
+    unsafe { libc::gettid() as usize }
+}
+
+// the CPUs the calling thread may run on, in ascending order
+#[cfg(target_os = "linux")]
+pub(crate) fn allowed_cpus() -> Option<Vec<usize>> {
+    unsafe {
+        let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return None;
+        }
+        Some(
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+                .collect(),
+        )
+    }
+}
+
+// failures leave the thread unpinned
+#[cfg(target_os = "linux")]
+pub(crate) fn pin_to_cpu(cpu: usize) {
+    unsafe {
+        let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+// Windows numbers CPUs group by group, a group holding up to 64 of them
+#[cfg(windows)]
+pub(crate) fn pin_to_cpu(cpu: usize) {
+    let mut offset = cpu;
+    for (group, size) in processor_groups().into_iter().enumerate() {
+        if offset < size {
+            return set_group_affinity(group as u16, 1 << offset);
+        }
+        offset -= size;
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub(crate) fn pin_to_cpu(_cpu: usize) {}
+
+// Windows keeps every thread in the processor group of its process unless
+// told otherwise, so machines with more than 64 logical processors would
+// only run the workers on one group of them. Worker `index` of `size` goes
+// to the group at its share of all the processors instead.
+#[cfg(windows)]
+pub(crate) fn spread_over_groups(index: usize, size: usize) {
+    let groups = processor_groups();
+    if groups.len() < 2 {
+        return;
+    }
+    let mut position = index * groups.iter().sum::<usize>() / size.max(1);
+    for (group, size) in groups.into_iter().enumerate() {
+        if position < size {
+            let mask = match size {
+                64.. => usize::MAX,
+                size => (1 << size) - 1,
+            };
+            return set_group_affinity(group as u16, mask);
+        }
+        position -= size;
+    }
+}
+
+#[cfg(not(windows))]
+pub(crate) fn spread_over_groups(_index: usize, _size: usize) {}
+
+// the number of active logical processors in each processor group
+#[cfg(windows)]
+pub(crate) fn processor_groups() -> Vec<usize> {
+    use windows_sys::Win32::System::Threading::{
+        GetActiveProcessorCount,
+        GetActiveProcessorGroupCount,
+    };
+
+    unsafe {
+        (0..GetActiveProcessorGroupCount())
+            .map(|group| GetActiveProcessorCount(group) as usize)
+            .collect()
+    }
+}
+
+#[cfg(windows)]
+fn set_group_affinity(group: u16, mask: usize) {
+    use windows_sys::Win32::System::{
+        SystemInformation::GROUP_AFFINITY,
+        Threading::{GetCurrentThread, SetThreadGroupAffinity},
+    };
+
+    let affinity = GROUP_AFFINITY {
+        Mask:     mask,
+        Group:    group,
+        Reserved: [0; 3],
+    };
+    unsafe {
+        SetThreadGroupAffinity(GetCurrentThread(), &affinity, std::ptr::null_mut());
+    }
+}
+
+#[cfg(target_vendor = "apple")]
+pub(crate) fn set_priority(priority: ThreadPriority) {
+    let qos = match priority.coarse() {
+        ThreadPriority::Background => libc::qos_class_t::QOS_CLASS_BACKGROUND,
+        ThreadPriority::Low => libc::qos_class_t::QOS_CLASS_UTILITY,
+        ThreadPriority::Normal => libc::qos_class_t::QOS_CLASS_DEFAULT,
+        ThreadPriority::High | ThreadPriority::Nice(_) => {
+            libc::qos_class_t::QOS_CLASS_USER_INITIATED
+        },
+        ThreadPriority::Realtime(_) => libc::qos_class_t::QOS_CLASS_USER_INTERACTIVE,
+    };
+    unsafe {
+        libc::pthread_set_qos_class_self_np(qos, 0);
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn set_priority(priority: ThreadPriority) {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentThread,
+        SetThreadPriority,
+        THREAD_PRIORITY_ABOVE_NORMAL,
+        THREAD_PRIORITY_BELOW_NORMAL,
+        THREAD_PRIORITY_LOWEST,
+        THREAD_PRIORITY_NORMAL,
+        THREAD_PRIORITY_TIME_CRITICAL,
+    };
+
+    let priority = match priority.coarse() {
+        ThreadPriority::Background => THREAD_PRIORITY_LOWEST,
+        ThreadPriority::Low => THREAD_PRIORITY_BELOW_NORMAL,
+        ThreadPriority::Normal => THREAD_PRIORITY_NORMAL,
+        ThreadPriority::High | ThreadPriority::Nice(_) => THREAD_PRIORITY_ABOVE_NORMAL,
+        ThreadPriority::Realtime(_) => THREAD_PRIORITY_TIME_CRITICAL,
+    };
+    unsafe {
+        SetThreadPriority(GetCurrentThread(), priority);
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_vendor = "apple", windows)))]
+pub(crate) fn set_priority(_priority: ThreadPriority) {}
+
+// blocks while `word` still holds `expected`, returning early on a wake, a
+// timeout or spuriously
+#[cfg(all(target_os = "linux", not(loom)))]
+pub(crate) fn futex_wait(
+    word: &crate::sync::AtomicU32,
+    expected: u32,
+    timeout: Option<std::time::Duration>,
+) {
+    let timeout = timeout.map(|timeout| {
+        libc::timespec {
+            tv_sec:  timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as _,
+        }
+    });
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            expected,
+            timeout
+                .as_ref()
+                .map_or(std::ptr::null(), |timeout| timeout as *const libc::timespec),
+        );
+    }
+}
+
+#[cfg(all(target_os = "linux", not(loom)))]
+pub(crate) fn futex_wake(word: &crate::sync::AtomicU32, count: i32) {
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+            count,
+        );
+    }
+}
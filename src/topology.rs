@@ -0,0 +1,113 @@
+use hwlocality::object::{types::ObjectType, TopologyObject};
+
+/// A hardware thread of the machine, as hwloc reports it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cpu {
+    /// The OS index of the hardware thread, as used for affinity.
+    pub index:   usize,
+    /// The core it belongs to, numbered from 0 in topology order.
+    pub core:    usize,
+    /// The L3 cache slice it belongs to, if the machine has any.
+    pub l3:      Option<usize>,
+    /// The socket it belongs to.
+    pub package: usize,
+}
+
+/// The hardware threads the process may run on, grouped into cores, L3
+/// slices and sockets by hwloc.
+#[derive(Clone, Debug)]
+pub struct Topology {
+    cpus: Vec<Cpu>,
+}
+
+/// Where `Builder::placement` puts the workers: one worker per CPU of the
+/// placement, pinned to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// One worker per L3 cache slice (per socket on machines without L3).
+    PerL3,
+    /// One worker per core, filling socket 0 before moving on to the next.
+    Compact,
+    /// One worker per core, alternating between the sockets.
+    Spread,
+    /// One worker per listed CPU, by OS index.
+    Cpus(Vec<usize>),
+}
+
+impl Topology {
+    pub fn detect() -> Option<Topology> {
+        let topology = hwlocality::Topology::new().ok()?;
+        let group = |pu: &TopologyObject, ty| {
+            pu.first_ancestor_with_type(ty)
+                .map(TopologyObject::logical_index)
+        };
+        // hwloc lists the hardware threads in topology order, socket by
+        // socket and core by core
+        let cpus = topology
+            .objects_with_type(ObjectType::PU)
+            .filter_map(|pu| {
+                Some(Cpu {
+                    index:   pu.os_index()?,
+                    core:    group(pu, ObjectType::Core).unwrap_or(pu.logical_index()),
+                    l3:      group(pu, ObjectType::L3Cache),
+                    package: group(pu, ObjectType::Package).unwrap_or(0),
+                })
+            })
+            .collect::<Vec<_>>();
+        (!cpus.is_empty()).then_some(Topology { cpus })
+    }
+
+    pub fn cpus(&self) -> &[Cpu] {
+        &self.cpus
+    }
+
+    pub fn cores(&self) -> usize {
+        self.first_of(|cpu| cpu.core).len()
+    }
+
+    pub fn l3_groups(&self) -> usize {
+        self.first_of(|cpu| cpu.l3).len()
+    }
+
+    pub fn packages(&self) -> usize {
+        self.first_of(|cpu| cpu.package).len()
+    }
+
+    /// The CPUs (by OS index) the workers of a pool with `placement` are
+    /// pinned to, one per worker.
+    pub fn place(&self, placement: &Placement) -> Vec<usize> {
+        let index = |cpus: Vec<&Cpu>| cpus.into_iter().map(|cpu| cpu.index).collect();
+        match placement {
+            Placement::PerL3 => index(self.first_of(|cpu| (cpu.package, cpu.l3))),
+            Placement::Compact => index(self.first_of(|cpu| cpu.core)),
+            Placement::Spread => {
+                let mut sockets = vec![Vec::new(); self.packages()];
+                let packages = self.first_of(|cpu| cpu.package);
+                for cpu in self.first_of(|cpu| cpu.core) {
+                    let socket = packages.iter().position(|p| p.package == cpu.package);
+                    sockets[socket.unwrap()].push(cpu.index);
+                }
+                let rounds = sockets.iter().map(Vec::len).max().unwrap_or(0);
+                (0..rounds)
+                    .flat_map(|round| sockets.iter().filter_map(move |cores| cores.get(round)))
+                    .copied()
+                    .collect()
+            },
+            Placement::Cpus(cpus) => cpus.clone(),
+        }
+    }
+
+    // the first hardware thread of every distinct `key`, in topology order
+    fn first_of<K: PartialEq>(&self, key: impl Fn(&Cpu) -> K) -> Vec<&Cpu> {
+        let mut seen = Vec::new();
+        let mut firsts = Vec::new();
+        for cpu in &self.cpus {
+            let key = key(cpu);
+            if !seen.contains(&key) {
+                seen.push(key);
+                firsts.push(cpu);
+            }
+        }
+        firsts
+    }
+}
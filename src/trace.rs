@@ -0,0 +1,55 @@
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use crate::{iter::SyncPtr, Pool};
+
+/// A run of indices one worker ran back to back, with when it started and
+/// ended relative to the start of the job. Returned by `Pool::execute_traced`,
+/// e.g. to draw a Gantt chart of the job with a row per worker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub indices: Range<usize>,
+    pub worker:  usize,
+    pub start:   Duration,
+    pub end:     Duration,
+}
+
+impl Pool {
+    // like `execute`, timing every index and returning which worker ran
+    // which indices when, ordered by start; consecutive indices claimed by
+    // the same worker are merged into one span
+    pub fn execute_traced(&self, num: usize, func: impl Fn(usize) + Send + Sync) -> Vec<Span> {
+        let mut records = Vec::<(usize, Duration, Duration)>::with_capacity(num);
+        let ptr = SyncPtr(records.as_mut_ptr());
+        let origin = Instant::now();
+        self.run(num, |worker, i| {
+            let start = origin.elapsed();
+            func(i);
+            // every index writes its own record
+            unsafe { ptr.get().add(i).write((worker, start, origin.elapsed())) };
+        });
+        // a panic in `func` is resumed by `run`, so every index has run
+        unsafe { records.set_len(num) };
+        let mut spans = Vec::<Span>::new();
+        for (i, (worker, start, end)) in records.into_iter().enumerate() {
+            match spans.last_mut() {
+                Some(span) if span.worker == worker => {
+                    span.indices.end = i + 1;
+                    span.end = end;
+                },
+                _ => {
+                    spans.push(Span {
+                        indices: i..i + 1,
+                        worker,
+                        start,
+                        end,
+                    })
+                },
+            }
+        }
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        OnceLock,
+    },
+    thread::{self, Thread},
+};
+
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+use web_sys::{Worker, WorkerOptions, WorkerType};
+
+use crate::ThreadProvider;
+
+thread_local! {
+    static CURRENT: RefCell<Option<WebThread>> = const { RefCell::new(None) };
+}
+
+/// A [`ThreadProvider`] running the threads of a [`RawPool`] on web workers,
+/// for `wasm32-unknown-unknown` built with the `atomics` target feature and
+/// a shared memory.
+///
+/// Every thread is a module worker started from `script`, which gets the
+/// message `[module, memory, ptr]` and has to instantiate the wasm-bindgen
+/// module with that shared memory, then call `ieu_worker_entry(ptr)`:
+///
+/// ```js
+/// import init, { ieu_worker_entry } from "./pkg/app.js";
+///
+/// self.onmessage = async ({ data: [module, memory, ptr] }) => {
+///     await init({ module_or_path: module, memory });
+///     ieu_worker_entry(ptr);
+/// };
+/// ```
+///
+/// Browsers don't let the main thread block, so `RawPool::execute` has to be
+/// called from a web worker itself.
+///
+/// [`RawPool`]: crate::RawPool
+#[derive(Clone, Debug)]
+pub struct WebWorkers {
+    script: String,
+}
+
+/// A thread started by [`WebWorkers`], or any other thread of the module.
+#[derive(Clone, Debug)]
+pub struct WebThread {
+    // set by `unpark` and consumed by `park`, which covers unparks that come
+    // before the worker has started and registered `thread`
+    token:  Arc<AtomicBool>,
+    thread: Arc<OnceLock<Thread>>,
+}
+
+impl WebWorkers {
+    /// Workers started from the module script at `script`.
+    pub fn new(script: impl Into<String>) -> WebWorkers {
+        WebWorkers {
+            script: script.into(),
+        }
+    }
+}
+
+impl WebThread {
+    fn new() -> WebThread {
+        WebThread {
+            token:  Arc::new(AtomicBool::new(false)),
+            thread: Arc::new(OnceLock::new()),
+        }
+    }
+
+    fn current() -> WebThread {
+        CURRENT.with(|current| {
+            current
+                .borrow_mut()
+                .get_or_insert_with(|| {
+                    let this = WebThread::new();
+                    let _ = this.thread.set(thread::current());
+                    this
+                })
+                .clone()
+        })
+    }
+}
+
+impl ThreadProvider for WebWorkers {
+    type Thread = WebThread;
+
+    fn spawn(&self, func: Box<dyn FnOnce() + Send>) -> WebThread {
+        let thread = WebThread::new();
+        let handle = thread.clone();
+        // the worker's handle is known before it runs, so unparks that come
+        // before its first park aren't lost
+        let start = Box::new(Box::new(move || {
+            let _ = handle.thread.set(thread::current());
+            CURRENT.with(|current| *current.borrow_mut() = Some(handle));
+            func();
+        }) as Box<dyn FnOnce() + Send>);
+        let options = WorkerOptions::new();
+        options.set_type(WorkerType::Module);
+        let worker = Worker::new_with_options(&self.script, &options)
+            .unwrap_or_else(|err| panic!("failed to start a web worker: {err:?}"));
+        let ptr = Box::into_raw(start) as u32;
+        let message = js_sys::Array::of3(
+            &wasm_bindgen::module(),
+            &wasm_bindgen::memory(),
+            &JsValue::from(ptr),
+        );
+        if let Err(err) = worker.post_message(&message) {
+            drop(unsafe { Box::from_raw(ptr as *mut Box<dyn FnOnce() + Send>) });
+            panic!("failed to start a web worker: {err:?}");
+        }
+        thread
+    }
+
+    fn current(&self) -> WebThread {
+        WebThread::current()
+    }
+
+    // std parks on `memory.atomic.wait32` with the `atomics` target feature
+    fn park(&self) {
+        if !WebThread::current().token.swap(false, Ordering::SeqCst) {
+            thread::park();
+        }
+    }
+
+    fn unpark(&self, thread: &WebThread) {
+        thread.token.store(true, Ordering::SeqCst);
+        if let Some(thread) = thread.thread.get() {
+            thread.unpark();
+        }
+    }
+}
+
+// called by the worker script with the pointer `WebWorkers::spawn` posted
+#[wasm_bindgen]
+pub fn ieu_worker_entry(ptr: u32) {
+    let func = unsafe { Box::from_raw(ptr as *mut Box<dyn FnOnce() + Send>) };
+    func();
+}